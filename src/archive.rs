@@ -0,0 +1,160 @@
+use crate::YouDlError;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Keeps track of the downloaded videos, one "youtube <video_id> <itag>" line each
+// (the first two columns match the archive format of youtube-dl).
+// The file is locked while being accessed, so that concurrent you-dl processes can share it.
+#[derive(Debug, Clone)]
+pub struct DownloadArchive {
+    path: PathBuf,
+    // the videos being downloaded by this process, which aren't in the file yet
+    claimed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl DownloadArchive {
+    pub fn new(path: &Path) -> Self {
+        DownloadArchive {
+            path: path.to_owned(),
+            claimed: Arc::default(),
+        }
+    }
+
+    pub async fn contains(&self, video_id: &str) -> Result<bool, YouDlError> {
+        let path = self.path.clone();
+        let video_id = video_id.to_owned();
+        smol::unblock(move || {
+            let mut file = open(&path)?;
+            file.lock_shared()?;
+            Ok(contains(&read(&mut file)?, &video_id))
+        })
+        .await
+        .map_err(|e: std::io::Error| YouDlError::Application(e.to_string()))
+    }
+
+    // Appends the video, unless another process recorded it in the meantime.
+    pub async fn record(&self, video_id: &str, itag: &str) -> Result<(), YouDlError> {
+        let path = self.path.clone();
+        let line = format!("youtube {} {}\n", video_id, itag);
+        let video_id = video_id.to_owned();
+        smol::unblock(move || {
+            let mut file = open(&path)?;
+            file.lock()?;
+            if !contains(&read(&mut file)?, &video_id) {
+                file.write_all(line.as_bytes())?;
+                file.flush()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e: std::io::Error| YouDlError::Application(e.to_string()))
+    }
+
+    // Reserves a video for a single download at a time, until the claim is recorded or dropped,
+    // so that the same video queued twice is downloaded once. None if it's archived or claimed already.
+    pub async fn claim(&self, video_id: &str) -> Result<Option<ArchiveClaim>, YouDlError> {
+        if !self.claimed.lock().unwrap().insert(video_id.to_owned()) {
+            return Ok(None);
+        }
+        // claimed before checking the file: a claim is only released once its video is recorded
+        let claim = ArchiveClaim {
+            archive: self.clone(),
+            video_id: video_id.to_owned(),
+        };
+        if self.contains(video_id).await? {
+            Ok(None)
+        } else {
+            Ok(Some(claim))
+        }
+    }
+}
+
+pub struct ArchiveClaim {
+    archive: DownloadArchive,
+    video_id: String,
+}
+
+impl ArchiveClaim {
+    pub async fn record(self, itag: &str) -> Result<(), YouDlError> {
+        self.archive.record(&self.video_id, itag).await
+    }
+}
+
+impl Drop for ArchiveClaim {
+    fn drop(&mut self) {
+        self.archive.claimed.lock().unwrap().remove(&self.video_id);
+    }
+}
+
+// the lock is released as soon as the returned file is dropped
+fn open(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+}
+
+fn read(file: &mut File) -> std::io::Result<String> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn contains(contents: &str, video_id: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(video_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    #[test]
+    fn records_each_video_once() {
+        smol::block_on(async {
+            let dir = TempDir::new("archive");
+            let path = dir.join("archive.txt");
+            let archive = DownloadArchive::new(&path);
+            assert!(!archive.contains("dQw4w9WgXcQ").await.unwrap());
+
+            archive.record("dQw4w9WgXcQ", "22").await.unwrap();
+            archive.record("dQw4w9WgXcQ", "18").await.unwrap();
+            archive.record("MAlSjtxy5ak", "137+140").await.unwrap();
+            assert!(archive.contains("dQw4w9WgXcQ").await.unwrap());
+            assert!(archive.contains("MAlSjtxy5ak").await.unwrap());
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                "youtube dQw4w9WgXcQ 22\nyoutube MAlSjtxy5ak 137+140\n"
+            );
+        })
+    }
+
+    #[test]
+    fn claims_each_video_for_one_download_at_a_time() {
+        smol::block_on(async {
+            let dir = TempDir::new("archive-claim");
+            let archive = DownloadArchive::new(&dir.join("archive.txt"));
+
+            let claim = archive.claim("dQw4w9WgXcQ").await.unwrap().unwrap();
+            assert!(archive.claim("dQw4w9WgXcQ").await.unwrap().is_none());
+            assert!(archive
+                .clone()
+                .claim("MAlSjtxy5ak")
+                .await
+                .unwrap()
+                .is_some());
+
+            // a failed download gives the video back
+            drop(claim);
+            let claim = archive.claim("dQw4w9WgXcQ").await.unwrap().unwrap();
+            claim.record("22").await.unwrap();
+            assert!(archive.claim("dQw4w9WgXcQ").await.unwrap().is_none());
+        })
+    }
+}
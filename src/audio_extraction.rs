@@ -0,0 +1,137 @@
+use crate::YouDlError;
+use std::fmt;
+use std::str::FromStr;
+
+// `--extract-audio`: the best audio stream is downloaded on its own, then converted by ffmpeg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AudioExtraction {
+    // `None` keeps the downloaded codec, only changing the container
+    pub format: Option<AudioFormat>,
+    pub quality: AudioQuality,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    M4a,
+    Opus,
+    Flac,
+}
+
+impl AudioFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Flac => "flac",
+        }
+    }
+}
+
+impl FromStr for AudioFormat {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mp3" => Ok(AudioFormat::Mp3),
+            "m4a" => Ok(AudioFormat::M4a),
+            "opus" => Ok(AudioFormat::Opus),
+            "flac" => Ok(AudioFormat::Flac),
+            _ => Err(YouDlError::User(format!(
+                "invalid audio format \"{}\", expected mp3, m4a, opus or flac",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file_extension())
+    }
+}
+
+// Same values as youtube-dl's `--audio-quality`: a VBR level from 0 (best) to 9 (worst), or a bitrate like "128K".
+// Only used when the audio gets re-encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioQuality {
+    Vbr(u8),
+    // kbit/s
+    Bitrate(u32),
+}
+
+impl Default for AudioQuality {
+    fn default() -> Self {
+        AudioQuality::Vbr(5)
+    }
+}
+
+impl AudioQuality {
+    // For encoders without VBR levels: roughly the average bitrates of LAME's presets V0 to V9.
+    pub fn bitrate(&self) -> u32 {
+        const VBR_BITRATES: [u32; 10] = [245, 225, 190, 175, 165, 130, 115, 100, 85, 65];
+        match *self {
+            AudioQuality::Vbr(level) => VBR_BITRATES[level.min(9) as usize],
+            AudioQuality::Bitrate(bitrate) => bitrate,
+        }
+    }
+}
+
+impl FromStr for AudioQuality {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            YouDlError::User(format!(
+                "invalid audio quality \"{}\", expected 0 (best) to 9 (worst) or a bitrate like 128K",
+                s
+            ))
+        };
+        let s = s.trim();
+        match s.strip_suffix(|c| c == 'k' || c == 'K') {
+            Some(bitrate) => bitrate
+                .parse()
+                .ok()
+                .filter(|&bitrate| bitrate > 0)
+                .map(AudioQuality::Bitrate)
+                .ok_or_else(invalid),
+            None => s
+                .parse()
+                .ok()
+                .filter(|&level| level <= 9)
+                .map(AudioQuality::Vbr)
+                .ok_or_else(invalid),
+        }
+    }
+}
+
+impl fmt::Display for AudioQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioQuality::Vbr(level) => write!(f, "{}", level),
+            AudioQuality::Bitrate(bitrate) => write!(f, "{}K", bitrate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_audio_quality() {
+        assert_eq!("0".parse::<AudioQuality>().unwrap(), AudioQuality::Vbr(0));
+        assert_eq!(
+            "128K".parse::<AudioQuality>().unwrap(),
+            AudioQuality::Bitrate(128)
+        );
+        assert_eq!(
+            "96k".parse::<AudioQuality>().unwrap(),
+            AudioQuality::Bitrate(96)
+        );
+        assert!("10".parse::<AudioQuality>().is_err());
+        assert!("0K".parse::<AudioQuality>().is_err());
+        assert_eq!(AudioQuality::Vbr(5).bitrate(), 130);
+    }
+}
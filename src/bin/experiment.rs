@@ -1,7 +1,5 @@
 use qstring::QString;
-use reqwest;
 use serde::Deserialize;
-use serde_json;
 use std::error::Error;
 use std::{fs, io, process};
 
@@ -12,6 +10,7 @@ use std::{fs, io, process};
 // https://opensource.com/article/17/6/ffmpeg-convert-media-file-formats
 // find out what's the best audio to download for the selected video, and make sure Quicktime can always play it
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct Format {
     itag: i32,
@@ -28,6 +27,7 @@ struct StreamingData {
     formats: Vec<Format>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct VideoDetails {
     #[serde(rename(deserialize = "videoId"))]
@@ -62,7 +62,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // COMMAND: ffmpeg -i <video> -i <audio> -c:v copy -c:a copy <output>
     let output_title = format!("{}.mp4", title);
     process::Command::new("ffmpeg")
-        .args(&[
+        .args([
             "-i",
             &video_title,
             "-i",
@@ -0,0 +1,176 @@
+use super::ConfigError;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Text(String),
+}
+
+// The options of a config file, by their long name, e.g. `output-dir = "~/Videos"`.
+// Only the part of TOML needed to mirror the command line is understood: comments and `key = value` lines,
+// whose value is a string, an integer, a boolean or an array of strings (joined with commas, e.g. for `sub-langs`).
+pub fn parse(contents: &str, path: &Path) -> Result<BTreeMap<String, Value>, ConfigError> {
+    let mut values = BTreeMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let invalid = |message: &str| {
+            ConfigError(format!(
+                "{} (line {}): {}",
+                path.display(),
+                index + 1,
+                message
+            ))
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(invalid(
+                "tables are not supported, options go at the top level",
+            ));
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected `key = value`"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(invalid(&format!("invalid key \"{}\"", key)));
+        }
+        let (value, rest) = parse_value(value.trim()).map_err(|message| invalid(&message))?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(invalid(&format!("unexpected \"{}\" after the value", rest)));
+        }
+        if values.insert(key.to_owned(), value).is_some() {
+            return Err(invalid(&format!("\"{}\" is set twice", key)));
+        }
+    }
+    Ok(values)
+}
+
+// Returns the value at the start of `input`, and what follows it.
+fn parse_value(input: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = input.strip_prefix('[') {
+        let mut items = Vec::new();
+        let mut rest = rest.trim_start();
+        while !rest.starts_with(']') {
+            let (item, after_item) = parse_string(rest)?;
+            items.push(item);
+            rest = after_item.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after_comma) => rest = after_comma.trim_start(),
+                None if rest.starts_with(']') => {}
+                None => return Err("arrays only hold strings, on a single line".to_owned()),
+            }
+        }
+        return Ok((Value::Text(items.join(",")), &rest[1..]));
+    }
+    if input.starts_with('"') || input.starts_with('\'') {
+        return parse_string(input).map(|(text, rest)| (Value::Text(text), rest));
+    }
+
+    let end = input
+        .find(|c: char| c.is_whitespace() || c == '#')
+        .unwrap_or(input.len());
+    let (word, rest) = input.split_at(end);
+    match word {
+        "true" => Ok((Value::Bool(true), rest)),
+        "false" => Ok((Value::Bool(false), rest)),
+        _ => {
+            let digits = word.replace('_', "");
+            digits
+                .parse::<i64>()
+                .map(|number| (Value::Text(number.to_string()), rest))
+                .map_err(|_| format!("invalid value \"{}\"", word))
+        }
+    }
+}
+
+fn parse_string(input: &str) -> Result<(String, &str), String> {
+    let unterminated = || "unterminated string".to_owned();
+    // literal strings have no escapes
+    if let Some(rest) = input.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or_else(unterminated)?;
+        return Ok((rest[..end].to_owned(), &rest[end + 1..]));
+    }
+    let rest = input
+        .strip_prefix('"')
+        .ok_or_else(|| "expected a string".to_owned())?;
+
+    let mut text = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((text, &rest[index + 1..])),
+            '\\' => match chars.next().map(|(_, escaped)| escaped) {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(escaped) => return Err(format!("unsupported escape \"\\{}\"", escaped)),
+                None => return Err(unterminated()),
+            },
+            c => text.push(c),
+        }
+    }
+    Err(unterminated())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_options_mirroring_the_command_line() {
+        let contents = r#"
+# downloads for the team
+output-dir = "~/Videos"   # shared folder
+wrapper = true
+segments = 4
+sub-langs = ["en", 'de']
+exec = "echo \"{}\" \\ done"
+"#;
+        let values = parse(contents, Path::new("config.toml")).unwrap();
+
+        let text = |value: &str| Value::Text(value.to_owned());
+        assert_eq!(
+            values.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("exec".to_owned(), text("echo \"{}\" \\ done")),
+                ("output-dir".to_owned(), text("~/Videos")),
+                ("segments".to_owned(), text("4")),
+                ("sub-langs".to_owned(), text("en,de")),
+                ("wrapper".to_owned(), Value::Bool(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn points_at_the_invalid_line() {
+        let error = |contents: &str| parse(contents, Path::new("config.toml")).unwrap_err().0;
+
+        assert_eq!(
+            error("segments = 4\nformat = best"),
+            "config.toml (line 2): invalid value \"best\""
+        );
+        assert_eq!(
+            error("[download]"),
+            "config.toml (line 1): tables are not supported, options go at the top level"
+        );
+        assert_eq!(
+            error("format = \"best"),
+            "config.toml (line 1): unterminated string"
+        );
+        assert_eq!(
+            error("wrapper = true\nwrapper = false"),
+            "config.toml (line 2): \"wrapper\" is set twice"
+        );
+    }
+}
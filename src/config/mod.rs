@@ -1,18 +1,50 @@
 use config_error::ConfigError;
 use raw_cli_args::parse as raw_parse;
 use smol::{fs, process};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use you_dl::{
+    wrapper, AudioExtraction, AudioFormat, AudioQuality, DownloadArchive, DownloadSettings,
+    FilenamePolicy, FilenameSanitizer, FormatSelector, Innertube, OutputTemplate, PlayerClient,
+    PlaylistItems, PlaylistOptions, PostProcessing, RateLimiter, RateSchedule, RemuxFormat,
+    SubtitleFormat, SubtitleOptions,
+};
 
 mod config_error;
+mod config_file;
 mod raw_cli_args;
+mod sources;
 
 #[derive(Debug)]
 pub struct Config {
     pub video_urls: Vec<String>,
+    pub innertube: Innertube,
     pub output_dir: String,
+    pub output_template: OutputTemplate,
+    pub format: Option<FormatSelector>,
+    pub post_processing: PostProcessing,
+    pub download_settings: DownloadSettings,
+    pub concurrent_downloads: usize,
+    pub playlist_options: PlaylistOptions,
+    pub download_archive: Option<DownloadArchive>,
+    pub dump_json: bool,
+    pub list_subs: bool,
     pub use_wrapper: bool,
 }
 
+impl Config {
+    // the same settings as the `Downloader`, for youtube-dl
+    pub fn wrapper_options(&self) -> wrapper::Options<'_> {
+        wrapper::Options {
+            output_dir: &self.output_dir,
+            output_template: &self.output_template,
+            format: self.format.as_ref(),
+            post_processing: &self.post_processing,
+            settings: &self.download_settings,
+            archive: self.download_archive.as_ref(),
+        }
+    }
+}
+
 pub async fn parse() -> Result<Config, ConfigError> {
     let raw_cli_args = raw_parse()?;
 
@@ -22,7 +54,7 @@ pub async fn parse() -> Result<Config, ConfigError> {
 
     let output_dir_path = Path::new(&raw_cli_args.output_dir);
     if !output_dir_path.is_dir() {
-        you_dl::info!("creating directory \"{}\"...", output_dir_path.display());
+        info!("creating directory \"{}\"...", output_dir_path.display());
         fs::create_dir_all(output_dir_path).await.unwrap();
     }
 
@@ -31,6 +63,198 @@ pub async fn parse() -> Result<Config, ConfigError> {
         return Err(ConfigError(err_message));
     };
 
+    let format = raw_cli_args
+        .format
+        .map(|selector| selector.parse::<FormatSelector>())
+        .transpose()
+        .map_err(|err| ConfigError(err.to_string()))?;
+
+    // whatever source they come from
+    if !raw_cli_args.extract_audio
+        && (raw_cli_args.audio_format.is_some() || raw_cli_args.audio_quality.is_some())
+    {
+        return Err(ConfigError(
+            "--audio-format and --audio-quality require --extract-audio".to_owned(),
+        ));
+    }
+    let audio_extraction = if raw_cli_args.extract_audio {
+        Some(AudioExtraction {
+            // "best" keeps the downloaded codec
+            format: raw_cli_args
+                .audio_format
+                .filter(|format| format != "best")
+                .map(|format| format.parse::<AudioFormat>())
+                .transpose()
+                .map_err(|err| ConfigError(err.to_string()))?,
+            quality: raw_cli_args
+                .audio_quality
+                .map(|quality| quality.parse::<AudioQuality>())
+                .transpose()
+                .map_err(|err| ConfigError(err.to_string()))?
+                .unwrap_or_default(),
+        })
+    } else {
+        None
+    };
+
+    // embedding needs the subtitles too, which are then removed unless written
+    let write_subs = raw_cli_args.write_subs || raw_cli_args.write_auto_subs;
+    let subtitles = if write_subs || raw_cli_args.embed_subs {
+        let languages = match raw_cli_args.sub_langs.trim() {
+            "all" => None,
+            languages => Some(
+                languages
+                    .split(',')
+                    .map(|language| language.trim().to_owned())
+                    .filter(|language| !language.is_empty())
+                    .collect(),
+            ),
+        };
+        Some(SubtitleOptions {
+            languages,
+            auto_generated: raw_cli_args.write_auto_subs,
+            format: raw_cli_args
+                .sub_format
+                .parse::<SubtitleFormat>()
+                .map_err(|err| ConfigError(err.to_string()))?,
+            write: write_subs,
+            embed: raw_cli_args.embed_subs,
+        })
+    } else {
+        None
+    };
+
+    let output_template = raw_cli_args
+        .output_template
+        .map(|template| template.parse::<OutputTemplate>())
+        .transpose()
+        .map_err(|err| ConfigError(err.to_string()))?
+        .unwrap_or_default();
+    let filename_sanitizer = FilenameSanitizer {
+        policy: raw_cli_args
+            .filename_policy
+            .map(|policy| policy.parse::<FilenamePolicy>())
+            .transpose()
+            .map_err(|err| ConfigError(err.to_string()))?
+            .unwrap_or_default(),
+        // leaves room for the longest extension and the suffixes
+        max_bytes: raw_cli_args
+            .max_filename_bytes
+            .parse()
+            .ok()
+            .filter(|&max_bytes| max_bytes >= 16 + FilenameSanitizer::SUFFIX_BYTES)
+            .ok_or(ConfigError(format!(
+                "invalid maximum filename length \"{}\"",
+                raw_cli_args.max_filename_bytes
+            )))?,
+    };
+    // youtube-dl has no such option
+    if raw_cli_args.use_wrapper
+        && filename_sanitizer.max_bytes != FilenameSanitizer::default().max_bytes
+    {
+        return Err(ConfigError(
+            "--max-filename-bytes is not supported with --wrapper".to_owned(),
+        ));
+    }
+    let output_template = output_template.with_sanitizer(filename_sanitizer);
+
+    let concurrent_downloads = raw_cli_args
+        .concurrent_downloads
+        .parse()
+        .ok()
+        .filter(|&concurrent_downloads| concurrent_downloads > 0)
+        .ok_or(ConfigError(format!(
+            "invalid number of concurrent downloads \"{}\"",
+            raw_cli_args.concurrent_downloads
+        )))?;
+
+    let rate_schedule = raw_cli_args
+        .limit_rate_schedule
+        .map(|schedule| schedule.parse::<RateSchedule>())
+        .transpose()
+        .map_err(|err| ConfigError(err.to_string()))?;
+    let rate_limiter = match (raw_cli_args.limit_rate, rate_schedule) {
+        (Some(rate), schedule) => {
+            let rate_limiter = RateLimiter::new(
+                you_dl::parse_file_size(&rate)
+                    .filter(|&rate| rate > 0)
+                    .ok_or(ConfigError(format!("invalid rate \"{}\"", rate)))?,
+            );
+            let rate_limiter = match schedule {
+                Some(schedule) => rate_limiter.with_schedule(schedule),
+                None => rate_limiter,
+            };
+            // youtube-dl processes can only be limited one by one
+            Some(if raw_cli_args.use_wrapper {
+                rate_limiter.split_between(concurrent_downloads)
+            } else {
+                rate_limiter
+            })
+        }
+        (None, Some(_)) => {
+            return Err(ConfigError(
+                "--limit-rate-schedule needs a --limit-rate".to_owned(),
+            ))
+        }
+        (None, None) => None,
+    };
+
+    let download_settings = DownloadSettings {
+        segments: raw_cli_args
+            .segments
+            .parse()
+            .ok()
+            .filter(|&segments| segments > 0)
+            .ok_or(ConfigError(format!(
+                "invalid number of segments \"{}\"",
+                raw_cli_args.segments
+            )))?,
+        chunk_size: you_dl::parse_file_size(&raw_cli_args.chunk_size)
+            .filter(|&chunk_size| chunk_size > 0)
+            .ok_or(ConfigError(format!(
+                "invalid chunk size \"{}\"",
+                raw_cli_args.chunk_size
+            )))?,
+        retries: raw_cli_args
+            .retries
+            .parse()
+            .ok()
+            .ok_or(ConfigError(format!(
+                "invalid number of retries \"{}\"",
+                raw_cli_args.retries
+            )))?,
+        fragment_retries: raw_cli_args
+            .fragment_retries
+            .parse()
+            .ok()
+            .ok_or(ConfigError(format!(
+                "invalid number of fragment retries \"{}\"",
+                raw_cli_args.fragment_retries
+            )))?,
+        rate_limiter,
+    };
+
+    let innertube = match raw_cli_args.player_clients {
+        Some(clients) => Innertube::new(
+            clients
+                .split(',')
+                .map(|client| client.parse::<PlayerClient>())
+                .collect::<Result<_, _>>()
+                .map_err(|err| ConfigError(err.to_string()))?,
+        ),
+        None => Innertube::default(),
+    };
+
+    let playlist_options = PlaylistOptions {
+        items: raw_cli_args
+            .playlist_items
+            .map(|items| items.parse::<PlaylistItems>())
+            .transpose()
+            .map_err(|err| ConfigError(err.to_string()))?,
+        reverse: raw_cli_args.playlist_reverse,
+        no_playlist: raw_cli_args.no_playlist,
+    };
+
     let mut video_urls = Vec::new();
     if let Some(mut urls) = raw_cli_args.urls {
         video_urls.append(&mut urls);
@@ -42,14 +266,38 @@ pub async fn parse() -> Result<Config, ConfigError> {
 
     Ok(Config {
         video_urls,
+        innertube,
         output_dir: raw_cli_args.output_dir,
+        output_template,
+        format,
+        post_processing: PostProcessing {
+            remux: raw_cli_args
+                .remux_video
+                .map(|format| format.parse::<RemuxFormat>())
+                .transpose()
+                .map_err(|err| ConfigError(err.to_string()))?,
+            audio_extraction,
+            embed_metadata: raw_cli_args.embed_metadata,
+            embed_thumbnail: raw_cli_args.embed_thumbnail,
+            subtitles,
+            temp_dir: raw_cli_args.temp_dir.map(PathBuf::from),
+            exec: raw_cli_args.exec,
+        },
+        download_settings,
+        concurrent_downloads,
+        playlist_options,
+        download_archive: raw_cli_args
+            .download_archive
+            .map(|path| DownloadArchive::new(Path::new(&path))),
+        dump_json: raw_cli_args.dump_json,
+        list_subs: raw_cli_args.list_subs,
         use_wrapper: raw_cli_args.use_wrapper,
     })
 }
 
 async fn is_youtube_dl_available() -> bool {
     process::Command::new("youtube-dl")
-        .args(&["-h"])
+        .args(["-h"])
         .output()
         .await
         .map_or_else(
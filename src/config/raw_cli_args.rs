@@ -1,19 +1,85 @@
+use super::config_file::Value;
+use super::sources::{self, ResolvedOption, Source, Sources};
 use super::ConfigError;
-use clap::{crate_version, App, Arg};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches};
 
 #[derive(Debug)]
 pub struct RawCliArgs {
     pub help_message: String,
+    pub audio_format: Option<String>,
+    pub audio_quality: Option<String>,
+    pub chunk_size: String,
+    pub concurrent_downloads: String,
+    pub download_archive: Option<String>,
+    pub dump_json: bool,
+    pub embed_metadata: bool,
+    pub embed_subs: bool,
+    pub embed_thumbnail: bool,
+    pub exec: Option<String>,
+    pub extract_audio: bool,
+    pub filename_policy: Option<String>,
+    pub fragment_retries: String,
     pub from_file_path: Option<String>,
+    pub format: Option<String>,
+    pub limit_rate: Option<String>,
+    pub limit_rate_schedule: Option<String>,
+    pub list_subs: bool,
+    pub max_filename_bytes: String,
+    pub no_playlist: bool,
     pub output_dir: String,
+    pub output_template: Option<String>,
+    pub player_clients: Option<String>,
+    pub playlist_items: Option<String>,
+    pub playlist_reverse: bool,
+    pub remux_video: Option<String>,
+    pub retries: String,
+    pub segments: String,
+    pub sub_format: String,
+    pub sub_langs: String,
+    pub temp_dir: Option<String>,
     pub urls: Option<Vec<String>>,
     pub use_wrapper: bool,
+    pub write_auto_subs: bool,
+    pub write_subs: bool,
 }
 
-const FROM_FILE_PATH_ARG: &str = "from-file-path";
+const AUDIO_FORMAT_ARG: &str = "audio-format";
+const AUDIO_QUALITY_ARG: &str = "audio-quality";
+const CHUNK_SIZE_ARG: &str = "chunk-size";
+const CONCURRENT_DOWNLOADS_ARG: &str = "concurrent-downloads";
+const CONFIG_ARG: &str = "config";
+const DOWNLOAD_ARCHIVE_ARG: &str = "download-archive";
+const DUMP_JSON_ARG: &str = "dump-json";
+const EMBED_METADATA_ARG: &str = "embed-metadata";
+const EMBED_SUBS_ARG: &str = "embed-subs";
+const EMBED_THUMBNAIL_ARG: &str = "embed-thumbnail";
+const EXEC_ARG: &str = "exec";
+const EXTRACT_AUDIO_ARG: &str = "extract-audio";
+const FILENAME_POLICY_ARG: &str = "filename-policy";
+const FORMAT_ARG: &str = "format";
+const FRAGMENT_RETRIES_ARG: &str = "fragment-retries";
+const FROM_FILE_PATH_ARG: &str = "from-file";
+const IGNORE_CONFIG_ARG: &str = "ignore-config";
+const LIMIT_RATE_ARG: &str = "limit-rate";
+const LIMIT_RATE_SCHEDULE_ARG: &str = "limit-rate-schedule";
+const LIST_SUBS_ARG: &str = "list-subs";
+const MAX_FILENAME_BYTES_ARG: &str = "max-filename-bytes";
+const NO_PLAYLIST_ARG: &str = "no-playlist";
 const OUTPUT_DIR_ARG: &str = "output-dir";
+const OUTPUT_TEMPLATE_ARG: &str = "output";
+const PLAYER_CLIENT_ARG: &str = "player-client";
+const PLAYLIST_ITEMS_ARG: &str = "playlist-items";
+const PLAYLIST_REVERSE_ARG: &str = "playlist-reverse";
+const REMUX_VIDEO_ARG: &str = "remux-video";
+const RETRIES_ARG: &str = "retries";
+const SEGMENTS_ARG: &str = "segments";
+const SUB_FORMAT_ARG: &str = "sub-format";
+const SUB_LANGS_ARG: &str = "sub-langs";
+const TEMP_DIR_ARG: &str = "temp-dir";
 const URL_ARG: &str = "url";
 const USE_WRAPPER_ARG: &str = "wrapper";
+const WRITE_AUTO_SUBS_ARG: &str = "write-auto-subs";
+const WRITE_SUBS_ARG: &str = "write-subs";
 
 pub fn parse() -> Result<RawCliArgs, ConfigError> {
     let mut app = App::new("you-dl")
@@ -45,6 +111,25 @@ For more info, check \"github.com/ytdl-org/youtube-dl\".",
                 .about("Read the URLs from a text file (lines starting with `#` and `//` are ignored)")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new(FORMAT_ARG)
+                .long("format")
+                .value_name("SELECTOR")
+                .long_about(
+                    "\
+Choose the file format without prompting, e.g. \"best\", \"worst\", \"itag=22\" or \"height<=720,ext=mp4\".
+Alternatives separated by \"/\" are tried from left to right: \"height<=720,ext=mp4/best\".
+Without a selector, the format is asked interactively (or \"best\" is chosen when stdin is not a terminal).",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(DOWNLOAD_ARCHIVE_ARG)
+                .long("download-archive")
+                .value_name("PATH")
+                .about("Skip the videos listed in this file, and record every new download in it")
+                .takes_value(true),
+        )
         .arg(
             Arg::new(OUTPUT_DIR_ARG)
                 .default_value(".")
@@ -53,12 +138,306 @@ For more info, check \"github.com/ytdl-org/youtube-dl\".",
                 .value_name("PATH")
                 .about("Change output directory")
                 .takes_value(true),
+        )
+        .arg(
+            Arg::new(OUTPUT_TEMPLATE_ARG)
+                .long("output")
+                .value_name("TEMPLATE")
+                .long_about(
+                    "\
+Name the downloaded files, relative to the output directory (default: \"{title}.{ext}\").
+Available fields: {title}, {id}, {itag}, {height}, {ext}, {uploader} and {upload_date} (YYYYMMDD).
+\"/\" creates subdirectories, e.g. \"{uploader}/{upload_date} {title}.{ext}\".",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(PLAYLIST_ITEMS_ARG)
+                .long("playlist-items")
+                .value_name("ITEMS")
+                .about("Download only these playlist items, e.g. \"1-5,8,10-\"")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(PLAYLIST_REVERSE_ARG)
+                .long("playlist-reverse")
+                .about("Download playlist items in reverse order")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new(NO_PLAYLIST_ARG)
+                .long("no-playlist")
+                .about("Download only the video if the URL refers to both a video and a playlist")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new(DUMP_JSON_ARG)
+                .short('j')
+                .long("dump-json")
+                .about("Print the metadata and formats of each video as a line of JSON, without downloading"),
+        )
+        .arg(
+            Arg::new(EXTRACT_AUDIO_ARG)
+                .short('x')
+                .long("extract-audio")
+                .about("Download only the audio, converted with ffmpeg"),
+        )
+        .arg(
+            Arg::new(AUDIO_FORMAT_ARG)
+                .long("audio-format")
+                .value_name("FORMAT")
+                .possible_values(&["best", "mp3", "m4a", "opus", "flac"])
+                .about("Convert the extracted audio to FORMAT (default: \"best\", keeping the downloaded codec)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(AUDIO_QUALITY_ARG)
+                .long("audio-quality")
+                .value_name("QUALITY")
+                .about("When converting, a VBR level from 0 (best) to 9 (worst) or a bitrate like 128K (default: 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(EMBED_METADATA_ARG)
+                .long("embed-metadata")
+                .about("Write the title, uploader, upload date, description and URL into the file"),
+        )
+        .arg(
+            Arg::new(EMBED_THUMBNAIL_ARG)
+                .long("embed-thumbnail")
+                .about("Embed the thumbnail as cover art (mp4, m4a, mp3, flac and mkv files)"),
+        )
+        .arg(
+            Arg::new(REMUX_VIDEO_ARG)
+                .long("remux-video")
+                .value_name("FORMAT")
+                .possible_values(&["mp4", "mkv", "webm", "mov"])
+                .about("Copy the downloaded streams into another container, without re-encoding")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(LIST_SUBS_ARG)
+                .long("list-subs")
+                .about("List the subtitles and auto-generated captions of each video, without downloading"),
+        )
+        .arg(
+            Arg::new(WRITE_SUBS_ARG)
+                .long("write-subs")
+                .about("Write the subtitles next to the downloaded file, e.g. \"<name>.en.srt\""),
+        )
+        .arg(
+            Arg::new(WRITE_AUTO_SUBS_ARG)
+                .long("write-auto-subs")
+                .about("Use auto-generated captions for the languages without subtitles"),
+        )
+        .arg(
+            Arg::new(SUB_LANGS_ARG)
+                .default_value("en")
+                .long("sub-langs")
+                .value_name("LANGS")
+                .about("Languages of the subtitles, e.g. \"en,de\", or \"all\"")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(SUB_FORMAT_ARG)
+                .default_value("srt")
+                .long("sub-format")
+                .value_name("FORMAT")
+                .possible_values(&["srt", "vtt"])
+                .about("Convert the subtitles to SubRip (srt) or WebVTT (vtt)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(EMBED_SUBS_ARG)
+                .long("embed-subs")
+                .about("Embed the subtitles into the file (mp4, mkv and webm files)"),
+        )
+        .arg(
+            Arg::new(TEMP_DIR_ARG)
+                .long("temp-dir")
+                .value_name("PATH")
+                .about("Download and process the files in this directory, then move them to the output directory")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(EXEC_ARG)
+                .long("exec")
+                .value_name("CMD")
+                .about("Run a shell command on each downloaded file, whose path replaces \"{}\" or is appended")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(FILENAME_POLICY_ARG)
+                .long("filename-policy")
+                .value_name("POLICY")
+                .possible_values(&["posix", "windows", "ascii"])
+                .about("Characters allowed in file names (default: \"windows\" on Windows, \"posix\" elsewhere)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(MAX_FILENAME_BYTES_ARG)
+                .default_value("255")
+                .long("max-filename-bytes")
+                .value_name("BYTES")
+                .about("Truncate longer file and directory names, keeping their extension")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(SEGMENTS_ARG)
+                .default_value("1")
+                .short('N')
+                .long("segments")
+                .value_name("COUNT")
+                .about("Download each stream over COUNT concurrent connections")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(CHUNK_SIZE_ARG)
+                .default_value("10M")
+                .long("chunk-size")
+                .value_name("SIZE")
+                .about("Size of the ranges requested by each connection, e.g. 512K or 10M")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(LIMIT_RATE_ARG)
+                .short('r')
+                .long("limit-rate")
+                .value_name("RATE")
+                .about("Maximum download rate in bytes per second, shared by all the downloads, e.g. 500K or 2M")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(LIMIT_RATE_SCHEDULE_ARG)
+                .long("limit-rate-schedule")
+                .value_name("TIMES")
+                .about("Only limit the rate during these times of the day, e.g. 08:00-19:00 (full speed the rest of the time), in local time (UTC on Windows)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(RETRIES_ARG)
+                .default_value("10")
+                .short('R')
+                .long("retries")
+                .value_name("COUNT")
+                .about("Retry requests failing with timeouts, dropped connections, 5xx or 429 errors, waiting longer each time")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(FRAGMENT_RETRIES_ARG)
+                .default_value("10")
+                .long("fragment-retries")
+                .value_name("COUNT")
+                .about("Resume interrupted streams (or chunks, with --segments) from their last received byte")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(CONCURRENT_DOWNLOADS_ARG)
+                .default_value("3")
+                .long("concurrent-downloads")
+                .value_name("N")
+                .about("Download up to N videos at the same time, the others waiting in line")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(PLAYER_CLIENT_ARG)
+                .long("player-client")
+                .value_name("CLIENTS")
+                .long_about(
+                    "\
+Ask for the streams as these apps, in order, until one gets them (default: \"ios,android,web,tv_embedded\").
+Available clients: web, android, ios and tv_embedded.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(CONFIG_ARG)
+                .long("config")
+                .value_name("PATH")
+                .long_about(
+                    "\
+Read the options from this file instead of \"$XDG_CONFIG_HOME/you-dl/config.toml\".
+Its keys are the long names of the options, e.g. `output-dir = \"~/Videos\"` or `wrapper = true`.
+The command line comes first, then the YOU_DL_* environment variables (e.g. YOU_DL_OUTPUT_DIR),
+then \"you-dl.toml\" in the current directory, then this file.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(IGNORE_CONFIG_ARG)
+                .long("ignore-config")
+                .about("Read no config file, only the command line and the environment"),
+        )
+        .subcommand(
+            App::new("config")
+                .about("Inspect the configuration")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("show")
+                        .about("Print the value of each option and where it comes from"),
+                ),
         );
 
     let help_message = get_help_message(&mut app);
-    let matches = app.get_matches();
-    let from_file_path = matches.value_of(FROM_FILE_PATH_ARG).map(|s| s.to_owned());
-    let output_dir = matches.value_of(OUTPUT_DIR_ARG).unwrap().to_owned();
+    // the options that can also be set by the environment and the config files
+    let keys: Vec<&str> = app
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .filter(|&key| !matches!(key, CONFIG_ARG | IGNORE_CONFIG_ARG | "help" | "version"))
+        .collect();
+    let matches = app.clone().get_matches();
+    let sources = Sources::load(
+        &keys,
+        matches.value_of(CONFIG_ARG),
+        matches.is_present(IGNORE_CONFIG_ARG),
+    )?;
+    let mut options = Options {
+        matches: &matches,
+        sources,
+        resolved: Vec::new(),
+    };
+
+    let from_file_path = options.value(FROM_FILE_PATH_ARG)?;
+    let format = options.value(FORMAT_ARG)?;
+    let dump_json = options.flag(DUMP_JSON_ARG)?;
+    let embed_metadata = options.flag(EMBED_METADATA_ARG)?;
+    let embed_thumbnail = options.flag(EMBED_THUMBNAIL_ARG)?;
+    let extract_audio = options.flag(EXTRACT_AUDIO_ARG)?;
+    let remux_video = options.value(REMUX_VIDEO_ARG)?;
+    let temp_dir = options.value(TEMP_DIR_ARG)?;
+    let exec = options.value(EXEC_ARG)?;
+    let list_subs = options.flag(LIST_SUBS_ARG)?;
+    let write_subs = options.flag(WRITE_SUBS_ARG)?;
+    let write_auto_subs = options.flag(WRITE_AUTO_SUBS_ARG)?;
+    let sub_langs = options.value(SUB_LANGS_ARG)?.unwrap();
+    let sub_format = options.value(SUB_FORMAT_ARG)?.unwrap();
+    let embed_subs = options.flag(EMBED_SUBS_ARG)?;
+    let audio_format = options.value(AUDIO_FORMAT_ARG)?;
+    let audio_quality = options.value(AUDIO_QUALITY_ARG)?;
+    let filename_policy = options.value(FILENAME_POLICY_ARG)?;
+    let max_filename_bytes = options.value(MAX_FILENAME_BYTES_ARG)?.unwrap();
+    let download_archive = options.value(DOWNLOAD_ARCHIVE_ARG)?;
+    let playlist_items = options.value(PLAYLIST_ITEMS_ARG)?;
+    let playlist_reverse = options.flag(PLAYLIST_REVERSE_ARG)?;
+    let no_playlist = options.flag(NO_PLAYLIST_ARG)?;
+    let output_dir = options.value(OUTPUT_DIR_ARG)?.unwrap();
+    let output_template = options.value(OUTPUT_TEMPLATE_ARG)?;
+    let player_clients = options.value(PLAYER_CLIENT_ARG)?;
+    let segments = options.value(SEGMENTS_ARG)?.unwrap();
+    let chunk_size = options.value(CHUNK_SIZE_ARG)?.unwrap();
+    let concurrent_downloads = options.value(CONCURRENT_DOWNLOADS_ARG)?.unwrap();
+    let retries = options.value(RETRIES_ARG)?.unwrap();
+    let limit_rate = options.value(LIMIT_RATE_ARG)?;
+    let limit_rate_schedule = options.value(LIMIT_RATE_SCHEDULE_ARG)?;
+    let fragment_retries = options.value(FRAGMENT_RETRIES_ARG)?.unwrap();
+    let use_wrapper = options.flag(USE_WRAPPER_ARG)?;
+    debug_assert_eq!(options.resolved.len(), keys.len(), "every option resolved");
+
+    // like `--help`, prints and exits before anything gets downloaded
+    if let Some(("config", _)) = matches.subcommand() {
+        print!("{}", sources::show(&options.resolved));
+        std::process::exit(0);
+    }
     let urls = matches
         .values_of(URL_ARG)
         .map(|values| values.collect::<Vec<&str>>())
@@ -67,17 +446,116 @@ For more info, check \"github.com/ytdl-org/youtube-dl\".",
                 .map(|&url| url.to_owned())
                 .collect::<Vec<String>>()
         });
-    let use_wrapper = matches.is_present(USE_WRAPPER_ARG);
 
     Ok(RawCliArgs {
         help_message,
+        audio_format,
+        audio_quality,
+        chunk_size,
+        concurrent_downloads,
+        download_archive,
+        dump_json,
+        embed_metadata,
+        embed_subs,
+        embed_thumbnail,
+        exec,
+        extract_audio,
+        filename_policy,
+        fragment_retries,
         from_file_path,
+        format,
+        limit_rate,
+        limit_rate_schedule,
+        list_subs,
+        max_filename_bytes,
+        no_playlist,
         output_dir,
+        output_template,
+        player_clients,
+        playlist_items,
+        playlist_reverse,
+        remux_video,
+        retries,
+        segments,
+        sub_format,
+        sub_langs,
+        temp_dir,
         urls,
         use_wrapper,
+        write_auto_subs,
+        write_subs,
     })
 }
 
+// Resolves each option from the command line, then the other sources, then its default.
+struct Options<'a> {
+    matches: &'a ArgMatches,
+    sources: Sources,
+    resolved: Vec<ResolvedOption>,
+}
+
+impl Options<'_> {
+    fn value(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+        let (value, source) = if self.matches.occurrences_of(key) > 0 {
+            (
+                self.matches.value_of(key).map(|s| s.to_owned()),
+                Source::CommandLine,
+            )
+        } else if let Some((value, source)) = self.sources.get(key) {
+            match value {
+                Value::Text(text) => (Some(text.to_owned()), source.clone()),
+                Value::Bool(_) => {
+                    return Err(ConfigError(format!(
+                        "{}: \"{}\" expects a value, not true or false",
+                        source, key
+                    )))
+                }
+            }
+        } else {
+            (
+                self.matches.value_of(key).map(|s| s.to_owned()),
+                Source::Default,
+            )
+        };
+        self.resolve(key, value.clone().map(Value::Text), source);
+        Ok(value)
+    }
+
+    // a flag on the command line can only be turned on
+    fn flag(&mut self, key: &str) -> Result<bool, ConfigError> {
+        let (flag, source) = if self.matches.is_present(key) {
+            (true, Source::CommandLine)
+        } else if let Some((value, source)) = self.sources.get(key) {
+            let flag = match value {
+                Value::Bool(flag) => *flag,
+                Value::Text(text) => match text.trim() {
+                    "true" | "1" => true,
+                    "false" | "0" | "" => false,
+                    _ => {
+                        return Err(ConfigError(format!(
+                            "{}: \"{}\" expects true or false, not \"{}\"",
+                            source, key, text
+                        )))
+                    }
+                },
+            };
+            (flag, source.clone())
+        } else {
+            (false, Source::Default)
+        };
+        self.resolve(key, Some(Value::Bool(flag)), source);
+        Ok(flag)
+    }
+
+    fn resolve(&mut self, key: &str, value: Option<Value>, source: Source) {
+        self.resolved.push(ResolvedOption {
+            key: key.to_owned(),
+            value,
+            source,
+        });
+    }
+}
+
 fn get_help_message(app: &mut clap::App) -> String {
     let mut bytes_vector = Vec::new();
     app.write_help(&mut bytes_vector)
@@ -0,0 +1,316 @@
+use super::config_file::{self, Value};
+use super::ConfigError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// looked up in the current directory, e.g. at the root of a project sharing its download settings
+const PROJECT_FILE: &str = "you-dl.toml";
+// A project file comes with its directory, e.g. a cloned repository:
+// it can't run commands, nor choose where files are read from or written to.
+const PROJECT_FILE_DENIED_KEYS: &[&str] = &[
+    "exec",
+    "output-dir",
+    "output",
+    "temp-dir",
+    "download-archive",
+    "from-file",
+];
+
+// Where the value of an option comes from, by decreasing precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    CommandLine,
+    Env(String),
+    ProjectFile(PathBuf),
+    // `--config` replaces the user file
+    UserFile(PathBuf),
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Env(variable) => write!(f, "environment variable {}", variable),
+            Source::ProjectFile(path) => write!(f, "project file {}", path.display()),
+            Source::UserFile(path) => write!(f, "user file {}", path.display()),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+// The sources below the command line, by decreasing precedence: environment, project file and user file.
+pub struct Sources(Vec<BTreeMap<String, (Value, Source)>>);
+
+impl Sources {
+    // `keys` are the long names of the options; any other key in the files is rejected.
+    pub fn load(
+        keys: &[&str],
+        config_path: Option<&str>,
+        ignore_config: bool,
+    ) -> Result<Self, ConfigError> {
+        let mut layers = vec![env_layer(keys, |variable| std::env::var(variable).ok())];
+        if ignore_config {
+            return Ok(Sources(layers));
+        }
+
+        let project_file = Path::new(PROJECT_FILE);
+        if project_file.is_file() {
+            layers.push(project_layer(keys, project_file)?);
+        }
+        // an explicit config file has to exist, unlike the default one
+        match config_path.map(PathBuf::from).or_else(user_config_path) {
+            Some(path) if config_path.is_some() || path.is_file() => {
+                layers.push(file_layer(keys, &path, Source::UserFile)?)
+            }
+            _ => {}
+        }
+        Ok(Sources(layers))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&(Value, Source)> {
+        self.0.iter().find_map(|layer| layer.get(key))
+    }
+}
+
+// e.g. `YOU_DL_OUTPUT_DIR` for `--output-dir`
+fn env_variable(key: &str) -> String {
+    format!("YOU_DL_{}", key.to_uppercase().replace('-', "_"))
+}
+
+fn env_layer(
+    keys: &[&str],
+    get_variable: impl Fn(&str) -> Option<String>,
+) -> BTreeMap<String, (Value, Source)> {
+    keys.iter()
+        .filter_map(|&key| {
+            let variable = env_variable(key);
+            get_variable(&variable)
+                .map(|value| (key.to_owned(), (Value::Text(value), Source::Env(variable))))
+        })
+        .collect()
+}
+
+fn file_layer(
+    keys: &[&str],
+    path: &Path,
+    source: impl Fn(PathBuf) -> Source,
+) -> Result<BTreeMap<String, (Value, Source)>, ConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| {
+        ConfigError(format!(
+            "could not read the config file {}: {}",
+            path.display(),
+            err
+        ))
+    })?;
+    config_file::parse(&contents, path)?
+        .into_iter()
+        .map(|(key, value)| {
+            if keys.contains(&key.as_str()) {
+                Ok((key, (expand_home(value), source(path.to_owned()))))
+            } else {
+                Err(ConfigError(format!(
+                    "{}: unknown option \"{}\"",
+                    path.display(),
+                    key
+                )))
+            }
+        })
+        .collect()
+}
+
+fn project_layer(
+    keys: &[&str],
+    path: &Path,
+) -> Result<BTreeMap<String, (Value, Source)>, ConfigError> {
+    let layer = file_layer(keys, path, Source::ProjectFile)?;
+    match PROJECT_FILE_DENIED_KEYS
+        .iter()
+        .find(|&&key| layer.contains_key(key))
+    {
+        Some(key) => Err(ConfigError(format!(
+            "{}: \"{}\" can only be set in the user file, the environment or on the command line",
+            path.display(),
+            key
+        ))),
+        None => Ok(layer),
+    }
+}
+
+// No shell expands "~/" in config files, e.g. in `output-dir = "~/Videos"`.
+fn expand_home(value: Value) -> Value {
+    match value {
+        Value::Text(text) if text.starts_with("~/") => match std::env::var("HOME") {
+            Ok(home) => Value::Text(format!("{}{}", home, &text[1..])),
+            Err(_) => Value::Text(text),
+        },
+        value => value,
+    }
+}
+
+// `$XDG_CONFIG_HOME/you-dl/config.toml`, or `~/.config/you-dl/config.toml` (`%APPDATA%\you-dl\config.toml` on Windows)
+fn user_config_path() -> Option<PathBuf> {
+    let non_empty = |variable: &str| std::env::var_os(variable).filter(|value| !value.is_empty());
+    let config_dir = if cfg!(windows) {
+        non_empty("APPDATA").map(PathBuf::from)
+    } else {
+        non_empty("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    config_dir.map(|config_dir| config_dir.join("you-dl").join("config.toml"))
+}
+
+// The value an option ends up with, as printed by `you-dl config show`.
+#[derive(Debug)]
+pub struct ResolvedOption {
+    pub key: String,
+    pub value: Option<Value>,
+    pub source: Source,
+}
+
+// Formatted as a config file, so that it can be copied from.
+pub fn show(options: &[ResolvedOption]) -> String {
+    let mut options: Vec<_> = options.iter().collect();
+    options.sort_by(|a, b| a.key.cmp(&b.key));
+    options
+        .iter()
+        .map(|option| match &option.value {
+            Some(value) => {
+                let value = match value {
+                    Value::Bool(flag) => flag.to_string(),
+                    Value::Text(text) => format!("{:?}", text),
+                };
+                format!(
+                    "{:<40} # {}\n",
+                    format!("{} = {}", option.key, value),
+                    option.source
+                )
+            }
+            None => format!("# {} is not set\n", option.key),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the library's `TempDir` isn't part of its API: removed once dropped, even if the test fails
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_the_variables_named_after_the_options() {
+        let layer = env_layer(&["output-dir", "segments"], |variable| match variable {
+            "YOU_DL_OUTPUT_DIR" => Some("~/Videos".to_owned()),
+            _ => None,
+        });
+
+        assert_eq!(
+            layer.into_iter().collect::<Vec<_>>(),
+            vec![(
+                "output-dir".to_owned(),
+                (
+                    Value::Text("~/Videos".to_owned()),
+                    Source::Env("YOU_DL_OUTPUT_DIR".to_owned())
+                )
+            )]
+        );
+    }
+
+    #[test]
+    fn prefers_the_environment_then_the_project_file() {
+        let dir =
+            TempDir(std::env::temp_dir().join(format!("you-dl-sources-{}", std::process::id())));
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let (project_path, user_path) = (dir.0.join("you-dl.toml"), dir.0.join("config.toml"));
+        std::fs::write(&project_path, "segments = 4\n").unwrap();
+        std::fs::write(
+            &user_path,
+            "segments = 2\nwrapper = true\ntemp-dir = \"~/tmp\"\n",
+        )
+        .unwrap();
+        let keys = ["format", "segments", "temp-dir", "wrapper"];
+        let sources = Sources(vec![
+            env_layer(&keys, |variable| {
+                (variable == "YOU_DL_FORMAT").then(|| "best".to_owned())
+            }),
+            project_layer(&keys, &project_path).unwrap(),
+            file_layer(&keys, &user_path, Source::UserFile).unwrap(),
+        ]);
+
+        assert_eq!(
+            sources.get("format").unwrap().1,
+            Source::Env("YOU_DL_FORMAT".to_owned())
+        );
+        assert_eq!(
+            sources.get("segments").unwrap(),
+            &(
+                Value::Text("4".to_owned()),
+                Source::ProjectFile(project_path.clone())
+            )
+        );
+        assert_eq!(
+            sources.get("wrapper").unwrap(),
+            &(Value::Bool(true), Source::UserFile(user_path.clone()))
+        );
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(
+                sources.get("temp-dir").unwrap().0,
+                Value::Text(format!("{}/tmp", home))
+            );
+        }
+        assert_eq!(
+            file_layer(&["format"], &user_path, Source::UserFile)
+                .unwrap_err()
+                .0,
+            format!("{}: unknown option \"segments\"", user_path.display())
+        );
+        // unlike in the user file
+        std::fs::write(&project_path, "temp-dir = \"/tmp\"\n").unwrap();
+        assert_eq!(
+            project_layer(&keys, &project_path).unwrap_err().0,
+            format!(
+                "{}: \"temp-dir\" can only be set in the user file, the environment or on the command line",
+                project_path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn shows_each_value_with_its_source() {
+        let options = vec![
+            ResolvedOption {
+                key: "segments".to_owned(),
+                value: Some(Value::Text("1".to_owned())),
+                source: Source::Default,
+            },
+            ResolvedOption {
+                key: "format".to_owned(),
+                value: None,
+                source: Source::Default,
+            },
+            ResolvedOption {
+                key: "wrapper".to_owned(),
+                value: Some(Value::Bool(true)),
+                source: Source::Env("YOU_DL_WRAPPER".to_owned()),
+            },
+        ];
+
+        assert_eq!(
+            show(&options),
+            "\
+# format is not set
+segments = \"1\"                           # default
+wrapper = true                           # environment variable YOU_DL_WRAPPER
+"
+        );
+    }
+}
@@ -0,0 +1,592 @@
+use crate::models::{self, ChosenFormat, Selection};
+use crate::player::PlayerCache;
+use crate::utils::YOUTUBE_URL;
+use crate::{
+    log, player, subtitles, ui, utils, DownloadArchive, DownloadSettings, FormatSelector,
+    Innertube, Logger, OutputTemplate, PostProcessing, ProgressEvent, ProgressObserver, Prompt,
+    VideoInfo, YouDlError,
+};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// stream URLs expiring sooner than that are fetched again before downloading
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30 * 60);
+
+// Downloads YouTube videos without touching the terminal: progress, prompts and messages go through
+// the `ProgressObserver`, `Prompt` and `Logger` it is given.
+pub struct Downloader {
+    innertube: Innertube,
+    players: PlayerCache,
+    output_dir: String,
+    output_template: OutputTemplate,
+    format: Option<FormatSelector>,
+    post_processing: PostProcessing,
+    settings: DownloadSettings,
+    archive: Option<DownloadArchive>,
+    // without one, the best format is chosen unless a selector is given
+    prompt: Option<Arc<dyn Prompt>>,
+    // without one, messages go to the logger set with `set_logger`
+    logger: Option<Arc<dyn Logger>>,
+}
+
+// e.g. `Downloader::new().output_dir("Videos").format(selector).build()`
+pub struct DownloaderBuilder(Downloader);
+
+impl Downloader {
+    // starts with the defaults of the CLI
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> DownloaderBuilder {
+        DownloaderBuilder(Downloader {
+            innertube: Innertube::default(),
+            players: PlayerCache::default(),
+            output_dir: ".".to_owned(),
+            output_template: OutputTemplate::default(),
+            format: None,
+            post_processing: PostProcessing::default(),
+            settings: DownloadSettings::default(),
+            archive: None,
+            prompt: None,
+            logger: None,
+        })
+    }
+
+    // The metadata, formats and subtitles of a video, without downloading it.
+    pub async fn fetch_info(&self, url: &str) -> Result<VideoInfo, YouDlError> {
+        log::scoped(self.logger.clone(), async {
+            let video_id = utils::extract_video_id(url)?;
+            let mut player_response = self
+                .innertube
+                .get_player_response(video_id, self.settings.retries)
+                .await?;
+            player::resolve_stream_urls(&mut player_response, &self.players).await?;
+            let subtitle_tracks = subtitles::tracks(&player_response);
+            let download_options = models::DownloadOptions::try_from(player_response)?;
+            let mut video_info = VideoInfo::from(&download_options);
+            video_info.subtitles = subtitle_tracks;
+            Ok(video_info)
+        })
+        .await
+    }
+
+    // Fetches the info of a video, unless it is in the download archive.
+    // Resolving every video before choosing their formats keeps the prompts apart from the downloads.
+    pub async fn resolve(
+        &self,
+        url: &str,
+        observer: &dyn ProgressObserver,
+    ) -> Result<Option<VideoInfo>, YouDlError> {
+        log::scoped(self.logger.clone(), async {
+            let video_id = utils::extract_video_id(url)?;
+            if let Some(archive) = &self.archive {
+                if archive.contains(video_id).await? {
+                    info!("skipping {}: already in the download archive", video_id);
+                    observer.on_event(ProgressEvent::Skipped {
+                        reason: "already in the download archive".to_owned(),
+                    });
+                    return Ok(None);
+                }
+            }
+
+            observer.on_event(ProgressEvent::Resolving {
+                url: url.to_owned(),
+            });
+            self.fetch_info(url).await.map(Some)
+        })
+        .await
+    }
+
+    // Whether choosing a format prompts for it, which is why videos get resolved ahead of their download.
+    pub fn asks_for_format(&self) -> bool {
+        self.prompt.is_some()
+            && self.format.is_none()
+            && self.post_processing.audio_extraction.is_none()
+    }
+
+    // Applies the format selector, or asks the prompt when there is none, or else picks the best format.
+    // Extracting audio defaults to the best audio stream instead.
+    pub fn choose_format(&self, video_info: &VideoInfo) -> Result<Selection, YouDlError> {
+        let best_audio = FormatSelector::best_audio();
+        let format = self
+            .format
+            .as_ref()
+            .or(self.post_processing.audio_extraction.map(|_| &best_audio));
+        match (format, &self.prompt) {
+            (None, Some(prompt)) => {
+                ui::ask_preferred_format(prompt.as_ref(), &video_info.title, &video_info.formats)
+            }
+            _ => {
+                let default_selector = FormatSelector::default();
+                let selector = format.unwrap_or(&default_selector);
+                selector.select(&video_info.formats).ok_or_else(|| {
+                    YouDlError::Undownloadable(
+                        video_info.title.to_owned(),
+                        format!("no file format matches \"{}\"", selector),
+                    )
+                })
+            }
+        }
+    }
+
+    // Downloads the chosen formats of a video, then post-processes them, reporting to `observer`.
+    // Stream URLs only last a few hours: the ones about to expire are fetched again first.
+    pub async fn download(
+        &self,
+        video_info: &VideoInfo,
+        selection: Selection,
+        observer: Arc<dyn ProgressObserver>,
+    ) -> Result<(), YouDlError> {
+        let result = log::scoped(self.logger.clone(), async {
+            if expires_soon(video_info, selection) {
+                self.refresh_and_download(video_info, selection, &observer)
+                    .await
+            } else {
+                self.download_chosen(video_info, selection, &observer).await
+            }
+        })
+        .await;
+        report_failure(observer.as_ref(), result)
+    }
+
+    // Fetches, chooses and downloads, skipping the videos already in the download archive.
+    pub async fn process(
+        &self,
+        url: &str,
+        observer: Arc<dyn ProgressObserver>,
+    ) -> Result<(), YouDlError> {
+        let result = log::scoped(
+            self.logger.clone(),
+            self.resolve_and_download(url, &observer),
+        )
+        .await;
+        report_failure(observer.as_ref(), result)
+    }
+
+    async fn download_chosen(
+        &self,
+        video_info: &VideoInfo,
+        selection: Selection,
+        observer: &Arc<dyn ProgressObserver>,
+    ) -> Result<(), YouDlError> {
+        let chosen_format = match (
+            ChosenFormat::from_video_info(video_info, selection)?,
+            &self.post_processing.audio_extraction,
+        ) {
+            // the video would be dropped anyway
+            (ChosenFormat::Merge { audio, .. }, Some(_)) => ChosenFormat::Single(audio),
+            (chosen_format, _) => chosen_format,
+        };
+        // another job of this run may have the same video
+        let claim = match &self.archive {
+            Some(archive) => match archive.claim(&video_info.id).await? {
+                Some(claim) => Some(claim),
+                None => {
+                    info!(
+                        "skipping {}: already in the download archive",
+                        video_info.id
+                    );
+                    observer.on_event(ProgressEvent::Skipped {
+                        reason: "already in the download archive".to_owned(),
+                    });
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+        let chosen_itag = chosen_format.itag();
+        info!("chosen itag {} for: {}", chosen_itag, chosen_format.title());
+        observer.on_event(ProgressEvent::FormatChosen {
+            title: chosen_format.title().to_owned(),
+            itag: chosen_itag.to_owned(),
+        });
+        crate::download(
+            chosen_format,
+            &self.output_dir,
+            &self.output_template,
+            &self.post_processing,
+            &video_info.subtitles,
+            &self.settings,
+            observer,
+        )
+        .await?;
+
+        if let Some(claim) = claim {
+            claim.record(&chosen_itag).await?;
+        }
+        Ok(())
+    }
+
+    async fn refresh_and_download(
+        &self,
+        video_info: &VideoInfo,
+        selection: Selection,
+        observer: &Arc<dyn ProgressObserver>,
+    ) -> Result<(), YouDlError> {
+        info!("fetching the stream URLs of {} again...", video_info.id);
+        let refreshed = self
+            .fetch_info(&format!("{}/watch?v={}", YOUTUBE_URL, video_info.id))
+            .await?;
+        let selection = reselect(selection, video_info, &refreshed).ok_or_else(|| {
+            YouDlError::Undownloadable(
+                video_info.title.to_owned(),
+                "the chosen format is no longer available".to_owned(),
+            )
+        })?;
+        self.download_chosen(&refreshed, selection, observer).await
+    }
+
+    async fn resolve_and_download(
+        &self,
+        url: &str,
+        observer: &Arc<dyn ProgressObserver>,
+    ) -> Result<(), YouDlError> {
+        let video_info = match self.resolve(url, observer.as_ref()).await? {
+            Some(video_info) => video_info,
+            None => return Ok(()),
+        };
+        let selection = self.choose_format(&video_info)?;
+        self.download_chosen(&video_info, selection, observer).await
+    }
+}
+
+fn expires_soon(video_info: &VideoInfo, selection: Selection) -> bool {
+    let indexes = match selection {
+        Selection::Single(index) => vec![index],
+        Selection::Pair(video, audio) => vec![video, audio],
+    };
+    indexes
+        .into_iter()
+        .filter_map(|index| video_info.formats.get(index))
+        .filter_map(|format| expiry(&format.url))
+        .any(|expiry| expiry < SystemTime::now() + EXPIRY_MARGIN)
+}
+
+// e.g. "https://r1---sn-4g5e6nsz.googlevideo.com/videoplayback?expire=1600000000&..."
+fn expiry(url: &str) -> Option<SystemTime> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let (_, expire) = url.query_pairs().find(|(name, _)| name == "expire")?;
+    Some(UNIX_EPOCH + Duration::from_secs(expire.parse().ok()?))
+}
+
+// The same formats, by itag, among the ones of `to`.
+fn reselect(selection: Selection, from: &VideoInfo, to: &VideoInfo) -> Option<Selection> {
+    let index_in_to = |index: usize| {
+        let itag = &from.formats.get(index)?.itag;
+        to.formats.iter().position(|format| &format.itag == itag)
+    };
+    Some(match selection {
+        Selection::Single(index) => Selection::Single(index_in_to(index)?),
+        Selection::Pair(video, audio) => Selection::Pair(index_in_to(video)?, index_in_to(audio)?),
+    })
+}
+
+// Every error ends the download, whatever step it comes from.
+fn report_failure(
+    observer: &dyn ProgressObserver,
+    result: Result<(), YouDlError>,
+) -> Result<(), YouDlError> {
+    if let Err(e) = &result {
+        observer.on_event(ProgressEvent::Failed {
+            error: e.to_string(),
+        });
+    }
+    result
+}
+
+impl DownloaderBuilder {
+    pub fn innertube(mut self, innertube: Innertube) -> Self {
+        self.0.innertube = innertube;
+        self
+    }
+
+    pub fn output_dir(mut self, output_dir: &str) -> Self {
+        self.0.output_dir = output_dir.to_owned();
+        self
+    }
+
+    pub fn output_template(mut self, output_template: OutputTemplate) -> Self {
+        self.0.output_template = output_template;
+        self
+    }
+
+    pub fn format(mut self, format: FormatSelector) -> Self {
+        self.0.format = Some(format);
+        self
+    }
+
+    pub fn post_processing(mut self, post_processing: PostProcessing) -> Self {
+        self.0.post_processing = post_processing;
+        self
+    }
+
+    pub fn download_settings(mut self, settings: DownloadSettings) -> Self {
+        self.0.settings = settings;
+        self
+    }
+
+    pub fn archive(mut self, archive: DownloadArchive) -> Self {
+        self.0.archive = Some(archive);
+        self
+    }
+
+    pub fn prompt(mut self, prompt: impl Prompt + 'static) -> Self {
+        self.0.prompt = Some(Arc::new(prompt));
+        self
+    }
+
+    pub fn logger(mut self, logger: impl Logger + 'static) -> Self {
+        self.0.logger = Some(Arc::new(logger));
+        self
+    }
+
+    pub fn build(self) -> Downloader {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FormatInfo, StreamKind, SCHEMA_VERSION};
+    use crate::test_dir::TempDir;
+    use crate::{test_server, LogLevel, NoProgress};
+    use std::sync::Mutex;
+
+    // answers with the given indexes, recording the questions
+    struct ScriptedPrompt(Mutex<Vec<usize>>, Arc<Mutex<Vec<String>>>);
+
+    impl Prompt for ScriptedPrompt {
+        fn select(&self, message: &str, _items: &[String]) -> Result<usize, YouDlError> {
+            self.1.lock().unwrap().push(message.to_owned());
+            Ok(self.0.lock().unwrap().remove(0))
+        }
+    }
+
+    fn video_info() -> VideoInfo {
+        let format = |itag: &str, kind, height| FormatInfo {
+            itag: itag.to_owned(),
+            ext: "mp4".to_owned(),
+            kind,
+            mime_type: None,
+            codecs: None,
+            bitrate: None,
+            width: None,
+            height,
+            filesize: None,
+            filesize_approx: None,
+            url: format!("https://example.com/{}", itag),
+        };
+        VideoInfo {
+            schema_version: SCHEMA_VERSION,
+            id: "dQw4w9WgXcQ".to_owned(),
+            title: "Title".to_owned(),
+            uploader: None,
+            upload_date: None,
+            description: None,
+            thumbnail: None,
+            formats: vec![
+                format("18", StreamKind::Muxed, Some(360)),
+                format("137", StreamKind::VideoOnly, Some(1080)),
+                format("140", StreamKind::AudioOnly, None),
+            ],
+            subtitles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn asks_for_the_audio_to_merge_with_a_video_only_format() {
+        let questions = Arc::new(Mutex::new(Vec::new()));
+        let downloader = Downloader::new()
+            .prompt(ScriptedPrompt(
+                Mutex::new(vec![1, 0]),
+                Arc::clone(&questions),
+            ))
+            .build();
+
+        assert_eq!(
+            downloader.choose_format(&video_info()).unwrap(),
+            Selection::Pair(1, 2)
+        );
+        assert_eq!(
+            *questions.lock().unwrap(),
+            vec![
+                "choose the file format for: Title",
+                "choose the audio to merge with itag 137"
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_choices_out_of_range() {
+        // past the formats, then past the audio to merge with itag 137
+        for (answers, expected) in [
+            (vec![3], "User Error: invalid choice 3 out of 3 items"),
+            (vec![1, 1], "User Error: invalid choice 1 out of 1 items"),
+        ] {
+            let downloader = Downloader::new()
+                .prompt(ScriptedPrompt(Mutex::new(answers), Arc::default()))
+                .build();
+            let error = downloader.choose_format(&video_info()).unwrap_err();
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn selects_without_asking_when_given_a_selector() {
+        let questions = Arc::new(Mutex::new(Vec::new()));
+        let downloader = Downloader::new()
+            .format("height<=720".parse().unwrap())
+            .prompt(ScriptedPrompt(
+                Mutex::new(Vec::new()),
+                Arc::clone(&questions),
+            ))
+            .build();
+
+        assert_eq!(
+            downloader.choose_format(&video_info()).unwrap(),
+            Selection::Single(0)
+        );
+        assert!(questions.lock().unwrap().is_empty());
+        assert_eq!(
+            Downloader::new()
+                .build()
+                .choose_format(&video_info())
+                .unwrap(),
+            Selection::Single(0)
+        );
+    }
+
+    #[test]
+    fn reports_the_title_of_undownloadable_videos() {
+        let downloader = Downloader::new()
+            .format("height>1080".parse().unwrap())
+            .build();
+        match downloader.choose_format(&video_info()) {
+            Err(YouDlError::Undownloadable(title, _)) => assert_eq!(title, "Title"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn finds_the_chosen_formats_again_once_their_urls_expire() {
+        let expiring_at = |time: SystemTime| {
+            let mut video_info = video_info();
+            let expire = time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+            video_info.formats[1].url = format!("https://example.com/137?expire={}", expire);
+            video_info
+        };
+        let in_an_hour = expiring_at(SystemTime::now() + Duration::from_secs(3600));
+        assert!(!expires_soon(&in_an_hour, Selection::Pair(1, 2)));
+        let in_a_minute = expiring_at(SystemTime::now() + Duration::from_secs(60));
+        assert!(expires_soon(&in_a_minute, Selection::Pair(1, 2)));
+        assert!(!expires_soon(&in_a_minute, Selection::Single(0)));
+
+        let mut refreshed = video_info();
+        refreshed.formats.reverse();
+        assert_eq!(
+            reselect(Selection::Pair(1, 2), &video_info(), &refreshed),
+            Some(Selection::Pair(1, 0))
+        );
+        refreshed.formats.remove(0);
+        assert_eq!(
+            reselect(Selection::Pair(1, 2), &video_info(), &refreshed),
+            None
+        );
+    }
+
+    #[test]
+    fn reports_each_step_of_the_download() {
+        let base_url =
+            test_server::start(|request| test_server::serve_ranges(request, b"0123456789"));
+        let output_dir = TempDir::new("events");
+        let mut video_info = video_info();
+        video_info.formats[0].url = format!("{}/videoplayback", base_url);
+        let downloader = Downloader::new()
+            .output_dir(output_dir.path().to_str().unwrap())
+            .build();
+        let (sender, receiver) = smol::channel::unbounded();
+
+        smol::block_on(downloader.download(&video_info, Selection::Single(0), Arc::new(sender)))
+            .unwrap();
+        let events: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(
+            events[..2],
+            [
+                ProgressEvent::FormatChosen {
+                    title: "Title".to_owned(),
+                    itag: "18".to_owned()
+                },
+                ProgressEvent::Started {
+                    total: Some(10),
+                    downloaded: 0
+                },
+            ]
+        );
+        assert!(matches!(
+            events[events.len() - 2],
+            ProgressEvent::Progress { bytes: 10, .. }
+        ));
+        match &events[events.len() - 1] {
+            ProgressEvent::Finished { title, path } => {
+                assert_eq!(title, "Title");
+                assert_eq!(std::fs::read(path).unwrap(), b"0123456789");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    struct RecordingLogger(Arc<Mutex<Vec<String>>>);
+
+    impl Logger for RecordingLogger {
+        fn log(&self, _level: LogLevel, message: &str) {
+            self.0.lock().unwrap().push(message.to_owned());
+        }
+    }
+
+    #[test]
+    fn logs_to_the_logger_of_each_downloader() {
+        let dir = TempDir::new("logged");
+        let archive_path = dir.join("archive.txt");
+        std::fs::write(
+            &archive_path,
+            "youtube dQw4w9WgXcQ 18\nyoutube jNQXAC9IVRw 18\n",
+        )
+        .unwrap();
+        let (first_messages, second_messages) = Default::default();
+        let downloader = |messages: &Arc<Mutex<Vec<String>>>| {
+            Downloader::new()
+                .archive(DownloadArchive::new(&archive_path))
+                .logger(RecordingLogger(Arc::clone(messages)))
+                .build()
+        };
+        let (first, second) = (downloader(&first_messages), downloader(&second_messages));
+
+        smol::block_on(futures::future::try_join(
+            first.process("https://youtu.be/dQw4w9WgXcQ", Arc::new(NoProgress)),
+            second.process("https://youtu.be/jNQXAC9IVRw", Arc::new(NoProgress)),
+        ))
+        .unwrap();
+        assert_eq!(
+            *first_messages.lock().unwrap(),
+            vec!["skipping dQw4w9WgXcQ: already in the download archive"]
+        );
+        assert_eq!(
+            *second_messages.lock().unwrap(),
+            vec!["skipping jNQXAC9IVRw: already in the download archive"]
+        );
+    }
+
+    #[test]
+    fn reports_the_failures() {
+        let (sender, receiver) = smol::channel::unbounded();
+        let result = smol::block_on(
+            Downloader::new()
+                .build()
+                .process("not a url", Arc::new(sender)),
+        );
+
+        let error = result.unwrap_err().to_string();
+        assert_eq!(receiver.try_recv(), Ok(ProgressEvent::Failed { error }));
+        assert!(receiver.try_recv().is_err());
+    }
+}
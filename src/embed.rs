@@ -0,0 +1,228 @@
+use crate::ffmpeg::{self, CoverArt};
+use crate::models::DownloadOption;
+use crate::utils::YOUTUBE_URL;
+use crate::YouDlError;
+use async_compat::CompatExt;
+use smol::fs;
+use std::path::Path;
+
+// the first bytes of every JPEG file
+const JPEG_SIGNATURE: &[u8] = &[0xFF, 0xD8, 0xFF];
+
+// Writes the metadata and/or the thumbnail of the video into the downloaded file,
+// which ffmpeg rewrites into a temporary file that then replaces it.
+// `has_video` tells whether the file has a video stream, which the thumbnail must come after.
+pub async fn embed(
+    path: &Path,
+    download_option: &DownloadOption,
+    has_video: bool,
+    metadata: bool,
+    thumbnail: bool,
+) -> Result<(), YouDlError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tags = if metadata {
+        tags(download_option, &extension)
+    } else {
+        Vec::new()
+    };
+
+    let thumbnail_url = match (thumbnail, &download_option.thumbnail_url) {
+        (false, _) => None,
+        (true, None) => {
+            warn!("no thumbnail to embed for: {}", download_option.title);
+            None
+        }
+        (true, Some(_)) if !supports_cover_art(&extension) => {
+            warn!("thumbnails can't be embedded into .{} files", extension);
+            None
+        }
+        (true, Some(url)) => Some(url),
+    };
+    if tags.is_empty() && thumbnail_url.is_none() {
+        return Ok(());
+    }
+
+    let thumbnail_path = path.with_extension(format!("{}.jpg", extension));
+    let temp_path = path.with_extension(format!("temp.{}", extension));
+    let embedded = embed_into(
+        path,
+        &temp_path,
+        &tags,
+        thumbnail_url.map(|url| (url.as_str(), thumbnail_path.as_path())),
+        &extension,
+        has_video,
+    )
+    .await;
+    // whatever step failed, neither the thumbnail nor the temporary file is left behind
+    let _ = fs::remove_file(&thumbnail_path).await;
+    let _ = fs::remove_file(&temp_path).await;
+    embedded
+}
+
+// `thumbnail` is the URL to download the cover art from, and where to save it.
+async fn embed_into(
+    path: &Path,
+    temp_path: &Path,
+    tags: &[(&str, String)],
+    thumbnail: Option<(&str, &Path)>,
+    extension: &str,
+    has_video: bool,
+) -> Result<(), YouDlError> {
+    let cover_art = match thumbnail {
+        Some((url, thumbnail_path)) => {
+            download_thumbnail(url, thumbnail_path).await?;
+            Some(match extension {
+                "mkv" | "mka" => CoverArt::Attachment(thumbnail_path),
+                _ => CoverArt::AttachedPicture {
+                    path: thumbnail_path,
+                    video_streams: has_video as usize,
+                },
+            })
+        }
+        None => None,
+    };
+    ffmpeg::embed(path, temp_path, tags, cover_art).await?;
+    fs::rename(temp_path, path)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))
+}
+
+// ffmpeg turns these keys into MP4 atoms, ID3 frames or Vorbis comments;
+// Matroska files take the tag names of its specification instead.
+fn tags(download_option: &DownloadOption, extension: &str) -> Vec<(&'static str, String)> {
+    let (artist_key, date_key, description_key, comment_key) = match extension {
+        "mkv" | "mka" | "webm" => ("ARTIST", "DATE_RELEASED", "DESCRIPTION", "COMMENT"),
+        _ => ("artist", "date", "description", "comment"),
+    };
+    // YYYYMMDD -> YYYY-MM-DD
+    let upload_date = download_option
+        .upload_date
+        .as_ref()
+        .filter(|upload_date| upload_date.len() == 8)
+        .map(|upload_date| {
+            format!(
+                "{}-{}-{}",
+                &upload_date[..4],
+                &upload_date[4..6],
+                &upload_date[6..]
+            )
+        });
+
+    let description = download_option
+        .description
+        .clone()
+        .filter(|description| !description.is_empty());
+
+    let mut tags = vec![("title", download_option.title.to_owned())];
+    tags.extend(
+        download_option
+            .uploader
+            .clone()
+            .map(|uploader| (artist_key, uploader)),
+    );
+    tags.extend(upload_date.map(|upload_date| (date_key, upload_date)));
+    tags.extend(description.map(|description| (description_key, description)));
+    tags.push((
+        comment_key,
+        format!("{}/watch?v={}", YOUTUBE_URL, download_option.video_id),
+    ));
+    tags
+}
+
+fn supports_cover_art(extension: &str) -> bool {
+    matches!(
+        extension,
+        "mp4" | "m4a" | "mov" | "mp3" | "flac" | "mkv" | "mka"
+    )
+}
+
+// Cover art is stored as JPEG, while thumbnails can also come as WebP: the others are converted with ffmpeg.
+async fn download_thumbnail(url: &str, path: &Path) -> Result<(), YouDlError> {
+    let bytes = crate::get(url)
+        .await?
+        .bytes()
+        .compat()
+        .await
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+    if bytes.starts_with(JPEG_SIGNATURE) {
+        return fs::write(path, bytes)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()));
+    }
+
+    let original_path = path.with_extension("original");
+    fs::write(&original_path, bytes)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))?;
+    let converted = ffmpeg::convert_image(&original_path, path).await;
+    let _ = fs::remove_file(&original_path).await;
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use crate::test_server;
+
+    #[test]
+    fn names_tags_after_the_container() {
+        let download_option = DownloadOption::fixture();
+        let keys = |extension| {
+            tags(&download_option, extension)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            tags(&download_option, "mp4"),
+            vec![
+                ("title", "Never Gonna Give You Up".to_owned()),
+                ("artist", "Rick Astley".to_owned()),
+                ("date", "2009-10-25".to_owned()),
+                (
+                    "comment",
+                    "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            keys("mkv"),
+            vec!["title", "ARTIST", "DATE_RELEASED", "COMMENT"]
+        );
+    }
+
+    #[test]
+    fn leaves_nothing_behind_when_embedding_fails() {
+        let base_url = test_server::start(|request| match request.path.as_str() {
+            // not a valid WebP image, which ffmpeg fails to convert (if it's installed at all)
+            "/maxresdefault.webp" => test_server::Response::ok(&b"RIFF\0\0\0\0WEBPVP8 "[..]),
+            _ => test_server::Response::status(404),
+        });
+
+        smol::block_on(async {
+            let dir = TempDir::new("embed");
+            let path = dir.join("video.mp4");
+            fs::write(&path, b"video").await.unwrap();
+
+            for thumbnail in ["maxresdefault.webp", "missing.jpg"] {
+                let mut download_option = DownloadOption::fixture();
+                download_option.thumbnail_url = Some(format!("{}/{}", base_url, thumbnail));
+                assert!(embed(&path, &download_option, true, true, true)
+                    .await
+                    .is_err());
+
+                let file_names: Vec<_> = std::fs::read_dir(dir.path())
+                    .unwrap()
+                    .map(|entry| entry.unwrap().file_name())
+                    .collect();
+                assert_eq!(file_names, vec!["video.mp4"]);
+            }
+            assert_eq!(fs::read(&path).await.unwrap(), b"video");
+        })
+    }
+}
@@ -0,0 +1,366 @@
+use crate::audio_extraction::{AudioExtraction, AudioFormat, AudioQuality};
+use crate::YouDlError;
+use smol::process;
+use std::ffi::OsString;
+use std::path::Path;
+
+// COMMAND: ffmpeg -i <video> -i <audio> -map 0:v:0 -map 1:a:0 -c copy <output>
+pub async fn merge(video: &Path, audio: &Path, output: &Path) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command
+        .arg("-i")
+        .arg(video)
+        .arg("-i")
+        .arg(audio)
+        .args(["-map", "0:v:0", "-map", "1:a:0", "-c", "copy"])
+        .arg(output);
+    run(command).await
+}
+
+// COMMAND: ffmpeg -i <input> -map 0 -c copy <output>
+pub async fn remux(input: &Path, output: &Path) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command
+        .arg("-i")
+        .arg(input)
+        .args(["-map", "0", "-c", "copy"])
+        .arg(output);
+    run(command).await
+}
+
+// COMMAND: ffmpeg -i <input> -frames:v 1 <output>
+// e.g. to turn a WebP thumbnail into a JPEG one
+pub async fn convert_image(input: &Path, output: &Path) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command
+        .arg("-i")
+        .arg(input)
+        .args(["-frames:v", "1"])
+        .arg(output);
+    run(command).await
+}
+
+// Video and audio streams can only be copied without re-encoding
+// if the output container supports both codecs: mp4 for h264/aac, mkv for anything else.
+pub fn merge_file_extension(video_extension: &str, audio_extension: &str) -> &'static str {
+    match (video_extension, audio_extension) {
+        ("mp4", "m4a") => "mp4",
+        ("webm", "webm") => "webm",
+        _ => "mkv",
+    }
+}
+
+// COMMAND: ffmpeg -i <input> -vn (-c:a copy | -c:a <encoder> <quality>) <output>
+// `codecs` are those of the input, e.g. "opus" or "avc1.64001F, mp4a.40.2".
+pub async fn extract_audio(
+    input: &Path,
+    output: &Path,
+    extraction: &AudioExtraction,
+    codecs: &str,
+) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command
+        .arg("-i")
+        .arg(input)
+        .arg("-vn")
+        .args(audio_codec_args(extraction, codecs))
+        .arg(output);
+    run(command).await
+}
+
+// How a cover image is stored, depending on the container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoverArt<'a> {
+    // an extra video stream (mp4, m4a, mp3, flac), after the `video_streams` already there
+    AttachedPicture {
+        path: &'a Path,
+        video_streams: usize,
+    },
+    // a file attachment (mkv, mka)
+    Attachment(&'a Path),
+}
+
+// COMMAND: ffmpeg -i <input> [-i <cover>] -map 0 [-map 1] -c copy [<cover options>] -metadata <key>=<value>... <output>
+pub async fn embed(
+    input: &Path,
+    output: &Path,
+    tags: &[(&str, String)],
+    cover_art: Option<CoverArt<'_>>,
+) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command.args(embed_args(input, output, tags, cover_art));
+    run(command).await
+}
+
+fn embed_args(
+    input: &Path,
+    output: &Path,
+    tags: &[(&str, String)],
+    cover_art: Option<CoverArt<'_>>,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-i".into(), input.into()];
+    match cover_art {
+        Some(CoverArt::AttachedPicture {
+            path,
+            video_streams,
+        }) => {
+            let disposition = format!("-disposition:v:{}", video_streams);
+            args.extend(["-i".into(), path.into()]);
+            args.extend(
+                [
+                    "-map",
+                    "0",
+                    "-map",
+                    "1",
+                    "-c",
+                    "copy",
+                    &disposition,
+                    "attached_pic",
+                ]
+                .map(OsString::from),
+            );
+        }
+        Some(CoverArt::Attachment(path)) => {
+            args.extend(["-map", "0", "-c", "copy", "-attach"].map(OsString::from));
+            args.push(path.into());
+            args.extend(
+                [
+                    "-metadata:s:t",
+                    "mimetype=image/jpeg",
+                    "-metadata:s:t",
+                    "filename=cover.jpg",
+                ]
+                .map(OsString::from),
+            );
+        }
+        None => args.extend(["-map", "0", "-c", "copy"].map(OsString::from)),
+    }
+    if output
+        .extension()
+        .is_some_and(|extension| extension == "mp3")
+    {
+        // the most widely supported ID3 version
+        args.extend(["-id3v2_version", "3"].map(OsString::from));
+    }
+    for (key, value) in tags {
+        args.push("-metadata".into());
+        args.push(format!("{}={}", key, value).into());
+    }
+    args.push(output.into());
+    args
+}
+
+// COMMAND: ffmpeg -i <input> -i <subtitles>... -map 0 -map 1... -c copy -c:s <codec> -metadata:s:s:<index> language=<code>... <output>
+// `subtitles` are (language code, path) pairs.
+pub async fn embed_subtitles(
+    input: &Path,
+    output: &Path,
+    subtitles: &[(&str, &Path)],
+) -> Result<(), YouDlError> {
+    let mut command = new_command();
+    command.args(embed_subtitles_args(input, output, subtitles));
+    run(command).await
+}
+
+fn embed_subtitles_args(input: &Path, output: &Path, subtitles: &[(&str, &Path)]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-i".into(), input.into()];
+    for (_, path) in subtitles {
+        args.push("-i".into());
+        args.push(path.into());
+    }
+    for index in 0..=subtitles.len() {
+        args.push("-map".into());
+        args.push(index.to_string().into());
+    }
+    // MP4 only takes its own text format, WebM only WebVTT, Matroska anything
+    let codec = match output.extension().and_then(|extension| extension.to_str()) {
+        Some("mp4") | Some("m4v") | Some("mov") => "mov_text",
+        Some("webm") => "webvtt",
+        _ => "copy",
+    };
+    args.extend(["-c", "copy", "-c:s", codec].map(OsString::from));
+    for (index, (language, _)) in subtitles.iter().enumerate() {
+        args.push(format!("-metadata:s:s:{}", index).into());
+        args.push(format!("language={}", language).into());
+    }
+    args.push(output.into());
+    args
+}
+
+// Without a target format, the audio is copied into the container made for its codec.
+pub fn extracted_audio_extension(format: Option<AudioFormat>, codecs: &str) -> &'static str {
+    match (format, audio_codec(codecs)) {
+        (Some(format), _) => format.file_extension(),
+        (None, Some("aac")) => "m4a",
+        (None, Some("opus")) => "opus",
+        (None, Some("vorbis")) => "ogg",
+        (None, _) => "mka",
+    }
+}
+
+fn audio_codec_args(extraction: &AudioExtraction, codecs: &str) -> Vec<String> {
+    let codec = audio_codec(codecs);
+    let encoder = match extraction.format {
+        None => None,
+        Some(AudioFormat::Mp3) if codec == Some("mp3") => None,
+        Some(AudioFormat::M4a) if codec == Some("aac") => None,
+        Some(AudioFormat::Opus) if codec == Some("opus") => None,
+        Some(AudioFormat::Mp3) => Some("libmp3lame"),
+        Some(AudioFormat::M4a) => Some("aac"),
+        Some(AudioFormat::Opus) => Some("libopus"),
+        Some(AudioFormat::Flac) => Some("flac"),
+    };
+    let quality = match (encoder, extraction.quality) {
+        (None, _) => vec![],
+        // lossless
+        (Some("flac"), _) => vec![],
+        (Some("libmp3lame"), AudioQuality::Vbr(level)) => {
+            vec!["-q:a".to_owned(), level.to_string()]
+        }
+        (Some(_), quality) => vec!["-b:a".to_owned(), format!("{}k", quality.bitrate())],
+    };
+    let mut args = vec!["-c:a".to_owned(), encoder.unwrap_or("copy").to_owned()];
+    args.extend(quality);
+    args
+}
+
+// The audio codec among those of a mime type, as named by ffmpeg.
+fn audio_codec(codecs: &str) -> Option<&'static str> {
+    codecs.split(',').find_map(|codec| match codec.trim() {
+        "opus" => Some("opus"),
+        "vorbis" => Some("vorbis"),
+        "mp3" | "mp4a.40.34" | "mp4a.6b" => Some("mp3"),
+        codec if codec.starts_with("mp4a") => Some("aac"),
+        _ => None,
+    })
+}
+
+fn new_command() -> process::Command {
+    let mut command = process::Command::new("ffmpeg");
+    command.args(["-y", "-loglevel", "error"]);
+    command
+}
+
+async fn run(mut command: process::Command) -> Result<(), YouDlError> {
+    let process_output = command
+        .output()
+        .await
+        .map_err(|e| YouDlError::Ffmpeg(e.to_string()))?;
+
+    if !process_output.status.success() {
+        let err = String::from_utf8(process_output.stderr)
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        return Err(YouDlError::Ffmpeg(err));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_container_supporting_both_codecs() {
+        assert_eq!(merge_file_extension("mp4", "m4a"), "mp4");
+        assert_eq!(merge_file_extension("webm", "webm"), "webm");
+        assert_eq!(merge_file_extension("mp4", "webm"), "mkv");
+    }
+
+    #[test]
+    fn embeds_tags_and_cover_art() {
+        let args = |output: &str, cover_art| {
+            let tags = [
+                ("title", "Title".to_owned()),
+                ("artist", "Uploader".to_owned()),
+            ];
+            embed_args(Path::new("in"), Path::new(output), &tags, cover_art)
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let cover = Path::new("cover.jpg");
+
+        assert_eq!(
+            args("out.mp4", None),
+            "-i in -map 0 -c copy -metadata title=Title -metadata artist=Uploader out.mp4"
+        );
+        assert_eq!(
+            args("out.mp3", Some(CoverArt::AttachedPicture { path: cover, video_streams: 0 })),
+            "-i in -i cover.jpg -map 0 -map 1 -c copy -disposition:v:0 attached_pic -id3v2_version 3 \
+             -metadata title=Title -metadata artist=Uploader out.mp3"
+        );
+        assert_eq!(
+            args("out.mkv", Some(CoverArt::Attachment(cover))),
+            "-i in -map 0 -c copy -attach cover.jpg -metadata:s:t mimetype=image/jpeg -metadata:s:t filename=cover.jpg \
+             -metadata title=Title -metadata artist=Uploader out.mkv"
+        );
+    }
+
+    #[test]
+    fn embeds_subtitles_in_a_codec_of_the_container() {
+        let subtitles = [
+            ("en", Path::new("in.en.vtt")),
+            ("de", Path::new("in.de.vtt")),
+        ];
+        let args = |output: &str| {
+            embed_subtitles_args(Path::new("in"), Path::new(output), &subtitles)
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        assert_eq!(
+            args("out.mp4"),
+            "-i in -i in.en.vtt -i in.de.vtt -map 0 -map 1 -map 2 -c copy -c:s mov_text \
+             -metadata:s:s:0 language=en -metadata:s:s:1 language=de out.mp4"
+        );
+        assert!(args("out.mkv").contains(" -c copy -c:s copy "));
+    }
+
+    #[test]
+    fn copies_audio_unless_the_format_needs_another_codec() {
+        let extraction = |format, quality| AudioExtraction { format, quality };
+        let args =
+            |extraction: AudioExtraction, codecs| audio_codec_args(&extraction, codecs).join(" ");
+
+        assert_eq!(
+            args(extraction(None, AudioQuality::Vbr(0)), "opus"),
+            "-c:a copy"
+        );
+        assert_eq!(extracted_audio_extension(None, "opus"), "opus");
+        assert_eq!(
+            extracted_audio_extension(None, "avc1.42001E, mp4a.40.2"),
+            "m4a"
+        );
+        assert_eq!(
+            args(
+                extraction(Some(AudioFormat::M4a), AudioQuality::Vbr(0)),
+                "mp4a.40.2"
+            ),
+            "-c:a copy"
+        );
+        assert_eq!(
+            args(
+                extraction(Some(AudioFormat::Mp3), AudioQuality::Vbr(2)),
+                "opus"
+            ),
+            "-c:a libmp3lame -q:a 2"
+        );
+        assert_eq!(
+            args(
+                extraction(Some(AudioFormat::Opus), AudioQuality::Bitrate(96)),
+                "mp4a.40.2"
+            ),
+            "-c:a libopus -b:a 96k"
+        );
+        assert_eq!(
+            args(
+                extraction(Some(AudioFormat::Flac), AudioQuality::Vbr(5)),
+                "opus"
+            ),
+            "-c:a flac"
+        );
+    }
+}
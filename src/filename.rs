@@ -0,0 +1,234 @@
+use crate::YouDlError;
+use smol::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Which characters are allowed in file names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilenamePolicy {
+    // only "/" and NUL are replaced
+    Posix,
+    // also replaces the characters Windows rejects, and avoids its reserved names (e.g. "CON")
+    Windows,
+    // the Windows rules, restricted to printable ASCII
+    Ascii,
+}
+
+impl FromStr for FilenamePolicy {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "posix" => Ok(FilenamePolicy::Posix),
+            "windows" => Ok(FilenamePolicy::Windows),
+            "ascii" => Ok(FilenamePolicy::Ascii),
+            _ => Err(YouDlError::User(format!(
+                "invalid filename policy \"{}\"",
+                s
+            ))),
+        }
+    }
+}
+
+impl Default for FilenamePolicy {
+    fn default() -> Self {
+        if cfg!(windows) {
+            FilenamePolicy::Windows
+        } else {
+            FilenamePolicy::Posix
+        }
+    }
+}
+
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+// extensions longer than this are considered part of the name when truncating
+const MAX_EXTENSION_BYTES: usize = 10;
+
+// Turns video titles and other remote values into names that are safe to create within the output directory.
+#[derive(Debug, Clone, Copy)]
+pub struct FilenameSanitizer {
+    pub policy: FilenamePolicy,
+    // the limit of most filesystems, for each path component, suffixes included
+    pub max_bytes: usize,
+}
+
+impl Default for FilenameSanitizer {
+    fn default() -> Self {
+        FilenameSanitizer {
+            policy: FilenamePolicy::default(),
+            max_bytes: 255,
+        }
+    }
+}
+
+impl FilenameSanitizer {
+    // left out of `max_bytes` for what gets appended to names later on,
+    // e.g. " (1)", ".f137.mp4" and ".part.len" or ".en.srt"
+    pub const SUFFIX_BYTES: usize = 32;
+
+    // Replaces the characters that are not allowed by the policy, including "/":
+    // a value never introduces subdirectories.
+    pub fn sanitize_value(&self, value: &str) -> String {
+        value
+            .chars()
+            .map(|c| match self.policy {
+                _ if c == '/' || c == '\0' => '_',
+                FilenamePolicy::Posix => c,
+                FilenamePolicy::Windows
+                    if c.is_control() || WINDOWS_RESERVED_CHARS.contains(&c) =>
+                {
+                    '_'
+                }
+                FilenamePolicy::Windows => c,
+                FilenamePolicy::Ascii
+                    if !c.is_ascii() || c.is_control() || WINDOWS_RESERVED_CHARS.contains(&c) =>
+                {
+                    '_'
+                }
+                FilenamePolicy::Ascii => c,
+            })
+            .collect()
+    }
+
+    // Makes a single path component safe: besides `sanitize_value`, it rules out "." and "..",
+    // fits the name into `max_bytes` (keeping the extension and room for suffixes)
+    // and avoids the names Windows reserves.
+    pub fn sanitize_component(&self, component: &str) -> String {
+        let max_bytes = self.max_bytes.saturating_sub(Self::SUFFIX_BYTES);
+        let mut name = self.sanitize_value(component);
+        name = truncate(&name, max_bytes);
+        if self.policy != FilenamePolicy::Posix {
+            // Windows silently drops them
+            name = name.trim_end_matches(&['.', ' '][..]).to_owned();
+            let stem = name.split('.').next().unwrap_or_default();
+            if WINDOWS_RESERVED_NAMES
+                .iter()
+                .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+            {
+                name = truncate(&format!("_{}", name), max_bytes);
+            }
+        }
+        match name.as_str() {
+            "" | "." => "_".to_owned(),
+            ".." => "__".to_owned(),
+            _ => name,
+        }
+    }
+}
+
+// Cuts the name down to `max_bytes` on a char boundary, shortening the part before the extension if possible.
+fn truncate(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_owned();
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 && name.len() - index <= MAX_EXTENSION_BYTES.min(max_bytes) => {
+            name.split_at(index)
+        }
+        _ => (name, ""),
+    };
+    let mut stem_len = max_bytes - extension.len();
+    while !stem.is_char_boundary(stem_len) {
+        stem_len -= 1;
+    }
+    [&stem[..stem_len], extension].concat()
+}
+
+// Appends " (1)", " (2)"... before the extension until the path doesn't exist yet,
+// e.g. "name.mp4" becomes "name (1).mp4".
+pub async fn avoid_collision(path: &Path) -> PathBuf {
+    if fs::metadata(path).await.is_err() {
+        return path.to_owned();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    for counter in 1.. {
+        let candidate = path.with_file_name(format!("{} ({}){}", stem, counter, extension));
+        if fs::metadata(&candidate).await.is_err() {
+            return candidate;
+        }
+    }
+    unreachable!("a free name is eventually found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn sanitizer(policy: FilenamePolicy) -> FilenameSanitizer {
+        FilenameSanitizer {
+            policy,
+            max_bytes: 255,
+        }
+    }
+
+    #[test]
+    fn applies_policies() {
+        let title = "AC/DC: \"Live\" at <Donington>? Ünïcödé\0.mp4";
+        assert_eq!(
+            sanitizer(FilenamePolicy::Posix).sanitize_component(title),
+            "AC_DC: \"Live\" at <Donington>? Ünïcödé_.mp4"
+        );
+        assert_eq!(
+            sanitizer(FilenamePolicy::Windows).sanitize_component(title),
+            "AC_DC_ _Live_ at _Donington__ Ünïcödé_.mp4"
+        );
+        assert_eq!(
+            sanitizer(FilenamePolicy::Ascii).sanitize_component(title),
+            "AC_DC_ _Live_ at _Donington__ _n_c_d__.mp4"
+        );
+    }
+
+    #[test]
+    fn rules_out_special_names() {
+        let posix = sanitizer(FilenamePolicy::Posix);
+        assert_eq!(posix.sanitize_component(".."), "__");
+        assert_eq!(posix.sanitize_component(""), "_");
+        assert_eq!(posix.sanitize_component("con.mp4"), "con.mp4");
+
+        let windows = sanitizer(FilenamePolicy::Windows);
+        assert_eq!(windows.sanitize_component("con.mp4"), "_con.mp4");
+        assert_eq!(windows.sanitize_component("title. . "), "title");
+        assert_eq!(windows.sanitize_component("..."), "_");
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries_keeping_the_extension() {
+        let sanitizer = FilenameSanitizer {
+            policy: FilenamePolicy::Posix,
+            max_bytes: 10 + FilenameSanitizer::SUFFIX_BYTES,
+        };
+        // "é" takes two bytes
+        assert_eq!(sanitizer.sanitize_component("ééééé.mp4"), "ééé.mp4");
+        assert_eq!(sanitizer.sanitize_component("aéééé.mp4"), "aéé.mp4");
+        assert_eq!(sanitizer.sanitize_component("abcdefghijkl"), "abcdefghij");
+        assert_eq!(
+            sanitizer.sanitize_component("a.verylongextension"),
+            "a.verylong"
+        );
+    }
+
+    #[test]
+    fn numbers_colliding_files() {
+        smol::block_on(async {
+            let dir = TempDir::new("collision");
+            let path = dir.join("name.mp4");
+            assert_eq!(avoid_collision(&path).await, path);
+
+            fs::write(&path, b"").await.unwrap();
+            fs::write(dir.join("name (1).mp4"), b"").await.unwrap();
+            assert_eq!(avoid_collision(&path).await, dir.join("name (2).mp4"));
+        })
+    }
+}
@@ -0,0 +1,334 @@
+use crate::models::PlayerResponse;
+use crate::utils::{self, YOUTUBE_URL};
+use crate::{retry, YouDlError};
+use async_compat::CompatExt;
+use reqwest::header;
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+
+// The apps whose identity is used to ask for the streams of a video: YouTube answers each one differently,
+// e.g. some videos are only available to some clients, and only the web client's streams need the player JS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerClient {
+    Web,
+    Android,
+    Ios,
+    TvEmbedded,
+}
+
+pub const DEFAULT_PLAYER_CLIENTS: &[PlayerClient] = &[
+    PlayerClient::Ios,
+    PlayerClient::Android,
+    PlayerClient::Web,
+    PlayerClient::TvEmbedded,
+];
+
+impl PlayerClient {
+    pub const NAMES: &'static [&'static str] = &["web", "android", "ios", "tv_embedded"];
+
+    // (clientName, id for the "X-YouTube-Client-Name" header, clientVersion)
+    fn identity(&self) -> (&'static str, u32, &'static str) {
+        match self {
+            PlayerClient::Web => ("WEB", 1, "2.20240726.00.00"),
+            PlayerClient::Android => ("ANDROID", 3, "19.29.37"),
+            PlayerClient::Ios => ("IOS", 5, "19.29.1"),
+            PlayerClient::TvEmbedded => ("TVHTML5_SIMPLY_EMBEDDED_PLAYER", 85, "2.0"),
+        }
+    }
+
+    fn user_agent(&self) -> Option<&'static str> {
+        match self {
+            PlayerClient::Android => {
+                Some("com.google.android.youtube/19.29.37 (Linux; U; Android 11) gzip")
+            }
+            PlayerClient::Ios => Some(
+                "com.google.ios.youtube/19.29.1 (iPhone16,2; U; CPU iOS 17_5_1 like Mac OS X;)",
+            ),
+            PlayerClient::Web | PlayerClient::TvEmbedded => None,
+        }
+    }
+
+    fn request_body(&self, video_id: &str) -> serde_json::Value {
+        let (name, _, version) = self.identity();
+        let mut client = json!({
+            "clientName": name,
+            "clientVersion": version,
+            "hl": "en",
+        });
+        let device = match self {
+            PlayerClient::Android => json!({
+                "androidSdkVersion": 30,
+                "osName": "Android",
+                "osVersion": "11",
+            }),
+            PlayerClient::Ios => json!({
+                "deviceMake": "Apple",
+                "deviceModel": "iPhone16,2",
+                "osName": "iPhone",
+                "osVersion": "17.5.1.21F90",
+            }),
+            PlayerClient::Web | PlayerClient::TvEmbedded => json!({}),
+        };
+        if let (Some(client), Some(device)) = (client.as_object_mut(), device.as_object()) {
+            client.extend(device.clone());
+        }
+
+        let mut context = json!({ "client": client });
+        if *self == PlayerClient::TvEmbedded {
+            // embedded players pretend to be on another page
+            context["thirdParty"] = json!({ "embedUrl": format!("{}/", YOUTUBE_URL) });
+        }
+        json!({
+            "context": context,
+            "videoId": video_id,
+            "contentCheckOk": true,
+            "racyCheckOk": true,
+        })
+    }
+}
+
+impl FromStr for PlayerClient {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "web" => Ok(PlayerClient::Web),
+            "android" => Ok(PlayerClient::Android),
+            "ios" => Ok(PlayerClient::Ios),
+            "tv_embedded" => Ok(PlayerClient::TvEmbedded),
+            _ => Err(YouDlError::User(format!(
+                "invalid player client \"{}\", expected one of {}",
+                s,
+                PlayerClient::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PlayerClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PlayerClient::Web => "web",
+            PlayerClient::Android => "android",
+            PlayerClient::Ios => "ios",
+            PlayerClient::TvEmbedded => "tv_embedded",
+        };
+        f.write_str(name)
+    }
+}
+
+// Asks YouTube's internal API for the player response of videos, trying each client in turn
+// until one gets the streams, and falls back to the one embedded in the watch page.
+#[derive(Debug, Clone)]
+pub struct Innertube {
+    base_url: String,
+    clients: Vec<PlayerClient>,
+}
+
+impl Default for Innertube {
+    fn default() -> Self {
+        Innertube::new(DEFAULT_PLAYER_CLIENTS.to_vec())
+    }
+}
+
+impl Innertube {
+    pub fn new(clients: Vec<PlayerClient>) -> Self {
+        Innertube {
+            base_url: YOUTUBE_URL.to_owned(),
+            clients,
+        }
+    }
+
+    // e.g. "http://127.0.0.1:12345", instead of YouTube
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    // `retries` apply to each request failing with a transient error, before moving on to the next client.
+    pub async fn get_player_response(
+        &self,
+        video_id: &str,
+        retries: u32,
+    ) -> Result<PlayerResponse, YouDlError> {
+        let mut failures = Vec::new();
+        for client in &self.clients {
+            match self.request_player(*client, video_id, retries).await {
+                Ok(player_response) => return Ok(player_response),
+                Err(reason) => failures.push(format!("{}: {}", client, reason)),
+            }
+        }
+        match self.scrape_watch_page(video_id, retries).await {
+            Ok(player_response) => Ok(player_response),
+            Err(reason) => {
+                failures.push(format!("watch page: {}", reason));
+                Err(YouDlError::InvalidResponse(format!(
+                    "no player response for {} ({})",
+                    video_id,
+                    failures.join(", ")
+                )))
+            }
+        }
+    }
+
+    // Fails with the reason to try another client, including when the streams are missing.
+    async fn request_player(
+        &self,
+        client: PlayerClient,
+        video_id: &str,
+        retries: u32,
+    ) -> Result<PlayerResponse, String> {
+        let (_, client_id, client_version) = client.identity();
+        let mut request = reqwest::Client::new()
+            .post(&format!(
+                "{}/youtubei/v1/player?prettyPrint=false",
+                self.base_url
+            ))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-YouTube-Client-Name", client_id)
+            .header("X-YouTube-Client-Version", client_version)
+            .body(client.request_body(video_id).to_string());
+        if let Some(user_agent) = client.user_agent() {
+            request = request.header(header::USER_AGENT, user_agent);
+        }
+
+        let response_body = get_text(retries, || {
+            request
+                .try_clone()
+                .expect("request without a streamed body")
+        })
+        .await?;
+        parse_player_response(&response_body)
+    }
+
+    // The page sets `var ytInitialPlayerResponse = {...};` in a script, among megabytes of HTML.
+    async fn scrape_watch_page(
+        &self,
+        video_id: &str,
+        retries: u32,
+    ) -> Result<PlayerResponse, String> {
+        let page = get_text(retries, || {
+            reqwest::Client::new()
+                .get(&format!("{}/watch?v={}", self.base_url, video_id))
+                // skips the cookie consent page shown to European visitors
+                .header(header::COOKIE, "CONSENT=YES+1")
+        })
+        .await?;
+        let json = [
+            "var ytInitialPlayerResponse = ",
+            "window[\"ytInitialPlayerResponse\"] = ",
+            "ytInitialPlayerResponse = ",
+        ]
+        .iter()
+        .find_map(|marker| utils::extract_json_object(&page, marker))
+        .ok_or("missing value for ytInitialPlayerResponse")?;
+        parse_player_response(json)
+    }
+}
+
+// The body of a successful response, retrying transient failures.
+async fn get_text(
+    retries: u32,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<String, String> {
+    retry::with_retries(retries, || async {
+        retry::send(request())
+            .await?
+            .error_for_status()
+            .map_err(retry::from_reqwest)?
+            .text()
+            .compat()
+            .await
+            .map_err(retry::from_reqwest)
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Fails with the reason when the streams are missing, e.g. "LOGIN_REQUIRED (Sign in to confirm your age)".
+fn parse_player_response(json: &str) -> Result<PlayerResponse, String> {
+    let response: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if response.get("streamingData").is_none() {
+        let status = &response["playabilityStatus"];
+        return Err(
+            match (status["status"].as_str(), status["reason"].as_str()) {
+                (Some(status), Some(reason)) => format!("{} ({})", status, reason),
+                (Some(status), None) => status.to_owned(),
+                _ => "no streams".to_owned(),
+            },
+        );
+    }
+    serde_json::from_value(response).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+    use std::sync::{Arc, Mutex};
+
+    const PLAYER_RESPONSE: &str = r#"{"playabilityStatus":{"status":"OK"},"videoDetails":{"videoId":"dQw4w9WgXcQ","title":"Never Gonna Give You Up {}"},
+        "streamingData":{"formats":[{"itag":18,"url":"https://example.com/18","mimeType":"video/mp4; codecs=\"avc1.42001E, mp4a.40.2\"","bitrate":500000,"approxDurationMs":"212091"}]}}"#;
+    const LOGIN_REQUIRED: &str = r#"{"playabilityStatus":{"status":"LOGIN_REQUIRED","reason":"Sign in to confirm your age"}}"#;
+
+    #[test]
+    fn falls_back_to_the_next_client_without_streams() {
+        let client_names = Arc::new(Mutex::new(Vec::new()));
+        let recorded_names = Arc::clone(&client_names);
+        let base_url = test_server::start(move |request| {
+            assert_eq!(request.method, "POST");
+            assert_eq!(request.path, "/youtubei/v1/player?prettyPrint=false");
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            assert_eq!(body["videoId"], "dQw4w9WgXcQ");
+            let client_name = body["context"]["client"]["clientName"].as_str().unwrap();
+            recorded_names.lock().unwrap().push(client_name.to_owned());
+
+            Response::ok(match client_name {
+                "IOS" => LOGIN_REQUIRED,
+                _ => PLAYER_RESPONSE,
+            })
+        });
+
+        let innertube = Innertube::new(vec![
+            PlayerClient::Ios,
+            PlayerClient::Android,
+            PlayerClient::Web,
+        ])
+        .with_base_url(&base_url);
+        let player_response =
+            smol::block_on(innertube.get_player_response("dQw4w9WgXcQ", 0)).unwrap();
+        assert_eq!(
+            player_response.video_details.title,
+            "Never Gonna Give You Up {}"
+        );
+        assert_eq!(player_response.streaming_data.unwrap().formats[0].itag, 18);
+        assert_eq!(*client_names.lock().unwrap(), vec!["IOS", "ANDROID"]);
+    }
+
+    #[test]
+    fn falls_back_to_the_watch_page() {
+        let base_url = test_server::start(|request| match request.method.as_str() {
+            "POST" => Response::ok(LOGIN_REQUIRED),
+            _ if request.path == "/watch?v=dQw4w9WgXcQ" => Response::ok(format!(
+                "<script>var ytInitialPlayerResponse = {};var meta = {{}};</script>",
+                PLAYER_RESPONSE
+            )),
+            _ => Response::ok("<html></html>"),
+        });
+
+        let innertube = Innertube::new(vec![PlayerClient::Ios]).with_base_url(&base_url);
+        let player_response =
+            smol::block_on(innertube.get_player_response("dQw4w9WgXcQ", 0)).unwrap();
+        assert_eq!(player_response.video_details.video_id, "dQw4w9WgXcQ");
+
+        match smol::block_on(innertube.get_player_response("xxxxxxxxxxx", 0)) {
+            Err(YouDlError::InvalidResponse(reason)) => assert_eq!(
+                reason,
+                "no player response for xxxxxxxxxxx (ios: LOGIN_REQUIRED (Sign in to confirm your age), \
+                 watch page: missing value for ytInitialPlayerResponse)"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
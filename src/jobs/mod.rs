@@ -0,0 +1,280 @@
+use crate::terminal::TerminalProgress;
+use futures::future::BoxFuture;
+use indicatif::ProgressBar;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use you_dl::{ProgressEvent, ProgressObserver, YouDlError};
+
+mod prepare;
+
+pub use prepare::{prepare, Prepared};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Queued,
+    Resolving,
+    Downloading,
+    PostProcessing,
+    Done,
+    Skipped,
+    Failed(String),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            JobState::Queued => "queued",
+            JobState::Resolving => "resolving",
+            JobState::Downloading => "downloading",
+            JobState::PostProcessing => "post-processing",
+            JobState::Done => "done",
+            JobState::Skipped => "skipped",
+            JobState::Failed(_) => "failed",
+        };
+        f.pad(state)
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub url: String,
+    // known once the format is chosen
+    pub title: Option<String>,
+    pub state: JobState,
+}
+
+// The videos to download, taken in order by a fixed number of workers.
+pub struct JobQueue<T> {
+    jobs: Mutex<Vec<Job>>,
+    // what each job downloads, taken by its worker; jobs without any are already over
+    work: Mutex<Vec<Option<T>>>,
+    next: AtomicUsize,
+}
+
+// Downloads a video, reporting to the given observer.
+pub type Process<T> = dyn Fn(T, Arc<dyn ProgressObserver>) -> BoxFuture<'static, Result<(), YouDlError>>
+    + Send
+    + Sync;
+
+impl<T: Send + 'static> JobQueue<T> {
+    pub fn new(jobs: Vec<(Job, Option<T>)>) -> Self {
+        let (jobs, work) = jobs.into_iter().unzip();
+        JobQueue {
+            jobs: Mutex::new(jobs),
+            work: Mutex::new(work),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // Runs every job with at most one worker per progress bar, each bar showing the job of its worker.
+    pub async fn run(self: &Arc<Self>, bars: Vec<ProgressBar>, process: Arc<Process<T>>) {
+        let workers: Vec<_> = bars
+            .into_iter()
+            .map(|bar| smol::spawn(Arc::clone(self).work(bar, Arc::clone(&process))))
+            .collect();
+        futures::future::join_all(workers).await;
+    }
+
+    async fn work(self: Arc<Self>, bar: ProgressBar, process: Arc<Process<T>>) {
+        while let Some((index, work)) = self.take() {
+            let observer = Arc::new(JobObserver {
+                queue: Arc::clone(&self),
+                index,
+                progress: TerminalProgress(bar.clone()),
+            });
+            let result = process(work, observer.clone()).await;
+            let reported = matches!(self.state(index), JobState::Failed(_));
+            match result {
+                // youtube-dl's failures don't go through the observer, the downloader's do
+                Err(e) if !reported => observer.on_event(ProgressEvent::Failed {
+                    error: e.to_string(),
+                }),
+                Err(_) => {}
+                Ok(()) => self.update(index, |job| {
+                    // the events of a skipped download end there
+                    if job.state != JobState::Skipped {
+                        job.state = JobState::Done
+                    }
+                }),
+            }
+        }
+        bar.finish_and_clear();
+    }
+
+    fn take(&self) -> Option<(usize, T)> {
+        let mut work = self.work.lock().expect("unpoisoned jobs");
+        loop {
+            let index = self.next.fetch_add(1, Ordering::SeqCst);
+            match work.get_mut(index)?.take() {
+                Some(work) => return Some((index, work)),
+                None => continue,
+            }
+        }
+    }
+
+    fn state(&self, index: usize) -> JobState {
+        self.jobs.lock().expect("unpoisoned jobs")[index]
+            .state
+            .clone()
+    }
+
+    fn update(&self, index: usize, update: impl FnOnce(&mut Job)) {
+        update(&mut self.jobs.lock().expect("unpoisoned jobs")[index]);
+    }
+
+    pub fn into_jobs(self) -> Vec<Job> {
+        self.jobs.into_inner().expect("unpoisoned jobs")
+    }
+}
+
+// Follows the state of a job, and shows its progress on the bar of its worker.
+struct JobObserver<T> {
+    queue: Arc<JobQueue<T>>,
+    index: usize,
+    progress: TerminalProgress,
+}
+
+impl<T: Send + 'static> ProgressObserver for JobObserver<T> {
+    fn on_event(&self, event: ProgressEvent) {
+        self.queue.update(self.index, |job| match &event {
+            ProgressEvent::Resolving { .. } => job.state = JobState::Resolving,
+            ProgressEvent::Skipped { .. } => job.state = JobState::Skipped,
+            ProgressEvent::FormatChosen { title, .. } => {
+                job.title = Some(title.to_owned());
+                job.state = JobState::Downloading;
+            }
+            ProgressEvent::Started { .. } | ProgressEvent::Progress { .. } => {
+                job.state = JobState::Downloading
+            }
+            ProgressEvent::PostProcessing { .. } => job.state = JobState::PostProcessing,
+            ProgressEvent::Finished { .. } => job.state = JobState::Done,
+            ProgressEvent::Failed { error } => job.state = JobState::Failed(error.to_owned()),
+        });
+        self.progress.on_event(event);
+    }
+}
+
+// One line per video, in the order of the URLs, after the count of each outcome.
+pub fn summary(jobs: &[Job]) -> String {
+    let count = |is_counted: fn(&JobState) -> bool| {
+        jobs.iter().filter(|job| is_counted(&job.state)).count()
+    };
+    let mut summary = format!(
+        "{} downloaded, {} skipped, {} failed\n",
+        count(|state| *state == JobState::Done),
+        count(|state| *state == JobState::Skipped),
+        count(|state| matches!(state, JobState::Failed(_))),
+    );
+    for job in jobs {
+        let video = match &job.title {
+            Some(title) => format!("{} ({})", title, job.url),
+            None => job.url.to_owned(),
+        };
+        match &job.state {
+            JobState::Failed(error) => {
+                summary.push_str(&format!("{:<8} {}: {}\n", job.state, video, error))
+            }
+            state => summary.push_str(&format!("{:<8} {}\n", state, video)),
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::time::Duration;
+
+    fn job(url: &str, state: JobState) -> (Job, Option<String>) {
+        let work = (state == JobState::Queued).then(|| url.to_owned());
+        let job = Job {
+            url: url.to_owned(),
+            title: None,
+            state,
+        };
+        (job, work)
+    }
+
+    #[test]
+    fn hands_out_the_jobs_first_in_first_out() {
+        let queue = JobQueue::new(vec![
+            job("a", JobState::Queued),
+            job("b", JobState::Skipped),
+            job("c", JobState::Queued),
+        ]);
+
+        assert_eq!(queue.take(), Some((0, "a".to_owned())));
+        assert_eq!(queue.take(), Some((2, "c".to_owned())));
+        assert_eq!(queue.take(), None);
+    }
+
+    #[test]
+    fn runs_every_job_with_bounded_concurrency() {
+        let urls: Vec<String> = (0..6).map(|index| format!("url{}", index)).collect();
+        // resolving found the third video in the download archive
+        let queue = Arc::new(JobQueue::new(
+            urls.iter()
+                .map(|url| match url.as_str() {
+                    "url2" => job(url, JobState::Skipped),
+                    url => job(url, JobState::Queued),
+                })
+                .collect(),
+        ));
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let (running, max_running) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        let process = {
+            let started = Arc::clone(&started);
+            let (running, max_running) = (Arc::clone(&running), Arc::clone(&max_running));
+            move |url: String, observer: Arc<dyn ProgressObserver>| {
+                let (started, running, max_running) = (
+                    Arc::clone(&started),
+                    Arc::clone(&running),
+                    Arc::clone(&max_running),
+                );
+                async move {
+                    started.lock().unwrap().push(url.to_owned());
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    smol::Timer::after(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    match url.as_str() {
+                        "url1" => Err(YouDlError::User("unavailable".to_owned())),
+                        _ => {
+                            observer.on_event(ProgressEvent::FormatChosen {
+                                title: format!("Title of {}", url),
+                                itag: "22".to_owned(),
+                            });
+                            Ok(())
+                        }
+                    }
+                }
+                .boxed()
+            }
+        };
+        let bars = vec![ProgressBar::hidden(), ProgressBar::hidden()];
+        smol::block_on(queue.run(bars, Arc::new(process)));
+
+        started.lock().unwrap().sort();
+        assert_eq!(
+            *started.lock().unwrap(),
+            ["url0", "url1", "url3", "url4", "url5"]
+        );
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        let jobs = Arc::try_unwrap(queue).ok().unwrap().into_jobs();
+        assert_eq!(
+            summary(&jobs),
+            "\
+4 downloaded, 1 skipped, 1 failed
+done     Title of url0 (url0)
+failed   url1: User Error: unavailable
+skipped  url2
+done     Title of url3 (url3)
+done     Title of url4 (url4)
+done     Title of url5 (url5)
+"
+        );
+    }
+}
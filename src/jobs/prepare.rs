@@ -0,0 +1,199 @@
+use super::{Job, JobState};
+use crate::config::Config;
+use crate::terminal::TerminalPrompt;
+use futures::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::future::Future;
+use std::io::IsTerminal;
+use you_dl::{wrapper, Downloader, NoProgress, Prompt, Selection, VideoInfo, YouDlError};
+
+// A video whose format is chosen, waiting for its download.
+pub enum Prepared {
+    Native(VideoInfo, Selection),
+    // the itag youtube-dl is given
+    Wrapper(wrapper::Video, String),
+    // resolved once its download starts, as there is nothing to ask
+    Url(String),
+}
+
+enum Resolved {
+    Native(VideoInfo),
+    Wrapper(wrapper::Video),
+}
+
+// Gets every video ready before any download starts, so that the prompts have the terminal to themselves.
+// Videos that are skipped or fail on the way come back with their final state, and nothing to download.
+pub async fn prepare(
+    urls: &[String],
+    config: &Config,
+    downloader: &Downloader,
+) -> Vec<(Job, Option<Prepared>)> {
+    // stream URLs expire after a few hours, they'd better be fetched right before downloading
+    // (both backends prompt in the same cases)
+    if !downloader.asks_for_format() {
+        return urls
+            .iter()
+            .map(|url| {
+                let job = Job {
+                    url: url.to_owned(),
+                    title: None,
+                    state: JobState::Queued,
+                };
+                (job, Some(Prepared::Url(url.to_owned())))
+            })
+            .collect();
+    }
+    let terminal_prompt = TerminalPrompt;
+    let wrapper_prompt: Option<&dyn Prompt> = if std::io::stdin().is_terminal() {
+        Some(&terminal_prompt)
+    } else {
+        None
+    };
+    let bar = ProgressBar::new(urls.len() as u64);
+    bar.set_style(ProgressStyle::default_bar().template("{prefix:.green} {pos}/{len} {wide_msg}"));
+    bar.set_prefix("Status:");
+    bar.set_message("resolving...");
+
+    let resolve = |url: String| {
+        let bar = bar.clone();
+        async move {
+            let resolved = if config.use_wrapper {
+                wrapper::resolve(&url, config.download_archive.as_ref(), &NoProgress)
+                    .await
+                    .map(|video| video.map(Resolved::Wrapper))
+            } else {
+                downloader
+                    .resolve(&url, &NoProgress)
+                    .await
+                    .map(|video_info| video_info.map(Resolved::Native))
+            };
+            bar.inc(1);
+            resolved
+        }
+    };
+    let choose = |resolved: Resolved| {
+        // the bar is gone once the first prompt shows up
+        bar.finish_and_clear();
+        match resolved {
+            Resolved::Native(video_info) => {
+                let selection = downloader.choose_format(&video_info)?;
+                Ok((
+                    video_info.title.to_owned(),
+                    Prepared::Native(video_info, selection),
+                ))
+            }
+            Resolved::Wrapper(video) => {
+                let itag =
+                    wrapper::choose_format(&video, config.wrapper_options(), wrapper_prompt)?;
+                Ok((video.title.to_owned(), Prepared::Wrapper(video, itag)))
+            }
+        }
+    };
+    let prepared = resolve_then_choose(urls, config.concurrent_downloads, resolve, choose).await;
+    bar.finish_and_clear();
+    prepared
+}
+
+// Resolves `concurrency` URLs at a time, then chooses for each video in turn, in the order of the URLs.
+async fn resolve_then_choose<R, P, Fut>(
+    urls: &[String],
+    concurrency: usize,
+    resolve: impl Fn(String) -> Fut,
+    mut choose: impl FnMut(R) -> Result<(String, P), YouDlError>,
+) -> Vec<(Job, Option<P>)>
+where
+    Fut: Future<Output = Result<Option<R>, YouDlError>>,
+{
+    let resolved: Vec<_> = stream::iter(urls.iter().cloned())
+        .map(resolve)
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    urls.iter()
+        .zip(resolved)
+        .map(|(url, resolved)| {
+            let job = |title, state| Job {
+                url: url.to_owned(),
+                title,
+                state,
+            };
+            match resolved.and_then(|resolved| resolved.map(&mut choose).transpose()) {
+                Ok(Some((title, prepared))) => (job(Some(title), JobState::Queued), Some(prepared)),
+                Ok(None) => (job(None, JobState::Skipped), None),
+                Err(e) => (job(None, JobState::Failed(e.to_string())), None),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn chooses_one_video_at_a_time_once_all_are_resolved() {
+        let urls: Vec<String> = ["slow", "archived", "fast", "private"]
+            .iter()
+            .map(|url| url.to_string())
+            .collect();
+        let resolved = AtomicUsize::new(0);
+        let mut choices = Vec::new();
+
+        let prepared = smol::block_on(resolve_then_choose(
+            &urls,
+            4,
+            |url| {
+                let resolved = &resolved;
+                async move {
+                    if url == "slow" {
+                        smol::Timer::after(Duration::from_millis(20)).await;
+                    }
+                    resolved.fetch_add(1, Ordering::SeqCst);
+                    match url.as_str() {
+                        "archived" => Ok(None),
+                        "private" => Err(YouDlError::User("private video".to_owned())),
+                        _ => Ok(Some(url)),
+                    }
+                }
+            },
+            |url: String| {
+                choices.push((url.to_owned(), resolved.load(Ordering::SeqCst)));
+                Ok((format!("Title of {}", url), url))
+            },
+        ));
+
+        // both choices only came once the slowest video was resolved
+        assert_eq!(
+            choices,
+            vec![("slow".to_owned(), 4), ("fast".to_owned(), 4)]
+        );
+        let states: Vec<_> = prepared
+            .iter()
+            .map(|(job, prepared)| (job.state.clone(), job.title.clone(), prepared.clone()))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                (
+                    JobState::Queued,
+                    Some("Title of slow".to_owned()),
+                    Some("slow".to_owned())
+                ),
+                (JobState::Skipped, None, None),
+                (
+                    JobState::Queued,
+                    Some("Title of fast".to_owned()),
+                    Some("fast".to_owned())
+                ),
+                (
+                    JobState::Failed("User Error: private video".to_owned()),
+                    None,
+                    None
+                ),
+            ]
+        );
+    }
+}
@@ -1,119 +1,550 @@
-use async_compat::CompatExt;
-use dialoguer::Select;
 use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
-use qstring::QString;
-use reqwest;
+use part_file::PartFile;
+use reqwest::{header, StatusCode};
+use smol::io::AsyncWriteExt;
 use smol::{fs, io};
-use std::convert::{TryFrom, TryInto};
-use std::path::Path;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ui::TransferProgress;
 
 // make macros in `log.rs` available to the entire project.
-pub use colored;
 #[macro_use]
 mod log;
 
+mod archive;
+mod audio_extraction;
+mod downloader;
+mod embed;
+mod ffmpeg;
+mod filename;
+mod innertube;
 mod models;
+mod output_template;
+mod part_file;
+mod player;
+mod playlist;
+mod post_processing;
+mod post_processor;
+mod rate_limit;
+mod retry;
+mod segmented_download;
+mod subtitles;
+#[cfg(test)]
+mod test_dir;
+#[cfg(test)]
+mod test_server;
+mod ui;
 mod utils;
 pub mod wrapper;
+pub use archive::{ArchiveClaim, DownloadArchive};
+pub use audio_extraction::{AudioExtraction, AudioFormat, AudioQuality};
+pub use downloader::{Downloader, DownloaderBuilder};
+pub use filename::{FilenamePolicy, FilenameSanitizer};
+pub use innertube::{Innertube, PlayerClient, DEFAULT_PLAYER_CLIENTS};
+pub use log::{set_logger, LogLevel, Logger};
 pub use models::PlayerResponse;
+pub use models::VideoInfo;
 pub use models::YouDlError;
+pub use models::{FormatInfo, FormatSelector, Selection};
+pub use output_template::OutputTemplate;
+pub use playlist::{expand_url, PlaylistItems, PlaylistOptions};
+pub use post_processing::PostProcessing;
+pub use post_processor::RemuxFormat;
+pub use rate_limit::{RateLimiter, RateSchedule};
+pub use segmented_download::DownloadSettings;
+pub use subtitles::{SubtitleFormat, SubtitleOptions, SubtitleTrack};
+pub use ui::{NoProgress, ProgressEvent, ProgressObserver, Prompt};
+pub use utils::parse_file_size;
 
-pub async fn process_request(
-    url: &str,
+async fn download(
+    chosen_format: models::ChosenFormat,
     output_dir: &str,
-    progress_bar: ProgressBar,
+    output_template: &OutputTemplate,
+    post_processing: &PostProcessing,
+    subtitle_tracks: &[SubtitleTrack],
+    settings: &DownloadSettings,
+    observer: &Arc<dyn ProgressObserver>,
 ) -> Result<(), YouDlError> {
-    let video_id = utils::extract_video_id(url)?;
-    let player_response = get_player_response(video_id).await?;
-    let download_options = models::DownloadOptions::try_from(player_response)?;
-    let chosen_option = ask_preferred_file_format(download_options);
-    download(chosen_option, output_dir, progress_bar).await?;
+    let progress = &Arc::new(TransferProgress::new(Arc::clone(observer)));
+    let audio_extraction = post_processing.audio_extraction.as_ref();
+    let relative_path = render_output_path(&chosen_format, audio_extraction, output_template);
+    let final_path = Path::new(output_dir).join(&relative_path);
+    create_parent_dir(&final_path).await?;
+    // earlier downloads are kept, unfinished ones get resumed as their final file doesn't exist yet
+    let final_path = filename::avoid_collision(&final_path).await;
+    let path = match &post_processing.temp_dir {
+        Some(temp_dir) => {
+            let path = temp_dir.join(
+                final_path
+                    .strip_prefix(output_dir)
+                    .unwrap_or(&relative_path),
+            );
+            create_parent_dir(&path).await?;
+            path
+        }
+        None => final_path.clone(),
+    };
+
+    let intermediate_path = |option: &models::DownloadOption| {
+        intermediate_path(&path, option.itag, &option.file_extension)
+    };
+    let streams = match (&chosen_format, audio_extraction) {
+        (models::ChosenFormat::Single(download_option), None) => {
+            download_single(download_option, &path, settings, progress).await?;
+            Vec::new()
+        }
+        (models::ChosenFormat::Single(download_option), Some(_)) => {
+            let downloaded_path = intermediate_path(download_option);
+            download_single(download_option, &downloaded_path, settings, progress).await?;
+            vec![downloaded_path]
+        }
+        (models::ChosenFormat::Merge { video, audio }, _) => {
+            let (video_path, audio_path) = (intermediate_path(video), intermediate_path(audio));
+            let (video_transfer, audio_transfer) = futures::try_join!(
+                open_transfer(video, &video_path, settings),
+                open_transfer(audio, &audio_path, settings),
+            )?;
+            let total = video_transfer
+                .total()
+                .zip(audio_transfer.total())
+                .map(|(video_len, audio_len)| video_len + audio_len);
+            progress.start(total, video_transfer.offset() + audio_transfer.offset());
+            futures::try_join!(
+                run_transfer(video_transfer, settings, progress),
+                run_transfer(audio_transfer, settings, progress),
+            )?;
+            vec![video_path, audio_path]
+        }
+    };
+    progress.finish();
+
+    let mut context = post_processor::Context {
+        chosen_format: &chosen_format,
+        subtitle_tracks,
+        streams,
+        path,
+        sidecar_files: Vec::new(),
+        final_path,
+    };
+    post_processing
+        .pipeline()
+        .run(&mut context, observer.as_ref())
+        .await?;
+
+    observer.on_event(ProgressEvent::Finished {
+        title: chosen_format.title().to_owned(),
+        path: context.path,
+    });
     Ok(())
 }
 
-async fn get_player_response(video_id: &str) -> Result<PlayerResponse, YouDlError> {
-    let get_video_info_url = format!(
-        "https://www.youtube.com/get_video_info?video_id={}",
-        video_id
-    );
+async fn create_parent_dir(path: &Path) -> Result<(), YouDlError> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string())),
+        None => Ok(()),
+    }
+}
+
+async fn download_single(
+    download_option: &models::DownloadOption,
+    path: &Path,
+    settings: &DownloadSettings,
+    progress: &Arc<TransferProgress>,
+) -> Result<(), YouDlError> {
+    let transfer = open_transfer(download_option, path, settings).await?;
+    progress.start(transfer.total(), transfer.offset());
+    run_transfer(transfer, settings, progress).await
+}
 
-    let response_body = reqwest::get(&get_video_info_url)
-        .compat()
-        .await
-        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+// e.g. "<title>.mp4.f137.mp4", appended to the whole name: templates without `{ext}` can still have dots, as in "Mr. Robot"
+fn intermediate_path(path: &Path, itag: i32, file_extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".f{}.{}", itag, file_extension));
+    path.with_file_name(file_name)
+}
+
+fn render_output_path(
+    chosen_format: &models::ChosenFormat,
+    audio_extraction: Option<&AudioExtraction>,
+    output_template: &OutputTemplate,
+) -> PathBuf {
+    use output_template::Field;
 
-    let json_player_response = QString::from(response_body.as_str())
-        .get("player_response")
-        .map(|s| s.to_owned())
-        .ok_or(YouDlError::InvalidResponse(
-            "missing value for player_response".to_owned(),
-        ))?;
+    // merged files take their metadata from the video stream
+    let (download_option, file_extension) = match chosen_format {
+        models::ChosenFormat::Single(option) => match audio_extraction {
+            Some(audio_extraction) => (
+                option,
+                ffmpeg::extracted_audio_extension(
+                    audio_extraction.format,
+                    &models::codecs_from_mime_type(&option.mime_type).unwrap_or_default(),
+                ),
+            ),
+            None => (option, option.file_extension.as_str()),
+        },
+        models::ChosenFormat::Merge { video, audio } => (
+            video,
+            ffmpeg::merge_file_extension(&video.file_extension, &audio.file_extension),
+        ),
+    };
+    output_template.render(|field| match field {
+        Field::Title => Some(download_option.title.to_owned()),
+        Field::Id => Some(download_option.video_id.to_owned()),
+        Field::Itag => Some(chosen_format.itag()),
+        Field::Height => download_option.height.map(|height| height.to_string()),
+        Field::Ext => Some(file_extension.to_owned()),
+        Field::Uploader => download_option.uploader.to_owned(),
+        Field::UploadDate => download_option.upload_date.to_owned(),
+    })
+}
 
-    serde_json::from_str::<PlayerResponse>(&json_player_response)
-        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+// A stream on its way to disk: either over a single (possibly resumed) connection,
+// or split across multiple connections when its length is known upfront.
+enum Transfer {
+    Sequential(Box<OpenStream>),
+    Segmented {
+        url: String,
+        part_file: PartFile,
+        total: u64,
+        // by a previous run, first and last byte of each range
+        completed: HashSet<(u64, u64)>,
+    },
 }
 
-fn ask_preferred_file_format(
-    mut download_options: models::DownloadOptions,
-) -> models::DownloadOption {
-    select!(
-        "choose the file format for: {}",
-        download_options.get_title()
-    );
-    let chosen_index = Select::new()
-        .items(&download_options.0)
-        .default(0)
-        .interact()
-        .unwrap();
+impl Transfer {
+    fn offset(&self) -> u64 {
+        match self {
+            Transfer::Sequential(stream) => stream.offset,
+            Transfer::Segmented { completed, .. } => {
+                completed.iter().map(|(start, end)| end + 1 - start).sum()
+            }
+        }
+    }
 
-    let chosen = download_options.0.remove(chosen_index);
-    info!("chosen itag {} for: {}", chosen.itag, chosen.title);
-    chosen
+    fn total(&self) -> Option<u64> {
+        match self {
+            Transfer::Sequential(stream) => stream.total,
+            Transfer::Segmented { total, .. } => Some(*total),
+        }
+    }
 }
 
-async fn download(
-    download_option: models::DownloadOption,
-    output_dir: &str,
-    progress_bar: ProgressBar,
-) -> Result<(), YouDlError> {
-    let response = reqwest::get(&download_option.url)
-        .compat()
+async fn open_transfer(
+    download_option: &models::DownloadOption,
+    file_path: &Path,
+    settings: &DownloadSettings,
+) -> Result<Transfer, YouDlError> {
+    match download_option.content_length {
+        Some(total) if settings.segments > 1 => {
+            let part_file = PartFile::new(file_path);
+            let completed = match part_file.completed_ranges(total).await {
+                Some(completed) => completed,
+                None => {
+                    part_file.create_segmented(total).await?;
+                    HashSet::new()
+                }
+            };
+            Ok(Transfer::Segmented {
+                url: download_option.url.to_owned(),
+                part_file,
+                total,
+                completed,
+            })
+        }
+        _ => retry::with_retries(settings.retries, || {
+            open_stream(&download_option.url, file_path)
+        })
         .await
-        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+        .map(|stream| Transfer::Sequential(Box::new(stream))),
+    }
+}
 
-    initialize_progress_bar(&progress_bar, response.content_length().unwrap_or(u64::MAX));
+async fn run_transfer(
+    transfer: Transfer,
+    settings: &DownloadSettings,
+    progress: &Arc<TransferProgress>,
+) -> Result<(), YouDlError> {
+    match transfer {
+        Transfer::Sequential(stream) => write_stream(*stream, settings, progress.as_ref()).await,
+        Transfer::Segmented {
+            url,
+            part_file,
+            total,
+            completed,
+        } => {
+            segmented_download::download(&url, &part_file, total, completed, settings, progress)
+                .await?;
+            part_file.complete().await
+        }
+    }
+}
 
-    let file_name = [&*download_option.title, &*download_option.file_extension].join(".");
-    let mut output_file = fs::File::create(Path::new(output_dir).join(file_name))
-        .await
-        .map_err(|e| YouDlError::Application(e.to_string()))?;
+struct OpenStream {
+    url: String,
+    file_path: PathBuf,
+    response: reqwest::Response,
+    file: fs::File,
+    part_file: PartFile,
+    // bytes already downloaded by a previous run
+    offset: u64,
+    total: Option<u64>,
+}
+
+// Resumes a previous partial download with a "Range" request if possible,
+// and starts over if the server ignores the range or the remote file changed.
+async fn open_stream(url: &str, file_path: &Path) -> Result<OpenStream, YouDlError> {
+    let part_file = PartFile::new(file_path);
+    let mut response = None;
+    if let Some(progress) = part_file.progress().await {
+        let range_response = get_range(url, progress.downloaded).await?;
+        if range_response.status() == StatusCode::PARTIAL_CONTENT
+            && content_range_total(&range_response) == Some(progress.total)
+        {
+            return Ok(OpenStream {
+                url: url.to_owned(),
+                file_path: file_path.to_owned(),
+                response: range_response,
+                file: part_file.append().await?,
+                part_file,
+                offset: progress.downloaded,
+                total: Some(progress.total),
+            });
+        }
+        if range_response.status() == StatusCode::OK {
+            response = Some(range_response);
+        }
+    }
+
+    let response = match response {
+        Some(response) => response,
+        None => get(url).await?,
+    };
+    let total = response.content_length();
+    Ok(OpenStream {
+        url: url.to_owned(),
+        file_path: file_path.to_owned(),
+        response,
+        file: part_file.create(total).await?,
+        part_file,
+        offset: 0,
+        total,
+    })
+}
 
-    let mut stream = response.bytes_stream();
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
-        progress_bar.inc(chunk.len().try_into().expect("valid conversion"));
-        io::copy(&mut &*chunk, &mut output_file)
+// An interrupted stream is opened again from its last received byte, up to `fragment_retries` times.
+async fn write_stream(
+    mut stream: OpenStream,
+    settings: &DownloadSettings,
+    progress: &TransferProgress,
+) -> Result<(), YouDlError> {
+    let mut retried = 0;
+    loop {
+        let OpenStream {
+            url,
+            file_path,
+            response,
+            mut file,
+            part_file,
+            offset,
+            ..
+        } = stream;
+
+        let mut bytes_stream = response.bytes_stream();
+        let mut written = 0;
+        let mut result = Ok(());
+        while let Some(chunk) = bytes_stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    result = Err(retry::from_reqwest(e));
+                    break;
+                }
+            };
+            if let Some(rate_limiter) = &settings.rate_limiter {
+                rate_limiter.acquire(chunk.len() as u64).await;
+            }
+            progress.advance(chunk.len().try_into().expect("valid conversion"));
+            if let Err(e) = io::copy(&mut &*chunk, &mut file).await {
+                result = Err(YouDlError::Application(e.to_string()));
+                break;
+            }
+            written += chunk.len() as u64;
+        }
+
+        // whatever has been received is kept on disk, so that the next attempt (or run) can resume from there
+        file.flush()
             .await
             .map_err(|e| YouDlError::Application(e.to_string()))?;
+        match result {
+            Ok(()) => return part_file.complete().await,
+            Err(e) if e.is_retryable() && retried < settings.fragment_retries => {
+                retry::wait(&e, retried, settings.fragment_retries).await;
+                retried += 1;
+                drop(file);
+                stream =
+                    retry::with_retries(settings.retries, || open_stream(&url, &file_path)).await?;
+                // without a known length, or when the server ignores the range, the stream starts over
+                progress.discard((offset + written).saturating_sub(stream.offset));
+            }
+            Err(e) => return Err(e),
+        }
     }
+}
 
-    progress_bar.finish_with_message(&format!(
-        "Successfully downloaded: {}",
-        download_option.title
-    ));
-    Ok(())
+async fn get(url: &str) -> Result<reqwest::Response, YouDlError> {
+    retry::send(reqwest::Client::new().get(url))
+        .await?
+        .error_for_status()
+        .map_err(retry::from_reqwest)
+}
+
+async fn get_range(url: &str, from_byte: u64) -> Result<reqwest::Response, YouDlError> {
+    retry::send(
+        reqwest::Client::new()
+            .get(url)
+            .header(header::RANGE, format!("bytes={}-", from_byte)),
+    )
+    .await
 }
 
-fn initialize_progress_bar(progress_bar: &ProgressBar, len: u64) {
-    progress_bar.set_style(
-        ProgressStyle::default_bar()
-            .template("{prefix:.green} {bar:40.cyan/blue} {percent}% {wide_msg}")
-            .progress_chars("##-"),
-    );
-    progress_bar.set_prefix("Status:");
-    progress_bar.set_length(len);
+// e.g. "Content-Range: bytes 1000-1999/2000"
+fn content_range_total(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn names_the_streams_after_the_whole_file_name() {
+        assert_eq!(
+            intermediate_path(Path::new("Videos/Mr. Robot"), 137, "mp4"),
+            Path::new("Videos/Mr. Robot.f137.mp4")
+        );
+        assert_eq!(
+            intermediate_path(Path::new("Videos/Mr. Robot.mkv"), 251, "webm"),
+            Path::new("Videos/Mr. Robot.mkv.f251.webm")
+        );
+    }
+
+    #[test]
+    fn leaves_room_in_long_names_for_every_suffix() {
+        smol::block_on(async {
+            let dir = TempDir::new("long-name");
+            let title = format!("{}.mkv", "é".repeat(150));
+            let file_name = FilenameSanitizer::default().sanitize_component(&title);
+            fs::write(dir.join(&file_name), b"").await.unwrap();
+
+            // "<title> (1).mkv.f399.webm.part.len"
+            let final_path = filename::avoid_collision(&dir.join(&file_name)).await;
+            let part_file = PartFile::new(&intermediate_path(&final_path, 399, "webm"));
+            part_file.create(Some(10)).await.unwrap();
+        })
+    }
+
+    #[test]
+    fn resumes_partial_downloads_unless_the_remote_file_changed() {
+        let base_url = test_server::start(|request| {
+            let body: &[u8] = if request.path == "/changed" {
+                b"abcdefghijkl"
+            } else {
+                b"0123456789"
+            };
+            test_server::serve_ranges(request, body)
+        });
+
+        smol::block_on(async {
+            let dir = TempDir::new("resume");
+            let file_path = dir.join("video.mp4");
+            let interrupt_download = || async {
+                let mut file = PartFile::new(&file_path).create(Some(10)).await.unwrap();
+                file.write_all(b"0123").await.unwrap();
+                file.flush().await.unwrap();
+            };
+
+            interrupt_download().await;
+            let stream = open_stream(&format!("{}/video", base_url), &file_path)
+                .await
+                .unwrap();
+            assert_eq!((stream.offset, stream.total), (4, Some(10)));
+            write_stream(
+                stream,
+                &DownloadSettings::default(),
+                &TransferProgress::new(Arc::new(NoProgress)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(fs::read(&file_path).await.unwrap(), b"0123456789");
+
+            interrupt_download().await;
+            let stream = open_stream(&format!("{}/changed", base_url), &file_path)
+                .await
+                .unwrap();
+            assert_eq!((stream.offset, stream.total), (0, Some(12)));
+            write_stream(
+                stream,
+                &DownloadSettings::default(),
+                &TransferProgress::new(Arc::new(NoProgress)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(fs::read(&file_path).await.unwrap(), b"abcdefghijkl");
+        })
+    }
+
+    #[test]
+    fn resumes_an_interrupted_stream_from_its_last_byte() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted_requests = Arc::clone(&requests);
+        let base_url = test_server::start(move |request| {
+            let mut response = test_server::serve_ranges(request, b"0123456789");
+            // the first two connections drop, after 4 then 3 more bytes
+            match counted_requests.fetch_add(1, Ordering::SeqCst) {
+                0 => response.cut_after = Some(4),
+                1 => response.cut_after = Some(3),
+                _ => {}
+            }
+            response
+        });
+
+        smol::block_on(async {
+            let dir = TempDir::new("retry");
+            let file_path = dir.join("video.mp4");
+            let (sender, receiver) = smol::channel::unbounded();
+
+            let stream = open_stream(&format!("{}/video", base_url), &file_path)
+                .await
+                .unwrap();
+            let progress = TransferProgress::new(Arc::new(sender));
+            progress.start(stream.total, stream.offset);
+            write_stream(stream, &DownloadSettings::default(), &progress)
+                .await
+                .unwrap();
+            assert_eq!(fs::read(&file_path).await.unwrap(), b"0123456789");
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+            let mut last_event = None;
+            while let Ok(event) = receiver.try_recv() {
+                last_event = Some(event);
+            }
+            match last_event {
+                Some(ProgressEvent::Progress { bytes, .. }) => assert_eq!(bytes, 10),
+                event => panic!("unexpected event: {:?}", event),
+            }
+        })
+    }
 }
@@ -1,29 +1,88 @@
-#[macro_export]
-macro_rules! info {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::blue("Info:")), format_args!($($arg)*)));
+use crate::YouDlError;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Success,
+    Warn,
 }
 
-#[macro_export]
-macro_rules! warn {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::yellow("Warn:")), format_args!($($arg)*)));
+// Receives the messages of the library, e.g. to print them on a terminal or forward them to a log collector.
+pub trait Logger: Send + Sync {
+    fn log(&self, level: LogLevel, message: &str);
 }
 
-#[macro_export]
-macro_rules! select {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::bold($crate::colored::Colorize::yellow("Select:"))), format_args!($($arg)*)));
+static LOGGER: OnceLock<Box<dyn Logger>> = OnceLock::new();
+
+thread_local! {
+    // the logger of the `Downloader` whose future is being polled on this thread
+    static SCOPED_LOGGER: RefCell<Option<Arc<dyn Logger>>> = RefCell::new(None);
 }
 
-#[macro_export]
-macro_rules! success {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::bold($crate::colored::Colorize::green("Success:"))), format_args!($($arg)*)));
+// The logger of the messages that don't come from a `Downloader` with its own logger,
+// e.g. from playlist expansion or the youtube-dl wrapper. Messages are dropped until it is set,
+// which can only happen once per process.
+pub fn set_logger(logger: impl Logger + 'static) -> Result<(), YouDlError> {
+    LOGGER
+        .set(Box::new(logger))
+        .map_err(|_| YouDlError::Application("a logger is already set".to_owned()))
+}
+
+pub(crate) fn log(level: LogLevel, message: &str) {
+    let scoped_logger = SCOPED_LOGGER.with(|scoped_logger| scoped_logger.borrow().clone());
+    match scoped_logger {
+        Some(logger) => logger.log(level, message),
+        None => {
+            if let Some(logger) = LOGGER.get() {
+                logger.log(level, message);
+            }
+        }
+    }
+}
+
+// The logger `future` runs with, to be handed over to the tasks it spawns.
+pub(crate) fn scoped_logger() -> Option<Arc<dyn Logger>> {
+    SCOPED_LOGGER.with(|scoped_logger| scoped_logger.borrow().clone())
+}
+
+// Sends the messages of `future` to `logger` (the global one when `None`), wherever it is polled.
+pub(crate) fn scoped<F: Future>(logger: Option<Arc<dyn Logger>>, future: F) -> Scoped<F> {
+    Scoped {
+        logger,
+        future: Box::pin(future),
+    }
 }
 
-#[macro_export]
-macro_rules! error {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::bold($crate::colored::Colorize::red("Error:"))), format_args!($($arg)*)));
+pub(crate) struct Scoped<F> {
+    logger: Option<Arc<dyn Logger>>,
+    future: Pin<Box<F>>,
 }
 
-#[macro_export]
-macro_rules! failed {
-    ($($arg:tt)*) => (println!("{:>7} {}", ($crate::colored::Colorize::bold($crate::colored::Colorize::red("Failed:"))), format_args!($($arg)*)));
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let logger = self.logger.clone();
+        let outer_logger = SCOPED_LOGGER.with(|scoped_logger| scoped_logger.replace(logger));
+        let poll = self.future.as_mut().poll(cx);
+        SCOPED_LOGGER.with(|scoped_logger| scoped_logger.replace(outer_logger));
+        poll
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log::log($crate::log::LogLevel::Info, &format!($($arg)*)));
+}
+
+macro_rules! success {
+    ($($arg:tt)*) => ($crate::log::log($crate::log::LogLevel::Success, &format!($($arg)*)));
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log::log($crate::log::LogLevel::Warn, &format!($($arg)*)));
 }
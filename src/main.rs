@@ -1,9 +1,17 @@
-use futures::future;
+use config::Config;
+use futures::FutureExt;
 use indicatif::{MultiProgress, ProgressBar};
+use jobs::{JobQueue, Prepared};
+use std::io::IsTerminal;
 use std::process;
-use you_dl::{self, YouDlError};
+use std::sync::Arc;
+use terminal::{TerminalLogger, TerminalPrompt};
+use you_dl::{self, Downloader, PlaylistOptions, ProgressObserver, YouDlError};
 
+#[macro_use]
+mod terminal;
 mod config;
+mod jobs;
 
 fn main() {
     smol::block_on(async_main())
@@ -11,43 +19,178 @@ fn main() {
 
 async fn async_main() {
     let multi_bar = MultiProgress::new();
+    you_dl::set_logger(TerminalLogger).expect("no logger set yet");
     let config = config::parse().await.unwrap_or_else(|e| {
-        you_dl::error!("{}", e);
+        error!("{}", e);
         process::exit(1);
     });
-    let smol_tasks: Vec<_> = config
-        .video_urls
+    let video_urls = expand_urls(&config.video_urls, &config.playlist_options).await;
+    if config.dump_json {
+        dump_json(&video_urls, &config).await;
+        return;
+    }
+    if config.list_subs {
+        list_subs(&video_urls, &config).await;
+        return;
+    }
+
+    // shared by all the workers, which outlive this function's borrows
+    let downloader = Arc::new(downloader(&config));
+    // the prompts are over before the first progress bar is drawn
+    let jobs = jobs::prepare(&video_urls, &config, &downloader).await;
+    let config = Arc::new(config);
+    let pending = jobs
         .iter()
-        .map(|url| {
-            let url = url.to_owned();
-            let output_dir = config.output_dir.clone();
-            let progress_bar = multi_bar.add(ProgressBar::new(0));
-            smol::spawn(process_request(
-                url,
-                output_dir,
-                progress_bar,
-                config.use_wrapper,
-            ))
-        })
+        .filter(|(_, prepared)| prepared.is_some())
+        .count();
+    let queue = Arc::new(JobQueue::new(jobs));
+    // one bar per worker, whatever the number of videos
+    let bars = (0..config.concurrent_downloads.min(pending))
+        .map(|_| multi_bar.add(ProgressBar::new(0)))
         .collect();
+    let jobs = smol::spawn({
+        let queue = Arc::clone(&queue);
+        async move {
+            queue
+                .run(
+                    bars,
+                    Arc::new(move |prepared, observer| {
+                        download(
+                            prepared,
+                            Arc::clone(&config),
+                            Arc::clone(&downloader),
+                            observer,
+                        )
+                        .boxed()
+                    }),
+                )
+                .await
+        }
+    });
 
     multi_bar.join().unwrap(); // request the draw instructions from the remote progress bars
-    for result in future::join_all(smol_tasks).await {
-        if let Err(e) = result {
-            you_dl::failed!("{}", e)
+    jobs.await;
+    let jobs = Arc::try_unwrap(queue)
+        .ok()
+        .expect("workers are done")
+        .into_jobs();
+    match jobs.as_slice() {
+        // the progress bar already printed the failures of the jobs that got to a worker
+        [job] if pending == 0 => {
+            if let jobs::JobState::Failed(error) = &job.state {
+                failed!("{}", error)
+            }
+        }
+        [_] => {}
+        jobs => eprint!("\n{}", jobs::summary(jobs)),
+    }
+}
+
+fn downloader(config: &Config) -> Downloader {
+    let mut builder = Downloader::new()
+        .innertube(config.innertube.clone())
+        .output_dir(&config.output_dir)
+        .output_template(config.output_template.clone())
+        .post_processing(config.post_processing.clone())
+        .download_settings(config.download_settings.clone());
+    if let Some(format) = &config.format {
+        builder = builder.format(format.clone());
+    }
+    if let Some(archive) = &config.download_archive {
+        builder = builder.archive(archive.clone());
+    }
+    // otherwise the best format is chosen, e.g. in cron jobs or CI
+    if std::io::stdin().is_terminal() {
+        builder = builder.prompt(TerminalPrompt);
+    }
+    builder.build()
+}
+
+// playlists are replaced by their videos, so that each one gets its own task and progress bar
+async fn expand_urls(urls: &[String], playlist_options: &PlaylistOptions) -> Vec<String> {
+    let mut video_urls = Vec::new();
+    for url in urls {
+        match you_dl::expand_url(url, playlist_options).await {
+            Ok(mut expanded_urls) => video_urls.append(&mut expanded_urls),
+            Err(e) => failed!("{}", e),
         }
     }
+    video_urls
 }
 
-async fn process_request(
-    url: String,
-    output_dir: String,
-    progress_bar: ProgressBar,
-    use_wrapper: bool,
+// One JSON object per line and video, in the order of the URLs (see `VideoInfo` for the schema).
+async fn dump_json(video_urls: &[String], config: &Config) {
+    let downloader = downloader(config);
+    for url in video_urls {
+        let video_info = if config.use_wrapper {
+            you_dl::wrapper::get_video_info(url).await
+        } else {
+            downloader.fetch_info(url).await
+        };
+        match video_info {
+            Ok(video_info) => println!(
+                "{}",
+                serde_json::to_string(&video_info).expect("serializable video info")
+            ),
+            Err(e) => failed!("{}", e),
+        }
+    }
+}
+
+async fn list_subs(video_urls: &[String], config: &Config) {
+    let downloader = downloader(config);
+    for url in video_urls {
+        if config.use_wrapper {
+            match you_dl::wrapper::list_subtitles(url).await {
+                Ok(table) => print!("{}", table),
+                Err(e) => failed!("{}", e),
+            }
+            continue;
+        }
+        match downloader.fetch_info(url).await {
+            Ok(video_info) if video_info.subtitles.is_empty() => {
+                println!("{} has no subtitles", url)
+            }
+            Ok(video_info) => {
+                println!("Available subtitles for {}:", url);
+                println!("{:<10} {:<16} Name", "Language", "Kind");
+                for track in video_info.subtitles {
+                    let kind = if track.auto_generated {
+                        "auto-generated"
+                    } else {
+                        "subtitles"
+                    };
+                    println!("{:<10} {:<16} {}", track.language_code, kind, track.name);
+                }
+            }
+            Err(e) => failed!("{}", e),
+        }
+    }
+}
+
+async fn download(
+    prepared: Prepared,
+    config: Arc<Config>,
+    downloader: Arc<Downloader>,
+    observer: Arc<dyn ProgressObserver>,
 ) -> Result<(), YouDlError> {
-    if use_wrapper {
-        you_dl::wrapper::process_request(&url, &output_dir).await
-    } else {
-        you_dl::process_request(&url, &output_dir, progress_bar).await
+    match prepared {
+        Prepared::Native(video_info, selection) => {
+            downloader.download(&video_info, selection, observer).await
+        }
+        Prepared::Wrapper(video, itag) => {
+            you_dl::wrapper::download(&video, &itag, config.wrapper_options(), observer.as_ref())
+                .await
+        }
+        Prepared::Url(url) if config.use_wrapper => {
+            you_dl::wrapper::process_request(
+                &url,
+                config.wrapper_options(),
+                None,
+                observer.as_ref(),
+            )
+            .await
+        }
+        Prepared::Url(url) => downloader.process(&url, observer).await,
     }
 }
@@ -1,3 +1,5 @@
+use super::player_response::Format;
+use super::{FormatInfo, Selectable, Selection, StreamKind, VideoInfo};
 use crate::utils;
 use crate::{PlayerResponse, YouDlError};
 use std::convert::TryFrom;
@@ -6,31 +8,200 @@ use std::fmt;
 pub struct DownloadOption {
     pub video_id: String,
     pub title: String,
+    pub uploader: Option<String>,
+    // YYYYMMDD
+    pub upload_date: Option<String>,
+    pub description: Option<String>,
+    // the largest one
+    pub thumbnail_url: Option<String>,
     pub file_extension: String,
     pub itag: i32,
     pub url: String,
     pub file_size: String,
+    // not always provided by YouTube
+    pub content_length: Option<u64>,
+    // estimated from the bitrate and duration, when the content length is missing
+    pub approx_file_size: Option<u64>,
     pub mime_type: String,
-    pub width: i32,
-    pub height: i32,
+    pub bitrate: i32,
+    pub kind: StreamKind,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
 }
 
 impl fmt::Display for DownloadOption {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resolution = match (self.width, self.height) {
+            (Some(width), Some(height)) => format!("{:>4}x{:<7}", width, height),
+            _ => format!("{:<12}", "audio only"),
+        };
+        let kind = match self.kind {
+            StreamKind::Muxed => "",
+            StreamKind::VideoOnly => " (video only)",
+            StreamKind::AudioOnly => " (audio only)",
+        };
         write!(
             f,
-            "{:<6}{:<7}{:>4}x{:<7}{:<15}{}",
-            self.itag, self.file_extension, self.width, self.height, self.file_size, self.mime_type
+            "{:<6}{:<7}{}{:<15}{}{}",
+            self.itag, self.file_extension, resolution, self.file_size, self.mime_type, kind
         )
     }
 }
 
-// DownloadOptions always wraps at least one DownloadOption
+impl Selectable for DownloadOption {
+    fn itag(&self) -> String {
+        self.itag.to_string()
+    }
+
+    fn file_extension(&self) -> &str {
+        &self.file_extension
+    }
+
+    fn width(&self) -> Option<i32> {
+        self.width
+    }
+
+    fn height(&self) -> Option<i32> {
+        self.height
+    }
+
+    fn bitrate(&self) -> Option<i32> {
+        Some(self.bitrate)
+    }
+
+    fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
+#[cfg(test)]
+impl DownloadOption {
+    // a muxed mp4 stream, for the tests
+    pub fn fixture() -> Self {
+        DownloadOption {
+            video_id: "dQw4w9WgXcQ".to_owned(),
+            title: "Never Gonna Give You Up".to_owned(),
+            uploader: Some("Rick Astley".to_owned()),
+            upload_date: Some("20091025".to_owned()),
+            description: Some(String::new()),
+            thumbnail_url: None,
+            file_extension: "mp4".to_owned(),
+            itag: 18,
+            url: "https://example.com/18".to_owned(),
+            file_size: "10.0MiB".to_owned(),
+            content_length: None,
+            approx_file_size: None,
+            mime_type: "video/mp4".to_owned(),
+            bitrate: 500_000,
+            kind: StreamKind::Muxed,
+            width: Some(640),
+            height: Some(360),
+        }
+    }
+}
+
+// DownloadOptions always wraps at least one DownloadOption.
+// Muxed options come first, followed by the video-only and audio-only DASH streams.
 pub struct DownloadOptions(pub Vec<DownloadOption>);
 
 impl DownloadOptions {
     pub fn get_title(&self) -> &str {
-        &self.0.get(0).expect("at least one option available").title
+        &self.0.first().expect("at least one option available").title
+    }
+
+    pub fn into_chosen_format(self, selection: Selection) -> ChosenFormat {
+        let mut options: Vec<_> = self.0.into_iter().map(Some).collect();
+        let mut take = |index: usize| options[index].take().expect("distinct chosen options");
+        match selection {
+            Selection::Single(index) => ChosenFormat::Single(take(index)),
+            Selection::Pair(video, audio) => ChosenFormat::Merge {
+                video: take(video),
+                audio: take(audio),
+            },
+        }
+    }
+}
+
+// What ends up being downloaded: either a single stream,
+// or a video-only and an audio-only stream that get merged into one file.
+// there is a single one per download, it's not worth boxing the options
+#[allow(clippy::large_enum_variant)]
+pub enum ChosenFormat {
+    Single(DownloadOption),
+    Merge {
+        video: DownloadOption,
+        audio: DownloadOption,
+    },
+}
+
+impl ChosenFormat {
+    pub fn title(&self) -> &str {
+        match self {
+            ChosenFormat::Single(option) => &option.title,
+            ChosenFormat::Merge { video, .. } => &video.title,
+        }
+    }
+
+    // formatted like youtube-dl does, e.g. "22" or "137+140"
+    pub fn itag(&self) -> String {
+        match self {
+            ChosenFormat::Single(option) => option.itag.to_string(),
+            ChosenFormat::Merge { video, audio } => format!("{}+{}", video.itag, audio.itag),
+        }
+    }
+}
+
+impl ChosenFormat {
+    // The streams chosen among the formats of a video, e.g. from `Downloader::fetch_info`.
+    pub fn from_video_info(
+        video_info: &VideoInfo,
+        selection: Selection,
+    ) -> Result<Self, YouDlError> {
+        let option = |index: usize| {
+            let format = video_info.formats.get(index).ok_or_else(|| {
+                YouDlError::User(format!("no format #{} for: {}", index, video_info.title))
+            })?;
+            DownloadOption::from_video_info(video_info, format)
+        };
+        Ok(match selection {
+            Selection::Single(index) => ChosenFormat::Single(option(index)?),
+            Selection::Pair(video, audio) => ChosenFormat::Merge {
+                video: option(video)?,
+                audio: option(audio)?,
+            },
+        })
+    }
+}
+
+impl DownloadOption {
+    fn from_video_info(video_info: &VideoInfo, format: &FormatInfo) -> Result<Self, YouDlError> {
+        let itag = format.itag.parse().map_err(|_| {
+            YouDlError::Undownloadable(
+                video_info.title.to_owned(),
+                format!("format {} doesn't come from YouTube", format.itag),
+            )
+        })?;
+        Ok(DownloadOption {
+            video_id: video_info.id.to_owned(),
+            title: video_info.title.to_owned(),
+            uploader: video_info.uploader.to_owned(),
+            upload_date: video_info.upload_date.to_owned(),
+            description: video_info.description.to_owned(),
+            thumbnail_url: video_info.thumbnail.to_owned(),
+            file_extension: format.ext.to_owned(),
+            itag,
+            url: format.url.to_owned(),
+            file_size: utils::format_file_size(format.file_size().unwrap_or(0)),
+            content_length: format.filesize,
+            approx_file_size: format.filesize_approx,
+            mime_type: format.mime_type.to_owned().unwrap_or_default(),
+            bitrate: format
+                .bitrate
+                .map_or(0, |bitrate| bitrate.min(i32::MAX as u64) as i32),
+            kind: format.kind,
+            width: format.width,
+            height: format.height,
+        })
     }
 }
 
@@ -38,54 +209,118 @@ impl TryFrom<PlayerResponse> for DownloadOptions {
     type Error = YouDlError;
 
     fn try_from(player_response: PlayerResponse) -> Result<Self, Self::Error> {
-        let video_id = player_response.video_details.video_id;
-        let title = player_response.video_details.title.replace("+", " ");
+        let video_details = player_response.video_details;
+        let title = video_details.title.replace("+", " ");
+        let thumbnail_url = video_details
+            .thumbnail
+            .and_then(|thumbnail| {
+                thumbnail.thumbnails.into_iter().max_by_key(|thumbnail| {
+                    thumbnail.width.unwrap_or(0) * thumbnail.height.unwrap_or(0)
+                })
+            })
+            .map(|thumbnail| thumbnail.url);
+        let video_metadata = VideoMetadata {
+            video_id: video_details.video_id,
+            title,
+            uploader: video_details.author,
+            description: video_details.short_description,
+            thumbnail_url,
+            upload_date: player_response
+                .microformat
+                .and_then(|microformat| microformat.player_microformat_renderer.upload_date)
+                .map(|upload_date| upload_date.chars().take(10).filter(|&c| c != '-').collect()),
+        };
+        let title = &video_metadata.title;
         let streaming_data = player_response
             .streaming_data
             .ok_or(YouDlError::Undownloadable(
-                (&title).to_owned(),
+                title.to_owned(),
                 "missing value for streaming_data".to_owned(),
             ))?;
 
-        if streaming_data.formats.len() == 0 {
+        if streaming_data.formats.is_empty() && streaming_data.adaptive_formats.is_empty() {
             return Err(YouDlError::Undownloadable(
                 title.to_owned(),
                 "no options available to download".to_owned(),
             ));
         }
 
-        let mut download_options =
-            Vec::<DownloadOption>::with_capacity(streaming_data.formats.len());
-        for format in streaming_data.formats.into_iter() {
-            let file_extension = utils::get_file_extension(format.itag).unwrap_or_else(|| {
-                warn!("no file_extension found for itag {}", format.itag);
-                return "";
-            });
-            let url = format.url.ok_or(YouDlError::Undownloadable(
-                (&title).to_owned(),
-                "missing value for url".to_owned(),
-            ))?;
-            let approx_duration_ms = format.approx_duration_ms.parse::<i32>().map_err(|_| {
-                YouDlError::InvalidResponse(
-                    "approx_duration_ms cannot be parsed into integer".to_owned(),
-                )
-            })?;
-            let file_size_bytes = format.bitrate * (approx_duration_ms / 1000) / 8;
-            let file_size = utils::format_file_size(file_size_bytes);
-
-            download_options.push(DownloadOption {
-                video_id: (&video_id).to_owned(),
-                title: (&title).to_owned(),
-                file_extension: file_extension.to_owned(),
-                itag: format.itag,
-                url,
-                file_size,
-                mime_type: format.mime_type,
-                width: format.width,
-                height: format.height,
-            });
+        let muxed_formats = streaming_data
+            .formats
+            .into_iter()
+            .map(|format| (StreamKind::Muxed, format));
+        let adaptive_formats = streaming_data.adaptive_formats.into_iter().map(|format| {
+            if format.mime_type.starts_with("audio/") {
+                (StreamKind::AudioOnly, format)
+            } else {
+                (StreamKind::VideoOnly, format)
+            }
+        });
+
+        let mut download_options = Vec::<DownloadOption>::new();
+        for (kind, format) in muxed_formats.chain(adaptive_formats) {
+            download_options.push(to_download_option(&video_metadata, kind, format)?);
         }
 
         Ok(DownloadOptions(download_options))
     }
 }
+
+// the fields shared by every option of the same video
+struct VideoMetadata {
+    video_id: String,
+    title: String,
+    uploader: Option<String>,
+    upload_date: Option<String>,
+    description: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+fn to_download_option(
+    video_metadata: &VideoMetadata,
+    kind: StreamKind,
+    format: Format,
+) -> Result<DownloadOption, YouDlError> {
+    let file_extension = utils::get_file_extension(format.itag)
+        .or_else(|| utils::get_file_extension_from_mime_type(&format.mime_type))
+        .unwrap_or_else(|| {
+            warn!("no file_extension found for itag {}", format.itag);
+            ""
+        });
+    let url = format.url.ok_or(YouDlError::Undownloadable(
+        video_metadata.title.to_owned(),
+        "missing value for url".to_owned(),
+    ))?;
+    let approx_duration_ms = format.approx_duration_ms.parse::<u64>().map_err(|_| {
+        YouDlError::InvalidResponse("approx_duration_ms cannot be parsed into integer".to_owned())
+    })?;
+    let content_length = format
+        .content_length
+        .and_then(|content_length| content_length.parse::<u64>().ok());
+    // streams without a known length have their size estimated
+    let approx_file_size = match content_length {
+        Some(_) => None,
+        None => Some(format.bitrate.max(0) as u64 * (approx_duration_ms / 1000) / 8),
+    };
+    let file_size = utils::format_file_size(content_length.or(approx_file_size).unwrap_or(0));
+
+    Ok(DownloadOption {
+        video_id: video_metadata.video_id.to_owned(),
+        title: video_metadata.title.to_owned(),
+        uploader: video_metadata.uploader.to_owned(),
+        upload_date: video_metadata.upload_date.to_owned(),
+        description: video_metadata.description.to_owned(),
+        thumbnail_url: video_metadata.thumbnail_url.to_owned(),
+        file_extension: file_extension.to_owned(),
+        itag: format.itag,
+        url,
+        file_size,
+        content_length,
+        approx_file_size,
+        mime_type: format.mime_type,
+        bitrate: format.bitrate,
+        kind,
+        width: format.width,
+        height: format.height,
+    })
+}
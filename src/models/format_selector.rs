@@ -0,0 +1,416 @@
+use crate::YouDlError;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKind {
+    Muxed,
+    VideoOnly,
+    AudioOnly,
+}
+
+// Anything a `FormatSelector` can be evaluated against:
+// the native `DownloadOption`s as well as youtube-dl's `FileFormat`s.
+pub trait Selectable {
+    fn itag(&self) -> String;
+    fn file_extension(&self) -> &str;
+    fn width(&self) -> Option<i32>;
+    fn height(&self) -> Option<i32>;
+    fn bitrate(&self) -> Option<i32>;
+    fn kind(&self) -> StreamKind;
+}
+
+// The indexes of the chosen formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Single(usize),
+    // a video-only and an audio-only stream, to be merged together
+    Pair(usize, usize),
+}
+
+// A declarative way of choosing a file format without prompting the user.
+//
+// Grammar:
+//   selector    := alternative ( "/" alternative )*
+//   alternative := choice ( "+" choice )?
+//   choice      := term ( "," term )*
+//   term        := keyword | field operator value
+//   keyword     := "best" | "worst" | "bestvideo" | "worstvideo" | "bestaudio" | "worstaudio"
+//   field       := "itag" | "ext" | "width" | "height"
+//   operator    := "=" | "!=" | "<" | "<=" | ">" | ">="
+//
+// `best` and `worst` only consider formats with both video and audio,
+// `*video` and `*audio` only the video-only and audio-only ones; without a keyword any format may match.
+// Alternatives are tried from left to right; the first one matching at least one format wins.
+// Examples: `best`, `worst`, `itag=22`, `height<=720,ext=mp4`, `bestvideo,height<=1080+bestaudio/best`.
+#[derive(Debug, Clone)]
+pub struct FormatSelector {
+    raw: String,
+    alternatives: Vec<Alternative>,
+}
+
+#[derive(Debug, Clone)]
+struct Alternative {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Clone)]
+struct Choice {
+    preference: Preference,
+    kind: Option<StreamKind>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Preference {
+    Best,
+    Worst,
+}
+
+#[derive(Debug, Clone)]
+struct Filter {
+    field: Field,
+    operator: Operator,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Itag,
+    Ext,
+    Width,
+    Height,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// checked in order, so that two-chars operators take precedence over their one-char prefixes
+const OPERATORS: [(&str, Operator); 6] = [
+    ("<=", Operator::Le),
+    (">=", Operator::Ge),
+    ("!=", Operator::Ne),
+    ("<", Operator::Lt),
+    (">", Operator::Gt),
+    ("=", Operator::Eq),
+];
+
+impl FormatSelector {
+    pub fn select<T: Selectable>(&self, formats: &[T]) -> Option<Selection> {
+        self.alternatives
+            .iter()
+            .find_map(|alternative| alternative.select(formats))
+    }
+}
+
+impl Alternative {
+    fn select<T: Selectable>(&self, formats: &[T]) -> Option<Selection> {
+        match self.choices.as_slice() {
+            [single] => single.select(formats).map(Selection::Single),
+            [video, audio] => {
+                let (video, audio) = (video.select(formats)?, audio.select(formats)?);
+                // a format cannot be merged with itself
+                (video != audio).then_some(Selection::Pair(video, audio))
+            }
+            _ => unreachable!("alternatives are made of one or two choices"),
+        }
+    }
+}
+
+impl Choice {
+    fn select<T: Selectable>(&self, formats: &[T]) -> Option<usize> {
+        let candidates = formats
+            .iter()
+            .enumerate()
+            .filter(|(_, format)| self.kind.is_none_or(|kind| format.kind() == kind))
+            .filter(|(_, format)| self.filters.iter().all(|filter| filter.matches(*format)));
+
+        // formats are listed from worst to best, so the position breaks any remaining tie
+        let rank = |(index, format): &(usize, &T)| {
+            (
+                format.height().unwrap_or(0),
+                format.width().unwrap_or(0),
+                format.kind() == StreamKind::Muxed,
+                format.bitrate().unwrap_or(0),
+                *index,
+            )
+        };
+        let chosen = match self.preference {
+            Preference::Best => candidates.max_by_key(rank),
+            Preference::Worst => candidates.min_by_key(rank),
+        };
+        chosen.map(|(index, _)| index)
+    }
+}
+
+impl Filter {
+    fn matches<T: Selectable>(&self, format: &T) -> bool {
+        match self.field {
+            Field::Itag => self.compare_str(&format.itag()),
+            Field::Ext => self.compare_str(format.file_extension()),
+            Field::Width => self.compare_num(format.width()),
+            Field::Height => self.compare_num(format.height()),
+        }
+    }
+
+    fn compare_str(&self, actual: &str) -> bool {
+        match self.operator {
+            Operator::Eq => actual == self.value,
+            Operator::Ne => actual != self.value,
+            _ => false,
+        }
+    }
+
+    fn compare_num(&self, actual: Option<i32>) -> bool {
+        let (actual, expected) = match (actual, self.value.parse::<i32>()) {
+            (Some(actual), Ok(expected)) => (actual, expected),
+            _ => return false,
+        };
+        match self.operator {
+            Operator::Eq => actual == expected,
+            Operator::Ne => actual != expected,
+            Operator::Lt => actual < expected,
+            Operator::Le => actual <= expected,
+            Operator::Gt => actual > expected,
+            Operator::Ge => actual >= expected,
+        }
+    }
+}
+
+impl FromStr for FormatSelector {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let alternatives = s
+            .split('/')
+            .map(|alternative| parse_alternative(alternative.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|msg| {
+                YouDlError::User(format!("invalid format selector \"{}\": {}", s, msg))
+            })?;
+
+        Ok(FormatSelector {
+            raw: s.to_owned(),
+            alternatives,
+        })
+    }
+}
+
+impl Default for FormatSelector {
+    fn default() -> Self {
+        "best".parse().expect("valid format selector")
+    }
+}
+
+impl FormatSelector {
+    // the default when only the audio is wanted
+    pub fn best_audio() -> Self {
+        "bestaudio/best".parse().expect("valid format selector")
+    }
+}
+
+impl fmt::Display for FormatSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+fn parse_alternative(alternative: &str) -> Result<Alternative, String> {
+    let choices = alternative
+        .split('+')
+        .map(|choice| parse_choice(choice.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if choices.len() > 2 {
+        return Err(format!("too many formats to merge in \"{}\"", alternative));
+    }
+    Ok(Alternative { choices })
+}
+
+fn parse_choice(choice: &str) -> Result<Choice, String> {
+    if choice.is_empty() {
+        return Err("empty alternative".to_owned());
+    }
+
+    let mut preference = Preference::Best;
+    let mut kind = None;
+    let mut filters = Vec::new();
+    for term in choice.split(',').map(str::trim) {
+        let keyword = match term {
+            "best" => Some((Preference::Best, StreamKind::Muxed)),
+            "worst" => Some((Preference::Worst, StreamKind::Muxed)),
+            "bestvideo" => Some((Preference::Best, StreamKind::VideoOnly)),
+            "worstvideo" => Some((Preference::Worst, StreamKind::VideoOnly)),
+            "bestaudio" => Some((Preference::Best, StreamKind::AudioOnly)),
+            "worstaudio" => Some((Preference::Worst, StreamKind::AudioOnly)),
+            _ => None,
+        };
+        match keyword {
+            Some((keyword_preference, keyword_kind)) => {
+                preference = keyword_preference;
+                kind = Some(keyword_kind);
+            }
+            None => filters.push(parse_filter(term)?),
+        }
+    }
+    Ok(Choice {
+        preference,
+        kind,
+        filters,
+    })
+}
+
+fn parse_filter(term: &str) -> Result<Filter, String> {
+    let position = term
+        .find(|c| "<>!=".contains(c))
+        .ok_or(format!("missing operator in \"{}\"", term))?;
+    let (symbol, operator) = OPERATORS
+        .iter()
+        .find(|(symbol, _)| term[position..].starts_with(symbol))
+        .copied()
+        .ok_or(format!("invalid operator in \"{}\"", term))?;
+
+    let field = match term[..position].trim() {
+        "itag" => Field::Itag,
+        "ext" => Field::Ext,
+        "width" => Field::Width,
+        "height" => Field::Height,
+        other => return Err(format!("unknown field \"{}\"", other)),
+    };
+    let value = term[position + symbol.len()..].trim().to_owned();
+    if value.is_empty() {
+        return Err(format!("missing value in \"{}\"", term));
+    }
+
+    let is_numeric = matches!(field, Field::Width | Field::Height);
+    if is_numeric && value.parse::<i32>().is_err() {
+        return Err(format!("\"{}\" is not a number", value));
+    }
+    if !is_numeric && !matches!(operator, Operator::Eq | Operator::Ne) {
+        return Err(format!("operator \"{}\" is only valid for numbers", symbol));
+    }
+
+    Ok(Filter {
+        field,
+        operator,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestFormat(&'static str, &'static str, Option<i32>, i32, StreamKind);
+
+    impl Selectable for TestFormat {
+        fn itag(&self) -> String {
+            self.0.to_owned()
+        }
+        fn file_extension(&self) -> &str {
+            self.1
+        }
+        fn width(&self) -> Option<i32> {
+            self.2.map(|height| height * 16 / 9)
+        }
+        fn height(&self) -> Option<i32> {
+            self.2
+        }
+        fn bitrate(&self) -> Option<i32> {
+            Some(self.3)
+        }
+        fn kind(&self) -> StreamKind {
+            self.4
+        }
+    }
+
+    const FORMATS: [TestFormat; 8] = [
+        TestFormat("18", "mp4", Some(360), 500_000, StreamKind::Muxed),
+        TestFormat("43", "webm", Some(360), 400_000, StreamKind::Muxed),
+        TestFormat("22", "mp4", Some(720), 1_500_000, StreamKind::Muxed),
+        TestFormat("136", "mp4", Some(720), 2_300_000, StreamKind::VideoOnly),
+        TestFormat("137", "mp4", Some(1080), 4_300_000, StreamKind::VideoOnly),
+        TestFormat("140", "m4a", None, 130_000, StreamKind::AudioOnly),
+        TestFormat("139", "m4a", None, 49_000, StreamKind::AudioOnly),
+        TestFormat("251", "webm", None, 160_000, StreamKind::AudioOnly),
+    ];
+
+    fn select(selector: &str) -> Option<String> {
+        let itag = |index: usize| FORMATS[index].0;
+        selector
+            .parse::<FormatSelector>()
+            .unwrap()
+            .select(&FORMATS)
+            .map(|selection| match selection {
+                Selection::Single(index) => itag(index).to_owned(),
+                Selection::Pair(video, audio) => format!("{}+{}", itag(video), itag(audio)),
+            })
+    }
+
+    #[test]
+    fn selects_best_and_worst() {
+        assert_eq!(select("best").as_deref(), Some("22"));
+        assert_eq!(select("worst").as_deref(), Some("43"));
+        assert_eq!(select("bestvideo").as_deref(), Some("137"));
+        assert_eq!(select("bestaudio").as_deref(), Some("251"));
+        assert_eq!(select("worstaudio").as_deref(), Some("139"));
+    }
+
+    #[test]
+    fn selects_by_filters() {
+        assert_eq!(select("itag=18").as_deref(), Some("18"));
+        assert_eq!(select("itag=140").as_deref(), Some("140"));
+        assert_eq!(select("height<=480").as_deref(), Some("18"));
+        assert_eq!(select("height<=720").as_deref(), Some("22"));
+        assert_eq!(select("height<=480,ext=webm").as_deref(), Some("43"));
+        assert_eq!(select("ext!=mp4,worst").as_deref(), Some("43"));
+        assert_eq!(select("bestaudio,ext=m4a").as_deref(), Some("140"));
+        assert_eq!(select("height>1080"), None);
+    }
+
+    #[test]
+    fn selects_pairs_to_merge() {
+        assert_eq!(select("bestvideo+bestaudio").as_deref(), Some("137+251"));
+        assert_eq!(
+            select("bestvideo,height<=720+bestaudio,ext=m4a").as_deref(),
+            Some("136+140")
+        );
+        assert_eq!(select("itag=137+itag=139").as_deref(), Some("137+139"));
+        assert_eq!(select("bestvideo+itag=999"), None);
+    }
+
+    #[test]
+    fn falls_back_to_next_alternative() {
+        assert_eq!(select("height>1080/ext=webm").as_deref(), Some("43"));
+        assert_eq!(select("itag=999/itag=888/worst").as_deref(), Some("43"));
+        assert_eq!(
+            select("bestvideo,height>1080+bestaudio/best").as_deref(),
+            Some("22")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        let selectors = [
+            "",
+            "best/",
+            "size=10",
+            "height<=high",
+            "ext>mp4",
+            "itag",
+            "bestvideo+",
+            "bestvideo+bestaudio+best",
+        ];
+        for selector in &selectors {
+            assert!(selector.parse::<FormatSelector>().is_err(), "{}", selector);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 mod download_options;
+mod format_selector;
 mod player_response;
+mod video_info;
 mod you_dl_error;
-pub use download_options::{DownloadOption, DownloadOptions};
+pub use download_options::{ChosenFormat, DownloadOption, DownloadOptions};
+pub use format_selector::{FormatSelector, Selectable, Selection, StreamKind};
 pub use player_response::*;
+pub use video_info::{codecs_from_mime_type, FormatInfo, VideoInfo, SCHEMA_VERSION};
 pub use you_dl_error::YouDlError;
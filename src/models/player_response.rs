@@ -6,6 +6,8 @@ pub struct PlayerResponse {
     pub streaming_data: Option<StreamingData>,
     #[serde(rename(deserialize = "videoDetails"))]
     pub video_details: VideoDetails,
+    pub microformat: Option<Microformat>,
+    pub captions: Option<Captions>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -14,26 +16,106 @@ pub struct VideoDetails {
     pub video_id: String,
     #[serde(rename(deserialize = "title"))]
     pub title: String,
+    // the channel name
+    pub author: Option<String>,
+    #[serde(rename(deserialize = "shortDescription"))]
+    pub short_description: Option<String>,
+    pub thumbnail: Option<Thumbnails>,
+    #[serde(rename(deserialize = "lengthSeconds"))]
+    pub length_seconds: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Thumbnails {
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Captions {
+    #[serde(rename(deserialize = "playerCaptionsTracklistRenderer"))]
+    pub player_captions_tracklist_renderer: CaptionsTracklist,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CaptionsTracklist {
+    #[serde(rename(deserialize = "captionTracks"), default)]
+    pub caption_tracks: Vec<CaptionTrack>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptionTrack {
+    // the timedtext API URL, sometimes relative to YouTube's
+    #[serde(rename(deserialize = "baseUrl"))]
+    pub base_url: String,
+    pub name: Option<CaptionName>,
+    #[serde(rename(deserialize = "languageCode"))]
+    pub language_code: String,
+    // "asr" (automatic speech recognition) for auto-generated captions
+    pub kind: Option<String>,
+}
+
+// Either `{"simpleText": "English"}` or `{"runs": [{"text": "English"}]}`, depending on the client.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptionName {
+    #[serde(rename(deserialize = "simpleText"))]
+    pub simple_text: Option<String>,
+    #[serde(default)]
+    pub runs: Vec<CaptionNameRun>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptionNameRun {
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Microformat {
+    #[serde(rename(deserialize = "playerMicroformatRenderer"))]
+    pub player_microformat_renderer: PlayerMicroformatRenderer,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PlayerMicroformatRenderer {
+    // e.g. "2009-10-24", sometimes followed by the time
+    #[serde(rename(deserialize = "uploadDate"))]
+    pub upload_date: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StreamingData {
-    #[serde(rename(deserialize = "formats"))]
+    #[serde(rename(deserialize = "formats"), default)]
     pub formats: Vec<Format>,
+    // DASH streams: each one carries either video or audio, never both
+    #[serde(rename(deserialize = "adaptiveFormats"), default)]
+    pub adaptive_formats: Vec<Format>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Format {
     pub itag: i32,
+    // missing for protected streams, which come with a signature cipher instead
     pub url: Option<String>,
+    #[serde(rename(deserialize = "signatureCipher"), alias = "cipher")]
+    pub signature_cipher: Option<String>,
+    // missing for audio-only streams
     #[serde(rename(deserialize = "width"))]
-    pub width: i32,
+    pub width: Option<i32>,
     #[serde(rename(deserialize = "height"))]
-    pub height: i32,
+    pub height: Option<i32>,
     #[serde(rename(deserialize = "mimeType"))]
     pub mime_type: String,
     #[serde(rename(deserialize = "bitrate"))]
     pub bitrate: i32,
     #[serde(rename(deserialize = "approxDurationMs"))]
     pub approx_duration_ms: String,
+    #[serde(rename(deserialize = "contentLength"))]
+    pub content_length: Option<String>,
 }
@@ -0,0 +1,164 @@
+use super::{DownloadOptions, Selectable, StreamKind};
+use crate::utils;
+use crate::SubtitleTrack;
+use serde::Serialize;
+use std::fmt;
+
+// Bumped whenever a field gets renamed, removed or changes meaning; new fields may be added at any time.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// What `--dump-json` prints for each video, whichever backend is used.
+// Values that are unknown are `null`.
+// It holds everything needed to download the video later on, see `Downloader::download`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct VideoInfo {
+    pub schema_version: u32,
+    pub id: String,
+    pub title: String,
+    pub uploader: Option<String>,
+    // YYYYMMDD
+    pub upload_date: Option<String>,
+    pub description: Option<String>,
+    // the URL of the largest thumbnail
+    pub thumbnail: Option<String>,
+    pub formats: Vec<FormatInfo>,
+    // including auto-generated captions
+    pub subtitles: Vec<SubtitleTrack>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FormatInfo {
+    pub itag: String,
+    pub ext: String,
+    pub kind: StreamKind,
+    // e.g. "video/mp4; codecs=\"avc1.64001F, mp4a.40.2\"", not reported by youtube-dl
+    pub mime_type: Option<String>,
+    // e.g. "avc1.64001F, mp4a.40.2"
+    pub codecs: Option<String>,
+    // bits per second
+    pub bitrate: Option<u64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    // in bytes: either exact, or estimated from the bitrate and duration
+    pub filesize: Option<u64>,
+    pub filesize_approx: Option<u64>,
+    pub url: String,
+}
+
+impl From<&DownloadOptions> for VideoInfo {
+    fn from(download_options: &DownloadOptions) -> Self {
+        let first = download_options
+            .0
+            .first()
+            .expect("at least one option available");
+        VideoInfo {
+            schema_version: SCHEMA_VERSION,
+            id: first.video_id.to_owned(),
+            title: first.title.to_owned(),
+            uploader: first.uploader.to_owned(),
+            upload_date: first.upload_date.to_owned(),
+            description: first.description.to_owned(),
+            thumbnail: first.thumbnail_url.to_owned(),
+            formats: download_options
+                .0
+                .iter()
+                .map(|option| FormatInfo {
+                    itag: option.itag.to_string(),
+                    ext: option.file_extension.to_owned(),
+                    kind: option.kind,
+                    mime_type: Some(option.mime_type.to_owned()),
+                    codecs: codecs_from_mime_type(&option.mime_type),
+                    bitrate: Some(option.bitrate.max(0) as u64),
+                    width: option.width,
+                    height: option.height,
+                    filesize: option.content_length,
+                    filesize_approx: option.approx_file_size,
+                    url: option.url.to_owned(),
+                })
+                .collect(),
+            subtitles: Vec::new(),
+        }
+    }
+}
+
+impl FormatInfo {
+    pub fn file_size(&self) -> Option<u64> {
+        self.filesize.or(self.filesize_approx)
+    }
+}
+
+// Same columns as the options of the native backend, for prompts.
+impl fmt::Display for FormatInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resolution = match (self.width, self.height) {
+            (Some(width), Some(height)) => format!("{:>4}x{:<7}", width, height),
+            _ => format!("{:<12}", "audio only"),
+        };
+        let kind = match self.kind {
+            StreamKind::Muxed => "",
+            StreamKind::VideoOnly => " (video only)",
+            StreamKind::AudioOnly => " (audio only)",
+        };
+        let file_size = self.file_size().map(utils::format_file_size);
+        write!(
+            f,
+            "{:<6}{:<7}{}{:<15}{}{}",
+            self.itag,
+            self.ext,
+            resolution,
+            file_size.unwrap_or_default(),
+            self.mime_type
+                .as_deref()
+                .or(self.codecs.as_deref())
+                .unwrap_or_default(),
+            kind
+        )
+    }
+}
+
+impl Selectable for FormatInfo {
+    fn itag(&self) -> String {
+        self.itag.to_owned()
+    }
+
+    fn file_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn width(&self) -> Option<i32> {
+        self.width
+    }
+
+    fn height(&self) -> Option<i32> {
+        self.height
+    }
+
+    fn bitrate(&self) -> Option<i32> {
+        self.bitrate
+            .map(|bitrate| bitrate.min(i32::MAX as u64) as i32)
+    }
+
+    fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
+pub fn codecs_from_mime_type(mime_type: &str) -> Option<String> {
+    mime_type
+        .split_once("codecs=")
+        .map(|(_, codecs)| codecs.trim_matches('"').to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_codecs_from_mime_type() {
+        assert_eq!(
+            codecs_from_mime_type("video/mp4; codecs=\"avc1.64001F, mp4a.40.2\"").as_deref(),
+            Some("avc1.64001F, mp4a.40.2")
+        );
+        assert_eq!(codecs_from_mime_type("video/mp4"), None);
+    }
+}
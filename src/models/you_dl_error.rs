@@ -1,37 +1,52 @@
 use std::error;
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum YouDlError {
     YoutubeDl(String),
+    Ffmpeg(String),
     Application(String),
     InvalidURL(String),
+    // the title of the video, and why
     Undownloadable(String, String),
     User(String),
     InvalidResponse(String),
+    // a request that may succeed later: timeouts, dropped connections, 5xx and 429 responses,
+    // with the delay asked by the server in its "Retry-After" header
+    Transient(String, Option<Duration>),
+    // the step that failed, and why
+    PostProcessing(String, String),
 }
 
 impl fmt::Display for YouDlError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             YouDlError::YoutubeDl(msg) => write!(f, "youtube-dl Error: {}", msg.trim()),
+            YouDlError::Ffmpeg(msg) => write!(f, "ffmpeg Error: {}", msg.trim()),
             YouDlError::Application(msg) => write!(f, "Application Error: {}", msg.trim()),
             YouDlError::InvalidURL(url) => {
                 write!(f, "Invalid URL Error: {}", url)
             }
-            YouDlError::Undownloadable(video_id_or_title, msg) => {
-                write!(
-                    f,
-                    "Undownloadable Error for \"{}\": {}",
-                    video_id_or_title, msg
-                )
+            YouDlError::Undownloadable(title, msg) => {
+                write!(f, "Undownloadable Error for \"{}\": {}", title, msg)
             }
             YouDlError::User(msg) => write!(f, "User Error: {}", msg.trim()),
             YouDlError::InvalidResponse(msg) => {
                 write!(f, "Invalid Response Error: {}", msg.trim())
             }
+            YouDlError::Transient(msg, _) => write!(f, "Network Error: {}", msg.trim()),
+            YouDlError::PostProcessing(step, msg) => {
+                write!(f, "Post-processing Error ({}): {}", step, msg.trim())
+            }
         }
     }
 }
 
+impl YouDlError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, YouDlError::Transient(..))
+    }
+}
+
 impl error::Error for YouDlError {}
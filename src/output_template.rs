@@ -0,0 +1,253 @@
+use crate::filename::FilenameSanitizer;
+use crate::YouDlError;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// The values a template can refer to, written as "{name}".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Id,
+    // e.g. "22", or "137+140" for merged streams
+    Itag,
+    Height,
+    Ext,
+    Uploader,
+    // YYYYMMDD
+    UploadDate,
+}
+
+impl Field {
+    const ALL: [Field; 7] = [
+        Field::Title,
+        Field::Id,
+        Field::Itag,
+        Field::Height,
+        Field::Ext,
+        Field::Uploader,
+        Field::UploadDate,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Id => "id",
+            Field::Itag => "itag",
+            Field::Height => "height",
+            Field::Ext => "ext",
+            Field::Uploader => "uploader",
+            Field::UploadDate => "upload_date",
+        }
+    }
+
+    // the name of the same field in youtube-dl's output templates
+    fn youtube_dl_name(self) -> &'static str {
+        match self {
+            Field::Itag => "format_id",
+            field => field.name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+// Where a download gets written, relative to the output directory, e.g. "{uploader}/{title}.{ext}".
+// "/" separates subdirectories, "{{" and "}}" stand for literal braces.
+// The same template is translated for youtube-dl, so that both backends write the same paths.
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    raw: String,
+    parts: Vec<Part>,
+    sanitizer: FilenameSanitizer,
+}
+
+impl OutputTemplate {
+    pub fn with_sanitizer(mut self, sanitizer: FilenameSanitizer) -> Self {
+        self.sanitizer = sanitizer;
+        self
+    }
+
+    pub fn sanitizer(&self) -> FilenameSanitizer {
+        self.sanitizer
+    }
+
+    // Fields without a value (e.g. the height of an audio-only stream) are rendered as "NA", like youtube-dl does.
+    // Values can't introduce subdirectories, and every component of the path gets sanitized.
+    pub fn render(&self, value_of: impl Fn(Field) -> Option<String>) -> PathBuf {
+        let path: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.to_owned(),
+                Part::Field(field) => self
+                    .sanitizer
+                    .sanitize_value(&value_of(*field).unwrap_or_else(|| "NA".to_owned())),
+            })
+            .collect();
+        path.split('/')
+            .map(|component| self.sanitizer.sanitize_component(component))
+            .collect()
+    }
+
+    // e.g. "{uploader}/{title}.{ext}" becomes "%(uploader)s/%(title)s.%(ext)s"
+    pub fn to_youtube_dl(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.replace('%', "%%"),
+                Part::Field(field) => format!("%({})s", field.youtube_dl_name()),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            YouDlError::User(format!("invalid output template \"{}\": {}", s, reason))
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(invalid("unmatched \"{\"".to_owned())),
+                        }
+                    }
+                    let field = Field::ALL
+                        .iter()
+                        .find(|field| field.name() == name)
+                        .ok_or_else(|| invalid(format!("unknown field \"{{{}}}\"", name)))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(*field));
+                }
+                '}' => return Err(invalid("unmatched \"}\"".to_owned())),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if parts.is_empty() {
+            return Err(invalid("empty template".to_owned()));
+        }
+        // the rendered path always stays within the output directory
+        if s.starts_with('/') || s.split('/').any(|component| component == "..") {
+            return Err(invalid(
+                "paths must be relative to the output directory".to_owned(),
+            ));
+        }
+        Ok(OutputTemplate {
+            raw: s.to_owned(),
+            parts,
+            sanitizer: FilenameSanitizer::default(),
+        })
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Default for OutputTemplate {
+    fn default() -> Self {
+        "{title}.{ext}".parse().expect("valid default template")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::filename::FilenamePolicy;
+
+    fn value_of(field: Field) -> Option<String> {
+        match field {
+            Field::Title => Some("AC/DC - Thunderstruck".to_owned()),
+            Field::Id => Some("v2AC41dglnM".to_owned()),
+            Field::Itag => Some("137+140".to_owned()),
+            Field::Ext => Some("mp4".to_owned()),
+            Field::Uploader => Some("acdcVEVO".to_owned()),
+            Field::UploadDate => Some("20101014".to_owned()),
+            Field::Height => None,
+        }
+    }
+
+    #[test]
+    fn renders_fields_into_subdirectories() {
+        let template: OutputTemplate =
+            "{uploader}/{upload_date} {title} [{id}] {{{height}p}}.f{itag}.{ext}"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            template.render(value_of),
+            PathBuf::from(
+                "acdcVEVO/20101014 AC_DC - Thunderstruck [v2AC41dglnM] {NAp}.f137+140.mp4"
+            )
+        );
+        assert_eq!(
+            OutputTemplate::default().render(value_of),
+            PathBuf::from("AC_DC - Thunderstruck.mp4")
+        );
+
+        let sanitizer = FilenameSanitizer {
+            policy: FilenamePolicy::Windows,
+            max_bytes: 12 + FilenameSanitizer::SUFFIX_BYTES,
+        };
+        let template: OutputTemplate = "{upload_date}: {uploader}/{title}.{ext}".parse().unwrap();
+        assert_eq!(
+            template.with_sanitizer(sanitizer).render(value_of),
+            PathBuf::from("20101014_ ac/AC_DC - .mp4")
+        );
+    }
+
+    #[test]
+    fn translates_to_youtube_dl_templates() {
+        let template: OutputTemplate = "{uploader}/100% {title}-{itag}.{ext}".parse().unwrap();
+        assert_eq!(
+            template.to_youtube_dl(),
+            "%(uploader)s/100%% %(title)s-%(format_id)s.%(ext)s"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for invalid in &[
+            "",
+            "{name}.{ext}",
+            "{title",
+            "title}.{ext}",
+            "/tmp/{title}.{ext}",
+            "../{title}.{ext}",
+        ] {
+            assert!(invalid.parse::<OutputTemplate>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::YouDlError;
+use smol::io::AsyncWriteExt;
+use smol::{fs, io};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// Downloads are written to "<file>.part" and only renamed to "<file>" once complete.
+// The expected total length is kept next to it in "<file>.part.len",
+// so that an interrupted download can be resumed only if the remote file didn't change.
+// Segmented downloads don't complete in order: they list their completed ranges in "<file>.part.ranges".
+#[derive(Clone)]
+pub struct PartFile {
+    file_path: PathBuf,
+    part_path: PathBuf,
+    len_path: PathBuf,
+    ranges_path: PathBuf,
+}
+
+pub struct Progress {
+    pub downloaded: u64,
+    pub total: u64,
+}
+
+impl PartFile {
+    pub fn new(file_path: &Path) -> Self {
+        let with_suffix = |suffix: &str| {
+            let mut path = file_path.as_os_str().to_owned();
+            path.push(suffix);
+            PathBuf::from(path)
+        };
+        PartFile {
+            file_path: file_path.to_owned(),
+            part_path: with_suffix(".part"),
+            len_path: with_suffix(".part.len"),
+            ranges_path: with_suffix(".part.ranges"),
+        }
+    }
+
+    pub fn part_path(&self) -> &Path {
+        &self.part_path
+    }
+
+    // `None` if there's nothing to resume from.
+    pub async fn progress(&self) -> Option<Progress> {
+        // a segmented download has holes
+        if fs::metadata(&self.ranges_path).await.is_ok() {
+            return None;
+        }
+        let downloaded = fs::metadata(&self.part_path).await.ok()?.len();
+        let total = self.expected_total().await?;
+        if downloaded == 0 || downloaded > total {
+            return None;
+        }
+        Some(Progress { downloaded, total })
+    }
+
+    // The ranges (first and last byte) a previous segmented download of `total` bytes completed,
+    // `None` if there's nothing to resume from.
+    pub async fn completed_ranges(&self, total: u64) -> Option<HashSet<(u64, u64)>> {
+        let ranges = fs::read_to_string(&self.ranges_path).await.ok()?;
+        if self.expected_total().await? != total
+            || fs::metadata(&self.part_path).await.ok()?.len() != total
+        {
+            return None;
+        }
+        // the last line is incomplete if the previous run stopped while writing it
+        let complete_lines = &ranges[..ranges.rfind('\n').map_or(0, |index| index + 1)];
+        Some(
+            complete_lines
+                .lines()
+                .filter_map(|line| {
+                    let (start, end) = line.split_once('-')?;
+                    Some((start.parse().ok()?, end.parse().ok()?))
+                })
+                .collect(),
+        )
+    }
+
+    async fn expected_total(&self) -> Option<u64> {
+        fs::read_to_string(&self.len_path)
+            .await
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    // Starts over, truncating any previous partial download.
+    pub async fn create(&self, total: Option<u64>) -> Result<fs::File, YouDlError> {
+        remove_if_exists(&self.ranges_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        match total {
+            Some(total) => fs::write(&self.len_path, total.to_string()).await,
+            // without a known length there's no way to tell whether resuming is safe
+            None => remove_if_exists(&self.len_path).await,
+        }
+        .map_err(|e| YouDlError::Application(e.to_string()))?;
+
+        fs::File::create(&self.part_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+
+    // Starts a segmented download over: the part file gets its whole length right away,
+    // and each range is written at its offset.
+    pub async fn create_segmented(&self, total: u64) -> Result<(), YouDlError> {
+        self.create(Some(total))
+            .await?
+            .set_len(total)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        fs::write(&self.ranges_path, "")
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+
+    // Once the bytes from `start` to `end` (included) are written, so that they're not downloaded again.
+    pub async fn record_range(&self, start: u64, end: u64) -> Result<(), YouDlError> {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&self.ranges_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        file.write_all(format!("{}-{}\n", start, end).as_bytes())
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        file.flush()
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+
+    pub async fn append(&self) -> Result<fs::File, YouDlError> {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&self.part_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+
+    pub async fn complete(&self) -> Result<(), YouDlError> {
+        fs::rename(&self.part_path, &self.file_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        remove_if_exists(&self.len_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        remove_if_exists(&self.ranges_path)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use smol::io::AsyncWriteExt;
+
+    #[test]
+    fn resumes_only_matching_partial_downloads() {
+        smol::block_on(async {
+            let dir = TempDir::new("part-file");
+            let part_file = PartFile::new(&dir.join("video.mp4"));
+            assert!(part_file.progress().await.is_none());
+
+            let mut file = part_file.create(Some(10)).await.unwrap();
+            file.write_all(b"0123").await.unwrap();
+            file.flush().await.unwrap();
+            let progress = part_file.progress().await.unwrap();
+            assert_eq!((progress.downloaded, progress.total), (4, 10));
+
+            let mut file = part_file.append().await.unwrap();
+            file.write_all(b"456789").await.unwrap();
+            file.flush().await.unwrap();
+            part_file.complete().await.unwrap();
+            assert_eq!(
+                fs::read(dir.join("video.mp4")).await.unwrap(),
+                b"0123456789"
+            );
+            assert!(part_file.progress().await.is_none());
+
+            let mut file = part_file.create(None).await.unwrap();
+            file.write_all(b"0123").await.unwrap();
+            file.flush().await.unwrap();
+            assert!(part_file.progress().await.is_none());
+        })
+    }
+}
@@ -0,0 +1,25 @@
+var _yt_player={};(function(g){var window=this;/*
+
+ Copyright The Closure Library Authors.
+ SPDX-License-Identifier: Apache-2.0
+*/
+'use strict';var ba,da,ea,fa,ha;
+var tB=function(a){return a&&a.length?"{"+a+"}":""};
+var uB={Xw:function(a,b){var c=a[0];a[0]=a[b%a.length];a[b%a.length]=c},
+fY:function(a){a.reverse()},
+dG:function(a,b){a.splice(0,b)}};
+var vB=function(a){a=a.split("");uB.Xw(a,49);uB.dG(a,1);uB.fY(a,34);uB.Xw(a,3);uB.dG(a,2);uB.fY(a,7);return a.join("")};
+g.wB=function(a,b){if(a.s){var c=a.sp||"signature";b.set(c,encodeURIComponent(vB(decodeURIComponent(a.s))))}};
+var Cpa=function(a){var b=a.split(""),c=[function(d,e){e=(e%d.length+d.length)%d.length;d.splice(-e).reverse().forEach(function(f){d.unshift(f)})},
+-1467262648,function(d,e){d.push(e)},b,"1Hx2",null,function(d){for(var e=d.length;e;)d.push(d.splice(--e,1)[0])},
+function(d,e){for(e=(e%d.length+d.length)%d.length;e--;)d.unshift(d.pop())},
+function(d,e,f){var h=f.length;d.forEach(function(l,m,n){this.push(n[m]=f[(f.indexOf(l)-f.indexOf(this[m])+m+h--)%f.length])},e.split(""))},
+function(){for(var d=64,e=[];++d-e.length-32;){switch(d){case 58:d-=14;case 91:case 92:case 93:continue;case 123:d=47;case 94:case 95:case 96:continue;case 46:d=95;default:e.push(String.fromCharCode(d))}}return e},
+function(d,e){e=(e%d.length+d.length)%d.length;var f=d[0];d[0]=d[e];d[e]=f},
+function(d,e){e=(e%d.length+d.length)%d.length;d.splice(0,1,d.splice(e,1,d[0])[0])},
+"pw9K",1923];c[5]=c;try{c[2](c[5],c[9]()),c[8](c[3],c[4],c[14]),c[10](c[3],c[1]),c[6](c[3]),c[11](c[3],c[13]),c[7](c[3],-37),
+c[0](c[3],c[12].length),c[8](c[3],c[12],c[5][14])}catch(d){return"enhanced_except_"+a}return b.join("")};
+var Bpa=[Cpa];
+g.yB=function(a){var b;(b=a.get("n"))&&(b=Bpa[0](b),a.set("n",b),Bpa.length||iha(""))};
+var xB={url:"/s/player/8f3e1c2a/player_ias.vflset/en_US/base.js",label:"}"};
+})(_yt_player);
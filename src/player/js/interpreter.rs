@@ -0,0 +1,1083 @@
+use super::parser::{Expr, FunctionDef, Stmt};
+use super::JsError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Guards against functions that never return, e.g. if the player relies on something that isn't supported.
+const MAX_STEPS: usize = 1_000_000;
+// Guards against huge allocations, e.g. from setting the `length` of an array or an index far past its end.
+const MAX_ARRAY_LEN: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Object(Rc<RefCell<HashMap<String, Value>>>),
+    Function(Rc<Closure>),
+    // the few global objects functions rely on, e.g. `String.fromCharCode`
+    Global(&'static str),
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    def: Rc<FunctionDef>,
+    scope: Rc<Scope>,
+}
+
+// Blocks don't have their own scope: `let` and `const` are treated like `var`.
+#[derive(Debug, Default)]
+struct Scope {
+    variables: RefCell<HashMap<String, Value>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    fn lookup(&self, name: &str) -> Option<Value> {
+        match self.variables.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.lookup(name)),
+        }
+    }
+
+    // Assigns to the closest scope declaring the variable, or declares it globally like non-strict JS does.
+    fn assign(&self, name: &str, value: Value) {
+        if self.variables.borrow().contains_key(name) || self.parent.is_none() {
+            self.variables.borrow_mut().insert(name.to_owned(), value);
+            return;
+        }
+        if let Some(parent) = &self.parent {
+            parent.assign(name, value);
+        }
+    }
+
+    fn declare(&self, name: &str, value: Value) {
+        self.variables.borrow_mut().insert(name.to_owned(), value);
+    }
+}
+
+// How a statement completes, besides normally.
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// Exceptions can be caught by the script, errors can't: they mean the script is not supported.
+pub enum Abrupt {
+    Throw(Value),
+    Error(JsError),
+}
+
+impl From<JsError> for Abrupt {
+    fn from(error: JsError) -> Self {
+        Abrupt::Error(error)
+    }
+}
+
+type Completion<T> = Result<T, Abrupt>;
+
+fn type_error(message: String) -> Abrupt {
+    Abrupt::Throw(Value::Str(format!("TypeError: {}", message).into()))
+}
+
+pub struct Interpreter {
+    global: Rc<Scope>,
+    steps: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            global: Rc::new(Scope::default()),
+            steps: 0,
+        }
+    }
+
+    pub fn call_function(
+        &mut self,
+        def: &Rc<FunctionDef>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, JsError> {
+        let closure = Rc::new(Closure {
+            def: Rc::clone(def),
+            scope: Rc::clone(&self.global),
+        });
+        match self.call(&closure, Value::Undefined, arguments) {
+            Ok(value) => Ok(value),
+            Err(Abrupt::Throw(value)) => Err(JsError(format!(
+                "uncaught exception: {}",
+                to_string(&value)
+            ))),
+            Err(Abrupt::Error(error)) => Err(error),
+        }
+    }
+
+    fn step(&mut self) -> Completion<()> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(JsError("too many steps".to_owned()).into());
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        closure: &Rc<Closure>,
+        this: Value,
+        arguments: Vec<Value>,
+    ) -> Completion<Value> {
+        let scope = Rc::new(Scope {
+            variables: RefCell::default(),
+            parent: Some(Rc::clone(&closure.scope)),
+        });
+        scope.declare("this", this);
+        scope.declare(
+            "arguments",
+            Value::Array(Rc::new(RefCell::new(arguments.clone()))),
+        );
+        let mut arguments = arguments.into_iter();
+        for param in &closure.def.params {
+            scope.declare(param, arguments.next().unwrap_or(Value::Undefined));
+        }
+        hoist(&closure.def.body, &scope);
+
+        match self.execute_block(&closure.def.body, &scope)? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Undefined),
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        callee: &Value,
+        this: Value,
+        arguments: Vec<Value>,
+    ) -> Completion<Value> {
+        match callee {
+            Value::Function(closure) => self.call(closure, this, arguments),
+            Value::Global("String") => Ok(Value::Str(
+                arguments.first().map_or(String::new(), to_string).into(),
+            )),
+            Value::Global("Number") => Ok(Value::Number(arguments.first().map_or(0.0, to_number))),
+            _ => Err(type_error(format!(
+                "{} is not a function",
+                to_string(callee)
+            ))),
+        }
+    }
+
+    fn execute_block(&mut self, statements: &[Stmt], scope: &Rc<Scope>) -> Completion<Flow> {
+        for statement in statements {
+            match self.execute(statement, scope)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn execute(&mut self, statement: &Stmt, scope: &Rc<Scope>) -> Completion<Flow> {
+        self.step()?;
+        match statement {
+            Stmt::Var(declarations) => {
+                for (name, init) in declarations {
+                    if let Some(init) = init {
+                        let value = self.evaluate(init, scope)?;
+                        scope.declare(name, value);
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            // already hoisted
+            Stmt::Function(..) | Stmt::Empty => Ok(Flow::Normal),
+            Stmt::Expr(expression) => self.evaluate(expression, scope).map(|_| Flow::Normal),
+            Stmt::Block(statements) => self.execute_block(statements, scope),
+            Stmt::If(test, consequent, alternate) => {
+                if to_bool(&self.evaluate(test, scope)?) {
+                    self.execute(consequent, scope)
+                } else if let Some(alternate) = alternate {
+                    self.execute(alternate, scope)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Stmt::For {
+                init,
+                test,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.execute(init, scope)?;
+                }
+                loop {
+                    if let Some(test) = test {
+                        if !to_bool(&self.evaluate(test, scope)?) {
+                            return Ok(Flow::Normal);
+                        }
+                    }
+                    match self.execute(body, scope)? {
+                        Flow::Break => return Ok(Flow::Normal),
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                    if let Some(update) = update {
+                        self.evaluate(update, scope)?;
+                    }
+                }
+            }
+            Stmt::While(test, body) => {
+                while to_bool(&self.evaluate(test, scope)?) {
+                    match self.execute(body, scope)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::DoWhile(body, test) => {
+                loop {
+                    match self.execute(body, scope)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                    if !to_bool(&self.evaluate(test, scope)?) {
+                        break;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::Switch(discriminant, cases) => {
+                let discriminant = self.evaluate(discriminant, scope)?;
+                let mut matched = None;
+                for (index, (test, _)) in cases.iter().enumerate() {
+                    if let Some(test) = test {
+                        if strict_equals(&discriminant, &self.evaluate(test, scope)?) {
+                            matched = Some(index);
+                            break;
+                        }
+                    }
+                }
+                let start = matched.or_else(|| cases.iter().position(|(test, _)| test.is_none()));
+                if let Some(start) = start {
+                    // cases fall through until a "break"
+                    for (_, body) in &cases[start..] {
+                        match self.execute_block(body, scope)? {
+                            Flow::Normal => {}
+                            Flow::Break => break,
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::Break => Ok(Flow::Break),
+            Stmt::Continue => Ok(Flow::Continue),
+            Stmt::Return(argument) => {
+                let value = match argument {
+                    Some(argument) => self.evaluate(argument, scope)?,
+                    None => Value::Undefined,
+                };
+                Ok(Flow::Return(value))
+            }
+            Stmt::Throw(argument) => Err(Abrupt::Throw(self.evaluate(argument, scope)?)),
+            Stmt::Try {
+                block,
+                catch,
+                finally,
+            } => {
+                let mut completion = self.execute_block(block, scope);
+                if let (Err(Abrupt::Throw(exception)), Some((param, handler))) =
+                    (&completion, catch)
+                {
+                    if let Some(param) = param {
+                        scope.declare(param, exception.clone());
+                    }
+                    completion = self.execute_block(handler, scope);
+                }
+                if let Some(finally) = finally {
+                    match self.execute_block(finally, scope)? {
+                        Flow::Normal => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                completion
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expression: &Expr, scope: &Rc<Scope>) -> Completion<Value> {
+        self.step()?;
+        match expression {
+            Expr::Number(number) => Ok(Value::Number(*number)),
+            Expr::Str(string) => Ok(Value::Str(string.as_str().into())),
+            Expr::Ident(name) => lookup(name, scope),
+            Expr::This => Ok(scope.lookup("this").unwrap_or(Value::Undefined)),
+            Expr::Array(elements) => {
+                let values = elements
+                    .iter()
+                    .map(|element| self.evaluate(element, scope))
+                    .collect::<Completion<Vec<_>>>()?;
+                Ok(Value::Array(Rc::new(RefCell::new(values))))
+            }
+            Expr::Object(properties) => {
+                let mut object = HashMap::new();
+                for (key, value) in properties {
+                    object.insert(key.to_owned(), self.evaluate(value, scope)?);
+                }
+                Ok(Value::Object(Rc::new(RefCell::new(object))))
+            }
+            Expr::Function(def) => Ok(Value::Function(Rc::new(Closure {
+                def: Rc::clone(def),
+                scope: Rc::clone(scope),
+            }))),
+            Expr::Member(object, name) => {
+                let object = self.evaluate(object, scope)?;
+                get_property(&object, name)
+            }
+            Expr::Index(object, index) => {
+                let object = self.evaluate(object, scope)?;
+                let key = to_string(&self.evaluate(index, scope)?);
+                get_property(&object, &key)
+            }
+            Expr::Call(callee, arguments) => self.evaluate_call(callee, arguments, scope),
+            Expr::New(callee) => {
+                Err(JsError(format!("unsupported constructor {:?}", callee)).into())
+            }
+            Expr::Unary("typeof", argument) => {
+                // unlike other references, undeclared variables are fine here
+                let value = match &**argument {
+                    Expr::Ident(name) => lookup(name, scope).unwrap_or(Value::Undefined),
+                    argument => self.evaluate(argument, scope)?,
+                };
+                Ok(Value::Str(type_of(&value).into()))
+            }
+            Expr::Unary(operator, argument) => {
+                let value = self.evaluate(argument, scope)?;
+                Ok(match *operator {
+                    "!" => Value::Bool(!to_bool(&value)),
+                    "-" => Value::Number(-to_number(&value)),
+                    "+" => Value::Number(to_number(&value)),
+                    "~" => Value::Number(!to_int32(&value) as f64),
+                    "void" => Value::Undefined,
+                    _ => return Err(JsError(format!("unsupported operator {}", operator)).into()),
+                })
+            }
+            Expr::Update(operator, prefix, target) => {
+                let old = to_number(&self.evaluate(target, scope)?);
+                let new = if *operator == "++" {
+                    old + 1.0
+                } else {
+                    old - 1.0
+                };
+                self.assign(target, Value::Number(new), scope)?;
+                Ok(Value::Number(if *prefix { new } else { old }))
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = self.evaluate(left, scope)?;
+                let right = self.evaluate(right, scope)?;
+                binary(operator, &left, &right)
+            }
+            Expr::Logical(operator, left, right) => {
+                let left = self.evaluate(left, scope)?;
+                if to_bool(&left) == (*operator == "&&") {
+                    self.evaluate(right, scope)
+                } else {
+                    Ok(left)
+                }
+            }
+            Expr::Assign(operator, target, value) => {
+                let value = match operator.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    Some(binary_operator) => {
+                        let current = self.evaluate(target, scope)?;
+                        let value = self.evaluate(value, scope)?;
+                        binary(binary_operator, &current, &value)?
+                    }
+                    None => self.evaluate(value, scope)?,
+                };
+                self.assign(target, value.clone(), scope)?;
+                Ok(value)
+            }
+            Expr::Conditional(test, consequent, alternate) => {
+                if to_bool(&self.evaluate(test, scope)?) {
+                    self.evaluate(consequent, scope)
+                } else {
+                    self.evaluate(alternate, scope)
+                }
+            }
+            Expr::Sequence(expressions) => {
+                let mut value = Value::Undefined;
+                for expression in expressions {
+                    value = self.evaluate(expression, scope)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value, scope: &Rc<Scope>) -> Completion<()> {
+        match target {
+            Expr::Ident(name) => {
+                scope.assign(name, value);
+                Ok(())
+            }
+            Expr::Member(object, name) => {
+                let object = self.evaluate(object, scope)?;
+                set_property(&object, name, value)
+            }
+            Expr::Index(object, index) => {
+                let object = self.evaluate(object, scope)?;
+                let key = to_string(&self.evaluate(index, scope)?);
+                set_property(&object, &key, value)
+            }
+            _ => Err(JsError("invalid assignment target".to_owned()).into()),
+        }
+    }
+
+    // Methods of arrays, strings and global objects are implemented natively, as they are called.
+    fn evaluate_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        scope: &Rc<Scope>,
+    ) -> Completion<Value> {
+        let (this, method) = match callee {
+            Expr::Member(object, name) => (self.evaluate(object, scope)?, Some(name.to_owned())),
+            Expr::Index(object, index) => {
+                let object = self.evaluate(object, scope)?;
+                let key = to_string(&self.evaluate(index, scope)?);
+                (object, Some(key))
+            }
+            _ => (Value::Undefined, None),
+        };
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument, scope))
+            .collect::<Completion<Vec<_>>>()?;
+
+        let method = match method {
+            Some(method) => method,
+            None => {
+                let callee = self.evaluate(callee, scope)?;
+                return self.call_value(&callee, Value::Undefined, arguments);
+            }
+        };
+        match &this {
+            Value::Array(array) => self.call_array_method(array, &method, arguments),
+            Value::Str(string) => call_string_method(string, &method, &arguments),
+            Value::Global(global) => call_global_method(global, &method, &arguments),
+            Value::Function(closure) if method == "call" => {
+                let mut arguments = arguments.into_iter();
+                let this = arguments.next().unwrap_or(Value::Undefined);
+                self.call(closure, this, arguments.collect())
+            }
+            Value::Function(closure) if method == "apply" => {
+                let mut arguments = arguments.into_iter();
+                let this = arguments.next().unwrap_or(Value::Undefined);
+                let arguments = match arguments.next() {
+                    Some(Value::Array(array)) => array.borrow().clone(),
+                    _ => Vec::new(),
+                };
+                self.call(closure, this, arguments)
+            }
+            _ => {
+                let function = get_property(&this, &method)?;
+                self.call_value(&function, this, arguments)
+            }
+        }
+    }
+
+    fn call_array_method(
+        &mut self,
+        array: &Rc<RefCell<Vec<Value>>>,
+        method: &str,
+        arguments: Vec<Value>,
+    ) -> Completion<Value> {
+        let argument = |index: usize| arguments.get(index).cloned().unwrap_or(Value::Undefined);
+        let len = array.borrow().len();
+        match method {
+            "push" => {
+                check_array_len(len + arguments.len())?;
+                let mut array = array.borrow_mut();
+                array.extend(arguments);
+                Ok(Value::Number(array.len() as f64))
+            }
+            "pop" => Ok(array.borrow_mut().pop().unwrap_or(Value::Undefined)),
+            "shift" if len == 0 => Ok(Value::Undefined),
+            "shift" => Ok(array.borrow_mut().remove(0)),
+            "unshift" => {
+                check_array_len(len + arguments.len())?;
+                let mut array = array.borrow_mut();
+                array.splice(0..0, arguments);
+                Ok(Value::Number(array.len() as f64))
+            }
+            "splice" => {
+                let start = relative_index(&argument(0), len);
+                let delete_count = match arguments.get(1) {
+                    None => len - start,
+                    Some(count) => (to_integer(count).max(0.0) as usize).min(len - start),
+                };
+                check_array_len(len - delete_count + arguments.len().saturating_sub(2))?;
+                let removed: Vec<_> = array
+                    .borrow_mut()
+                    .splice(start..start + delete_count, arguments.into_iter().skip(2))
+                    .collect();
+                Ok(Value::Array(Rc::new(RefCell::new(removed))))
+            }
+            "slice" => {
+                let start = relative_index(&argument(0), len);
+                let end = match argument(1) {
+                    Value::Undefined => len,
+                    end => relative_index(&end, len),
+                };
+                let slice = array.borrow()[start..end.max(start)].to_vec();
+                Ok(Value::Array(Rc::new(RefCell::new(slice))))
+            }
+            "reverse" => {
+                array.borrow_mut().reverse();
+                Ok(Value::Array(Rc::clone(array)))
+            }
+            "join" => {
+                let separator = match argument(0) {
+                    Value::Undefined => ",".to_owned(),
+                    separator => to_string(&separator),
+                };
+                Ok(Value::Str(join(&array.borrow(), &separator).into()))
+            }
+            "indexOf" => {
+                let searched = argument(0);
+                let index = array
+                    .borrow()
+                    .iter()
+                    .position(|value| strict_equals(value, &searched));
+                Ok(Value::Number(index.map_or(-1.0, |index| index as f64)))
+            }
+            "includes" => {
+                let searched = argument(0);
+                let found = array
+                    .borrow()
+                    .iter()
+                    .any(|value| strict_equals(value, &searched));
+                Ok(Value::Bool(found))
+            }
+            "concat" => {
+                let mut values = array.borrow().clone();
+                for argument in arguments {
+                    match argument {
+                        Value::Array(other) => values.extend(other.borrow().iter().cloned()),
+                        other => values.push(other),
+                    }
+                    check_array_len(values.len())?;
+                }
+                Ok(Value::Array(Rc::new(RefCell::new(values))))
+            }
+            "forEach" | "map" | "filter" => {
+                let callback = argument(0);
+                let this = argument(1);
+                let mut results = Vec::new();
+                // the callback may modify the array: elements are read one at a time
+                for index in 0..len {
+                    let element = match array.borrow().get(index) {
+                        Some(element) => element.clone(),
+                        None => break,
+                    };
+                    let arguments = vec![
+                        element.clone(),
+                        Value::Number(index as f64),
+                        Value::Array(Rc::clone(array)),
+                    ];
+                    let result = self.call_value(&callback, this.clone(), arguments)?;
+                    match method {
+                        "map" => results.push(result),
+                        "filter" if to_bool(&result) => results.push(element),
+                        _ => {}
+                    }
+                }
+                Ok(match method {
+                    "forEach" => Value::Undefined,
+                    _ => Value::Array(Rc::new(RefCell::new(results))),
+                })
+            }
+            "toString" => Ok(Value::Str(join(&array.borrow(), ",").into())),
+            _ => {
+                let function = get_property(&Value::Array(Rc::clone(array)), method)?;
+                self.call_value(&function, Value::Array(Rc::clone(array)), arguments)
+            }
+        }
+    }
+}
+
+fn call_string_method(string: &str, method: &str, arguments: &[Value]) -> Completion<Value> {
+    let argument = |index: usize| arguments.get(index).cloned().unwrap_or(Value::Undefined);
+    let chars: Vec<char> = string.chars().collect();
+    let len = chars.len();
+    let substring = |start: usize, end: usize| -> Value {
+        Value::Str(
+            chars[start..end.max(start)]
+                .iter()
+                .collect::<String>()
+                .into(),
+        )
+    };
+    Ok(match method {
+        "split" => {
+            let parts: Vec<Value> = match argument(0) {
+                Value::Undefined => vec![Value::Str(string.into())],
+                separator => {
+                    let separator = to_string(&separator);
+                    if separator.is_empty() {
+                        chars
+                            .iter()
+                            .map(|c| Value::Str(c.to_string().into()))
+                            .collect()
+                    } else {
+                        string
+                            .split(separator.as_str())
+                            .map(|part| Value::Str(part.into()))
+                            .collect()
+                    }
+                }
+            };
+            Value::Array(Rc::new(RefCell::new(parts)))
+        }
+        "charAt" => {
+            let index = to_integer(&argument(0));
+            match chars.get(index as usize) {
+                Some(c) if index >= 0.0 => Value::Str(c.to_string().into()),
+                _ => Value::Str("".into()),
+            }
+        }
+        "charCodeAt" => {
+            let index = to_integer(&argument(0));
+            match chars.get(index as usize) {
+                Some(&c) if index >= 0.0 => Value::Number(c as u32 as f64),
+                _ => Value::Number(f64::NAN),
+            }
+        }
+        "indexOf" => {
+            let searched = to_string(&argument(0));
+            let index = string
+                .find(&searched)
+                .map(|byte_index| string[..byte_index].chars().count());
+            Value::Number(index.map_or(-1.0, |index| index as f64))
+        }
+        "slice" => {
+            let start = relative_index(&argument(0), len);
+            let end = match argument(1) {
+                Value::Undefined => len,
+                end => relative_index(&end, len),
+            };
+            substring(start, end)
+        }
+        "substring" => {
+            let clamp = |value: Value| (to_integer(&value).max(0.0) as usize).min(len);
+            let start = clamp(argument(0));
+            let end = match argument(1) {
+                Value::Undefined => len,
+                end => clamp(end),
+            };
+            substring(start.min(end), start.max(end))
+        }
+        "concat" => {
+            let mut result = string.to_owned();
+            for argument in arguments {
+                result.push_str(&to_string(argument));
+            }
+            Value::Str(result.into())
+        }
+        "toString" => Value::Str(string.into()),
+        _ => return Err(JsError(format!("unsupported string method {}", method)).into()),
+    })
+}
+
+fn call_global_method(global: &str, method: &str, arguments: &[Value]) -> Completion<Value> {
+    let numbers: Vec<f64> = arguments.iter().map(to_number).collect();
+    let first = numbers.first().copied().unwrap_or(f64::NAN);
+    Ok(match (global, method) {
+        ("String", "fromCharCode") => Value::Str(
+            numbers
+                .iter()
+                .filter_map(|&code| char::from_u32(code as u32 & 0xFFFF))
+                .collect::<String>()
+                .into(),
+        ),
+        ("Math", "floor") => Value::Number(first.floor()),
+        ("Math", "ceil") => Value::Number(first.ceil()),
+        ("Math", "round") => Value::Number((first + 0.5).floor()),
+        ("Math", "abs") => Value::Number(first.abs()),
+        ("Math", "pow") => Value::Number(first.powf(numbers.get(1).copied().unwrap_or(f64::NAN))),
+        ("Math", "max") => Value::Number(numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        ("Math", "min") => Value::Number(numbers.iter().copied().fold(f64::INFINITY, f64::min)),
+        _ => return Err(JsError(format!("unsupported function {}.{}", global, method)).into()),
+    })
+}
+
+// Declares the functions and variables of a function body before running it, like JS does.
+fn hoist(statements: &[Stmt], scope: &Rc<Scope>) {
+    for statement in statements {
+        match statement {
+            Stmt::Function(name, def) => scope.declare(
+                name,
+                Value::Function(Rc::new(Closure {
+                    def: Rc::clone(def),
+                    scope: Rc::clone(scope),
+                })),
+            ),
+            Stmt::Var(declarations) => {
+                for (name, _) in declarations {
+                    if scope.variables.borrow().get(name).is_none() {
+                        scope.declare(name, Value::Undefined);
+                    }
+                }
+            }
+            Stmt::Block(statements) => hoist(statements, scope),
+            Stmt::If(_, consequent, alternate) => {
+                hoist(std::slice::from_ref(&**consequent), scope);
+                if let Some(alternate) = alternate {
+                    hoist(std::slice::from_ref(&**alternate), scope);
+                }
+            }
+            Stmt::For { init, body, .. } => {
+                if let Some(init) = init {
+                    hoist(std::slice::from_ref(&**init), scope);
+                }
+                hoist(std::slice::from_ref(&**body), scope);
+            }
+            Stmt::While(_, body) | Stmt::DoWhile(body, _) => {
+                hoist(std::slice::from_ref(&**body), scope)
+            }
+            Stmt::Switch(_, cases) => {
+                for (_, body) in cases {
+                    hoist(body, scope);
+                }
+            }
+            Stmt::Try {
+                block,
+                catch,
+                finally,
+            } => {
+                hoist(block, scope);
+                if let Some((_, handler)) = catch {
+                    hoist(handler, scope);
+                }
+                if let Some(finally) = finally {
+                    hoist(finally, scope);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lookup(name: &str, scope: &Rc<Scope>) -> Completion<Value> {
+    if let Some(value) = scope.lookup(name) {
+        return Ok(value);
+    }
+    Ok(match name {
+        "undefined" => Value::Undefined,
+        "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "NaN" => Value::Number(f64::NAN),
+        "Infinity" => Value::Number(f64::INFINITY),
+        "String" => Value::Global("String"),
+        "Number" => Value::Global("Number"),
+        "Math" => Value::Global("Math"),
+        _ => {
+            return Err(Abrupt::Throw(Value::Str(
+                format!("ReferenceError: {} is not defined", name).into(),
+            )))
+        }
+    })
+}
+
+fn get_property(object: &Value, key: &str) -> Completion<Value> {
+    match object {
+        Value::Undefined | Value::Null => Err(type_error(format!(
+            "cannot read property {} of {}",
+            key,
+            to_string(object)
+        ))),
+        Value::Array(array) => {
+            let array = array.borrow();
+            Ok(match key {
+                "length" => Value::Number(array.len() as f64),
+                _ => array_index(key)
+                    .and_then(|index| array.get(index).cloned())
+                    .unwrap_or(Value::Undefined),
+            })
+        }
+        Value::Str(string) => Ok(match key {
+            "length" => Value::Number(string.chars().count() as f64),
+            _ => array_index(key)
+                .and_then(|index| string.chars().nth(index))
+                .map_or(Value::Undefined, |c| Value::Str(c.to_string().into())),
+        }),
+        Value::Object(object) => Ok(object
+            .borrow()
+            .get(key)
+            .cloned()
+            .unwrap_or(Value::Undefined)),
+        Value::Function(closure) if key == "length" => {
+            Ok(Value::Number(closure.def.params.len() as f64))
+        }
+        _ => Ok(Value::Undefined),
+    }
+}
+
+fn set_property(object: &Value, key: &str, value: Value) -> Completion<()> {
+    match object {
+        Value::Undefined | Value::Null => Err(type_error(format!(
+            "cannot set property {} of {}",
+            key,
+            to_string(object)
+        ))),
+        Value::Array(array) => {
+            let mut array = array.borrow_mut();
+            if key == "length" {
+                let len = to_number(&value).max(0.0) as usize;
+                check_array_len(len)?;
+                array.resize(len, Value::Undefined);
+                return Ok(());
+            }
+            let index = array_index(key)
+                .ok_or_else(|| JsError(format!("unsupported array property {}", key)))?;
+            if index >= array.len() {
+                check_array_len(index.saturating_add(1))?;
+                array.resize(index + 1, Value::Undefined);
+            }
+            array[index] = value;
+            Ok(())
+        }
+        Value::Object(object) => {
+            object.borrow_mut().insert(key.to_owned(), value);
+            Ok(())
+        }
+        // primitives silently ignore new properties
+        _ => Ok(()),
+    }
+}
+
+fn check_array_len(len: usize) -> Completion<()> {
+    if len > MAX_ARRAY_LEN {
+        Err(JsError(format!("array too large ({} elements)", len)).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn array_index(key: &str) -> Option<usize> {
+    key.parse::<usize>()
+        .ok()
+        .filter(|index| index.to_string() == key)
+}
+
+// Resolves negative positions from the end, clamped to `0..=len`.
+fn relative_index(value: &Value, len: usize) -> usize {
+    let index = to_integer(value);
+    if index < 0.0 {
+        (len as f64 + index).max(0.0) as usize
+    } else {
+        (index as usize).min(len)
+    }
+}
+
+fn binary(operator: &str, left: &Value, right: &Value) -> Completion<Value> {
+    Ok(match operator {
+        "+" => {
+            let (left, right) = (to_primitive(left), to_primitive(right));
+            if matches!(left, Value::Str(_)) || matches!(right, Value::Str(_)) {
+                Value::Str(format!("{}{}", to_string(&left), to_string(&right)).into())
+            } else {
+                Value::Number(to_number(&left) + to_number(&right))
+            }
+        }
+        "-" => Value::Number(to_number(left) - to_number(right)),
+        "*" => Value::Number(to_number(left) * to_number(right)),
+        "/" => Value::Number(to_number(left) / to_number(right)),
+        // like JS, the result takes the sign of the dividend
+        "%" => Value::Number(to_number(left) % to_number(right)),
+        "&" => Value::Number((to_int32(left) & to_int32(right)) as f64),
+        "|" => Value::Number((to_int32(left) | to_int32(right)) as f64),
+        "^" => Value::Number((to_int32(left) ^ to_int32(right)) as f64),
+        "<<" => Value::Number(to_int32(left).wrapping_shl(to_uint32(right) & 31) as f64),
+        ">>" => Value::Number((to_int32(left) >> (to_uint32(right) & 31)) as f64),
+        ">>>" => Value::Number((to_uint32(left) >> (to_uint32(right) & 31)) as f64),
+        "===" => Value::Bool(strict_equals(left, right)),
+        "!==" => Value::Bool(!strict_equals(left, right)),
+        "==" => Value::Bool(loose_equals(left, right)),
+        "!=" => Value::Bool(!loose_equals(left, right)),
+        "<" | ">" | "<=" | ">=" => {
+            let (left, right) = (to_primitive(left), to_primitive(right));
+            let ordering = match (&left, &right) {
+                (Value::Str(left), Value::Str(right)) => Some(left.cmp(right)),
+                _ => to_number(&left).partial_cmp(&to_number(&right)),
+            };
+            Value::Bool(match ordering {
+                // comparisons involving NaN are always false
+                None => false,
+                Some(ordering) => match operator {
+                    "<" => ordering.is_lt(),
+                    ">" => ordering.is_gt(),
+                    "<=" => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                },
+            })
+        }
+        _ => return Err(JsError(format!("unsupported operator {}", operator)).into()),
+    })
+}
+
+fn strict_equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Undefined, Value::Undefined) | (Value::Null, Value::Null) => true,
+        (Value::Bool(left), Value::Bool(right)) => left == right,
+        (Value::Number(left), Value::Number(right)) => left == right,
+        (Value::Str(left), Value::Str(right)) => left == right,
+        (Value::Array(left), Value::Array(right)) => Rc::ptr_eq(left, right),
+        (Value::Object(left), Value::Object(right)) => Rc::ptr_eq(left, right),
+        (Value::Function(left), Value::Function(right)) => Rc::ptr_eq(left, right),
+        (Value::Global(left), Value::Global(right)) => left == right,
+        _ => false,
+    }
+}
+
+fn loose_equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Undefined | Value::Null, Value::Undefined | Value::Null) => true,
+        (Value::Undefined | Value::Null, _) | (_, Value::Undefined | Value::Null) => false,
+        (Value::Number(_) | Value::Str(_) | Value::Bool(_), Value::Number(_) | Value::Bool(_))
+        | (Value::Number(_) | Value::Bool(_), Value::Str(_)) => to_number(left) == to_number(right),
+        (Value::Array(_), Value::Number(_) | Value::Str(_) | Value::Bool(_))
+        | (Value::Number(_) | Value::Str(_) | Value::Bool(_), Value::Array(_)) => {
+            loose_equals(&to_primitive(left), &to_primitive(right))
+        }
+        _ => strict_equals(left, right),
+    }
+}
+
+fn to_primitive(value: &Value) -> Value {
+    match value {
+        Value::Array(_) | Value::Object(_) | Value::Function(_) | Value::Global(_) => {
+            Value::Str(to_string(value).into())
+        }
+        _ => value.clone(),
+    }
+}
+
+fn to_bool(value: &Value) -> bool {
+    match value {
+        Value::Undefined | Value::Null => false,
+        Value::Bool(bool) => *bool,
+        Value::Number(number) => *number != 0.0 && !number.is_nan(),
+        Value::Str(string) => !string.is_empty(),
+        _ => true,
+    }
+}
+
+fn to_number(value: &Value) -> f64 {
+    match value {
+        Value::Undefined => f64::NAN,
+        Value::Null => 0.0,
+        Value::Bool(bool) => *bool as u8 as f64,
+        Value::Number(number) => *number,
+        Value::Str(string) => {
+            let string = string.trim();
+            if string.is_empty() {
+                0.0
+            } else if let Some(hex) = string
+                .strip_prefix("0x")
+                .or_else(|| string.strip_prefix("0X"))
+            {
+                i64::from_str_radix(hex, 16).map_or(f64::NAN, |number| number as f64)
+            } else {
+                string.parse().unwrap_or(f64::NAN)
+            }
+        }
+        Value::Array(_) => to_number(&to_primitive(value)),
+        _ => f64::NAN,
+    }
+}
+
+fn to_integer(value: &Value) -> f64 {
+    let number = to_number(value);
+    if number.is_nan() {
+        0.0
+    } else {
+        number.trunc()
+    }
+}
+
+fn to_uint32(value: &Value) -> u32 {
+    let number = to_number(value);
+    if !number.is_finite() {
+        return 0;
+    }
+    number.trunc().rem_euclid(4_294_967_296.0) as u32
+}
+
+fn to_int32(value: &Value) -> i32 {
+    to_uint32(value) as i32
+}
+
+pub fn to_string(value: &Value) -> String {
+    match value {
+        Value::Undefined => "undefined".to_owned(),
+        Value::Null => "null".to_owned(),
+        Value::Bool(bool) => bool.to_string(),
+        Value::Number(number) => number_to_string(*number),
+        Value::Str(string) => string.to_string(),
+        Value::Array(array) => join(&array.borrow(), ","),
+        Value::Object(_) => "[object Object]".to_owned(),
+        Value::Function(_) => "function".to_owned(),
+        Value::Global(global) => global.to_string(),
+    }
+}
+
+fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_owned()
+    } else if number.is_infinite() {
+        if number > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+        .to_owned()
+    } else if number == number.trunc() && number.abs() < 1e21 {
+        // also turns -0 into "0"
+        format!("{}", number as i128)
+    } else {
+        number.to_string()
+    }
+}
+
+fn join(values: &[Value], separator: &str) -> String {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Undefined | Value::Null => String::new(),
+            value => to_string(value),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Undefined => "undefined",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::Str(_) => "string",
+        Value::Function(_) => "function",
+        Value::Null | Value::Array(_) | Value::Object(_) | Value::Global(_) => "object",
+    }
+}
@@ -0,0 +1,216 @@
+use super::JsError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Punct(&'static str),
+}
+
+// longest first, so that e.g. ">>>=" isn't read as ">>" followed by ">="
+const PUNCTUATORS: &[&str] = &[
+    ">>>=", "===", "!==", ">>>", "<<=", ">>=", "==", "!=", "<=", ">=", "&&", "||", "++", "--",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<", ">>", "{", "}", "(", ")", "[", "]", ";",
+    ",", "<", ">", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~", "?", ":", "=", ".",
+];
+
+// Reads tokens on demand: the player is several megabytes long, only the few functions being parsed get tokenized.
+pub struct Lexer<'a> {
+    source: &'a str,
+    position: usize,
+    // whether a line break precedes the last token, for automatic semicolon insertion
+    newline_before: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Lexer {
+            source,
+            position: 0,
+            newline_before: false,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn newline_before(&self) -> bool {
+        self.newline_before
+    }
+
+    pub fn next_token(&mut self) -> Result<Option<Token>, JsError> {
+        self.skip_whitespace_and_comments()?;
+        let rest = &self.source[self.position..];
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            self.position += len;
+            return Ok(Some(Token::Ident(rest[..len].to_owned())));
+        }
+        if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            return self.read_number().map(Some);
+        }
+        if c == '"' || c == '\'' {
+            return self.read_string(c).map(Some);
+        }
+        match PUNCTUATORS.iter().find(|punct| rest.starts_with(*punct)) {
+            Some(punct) => {
+                self.position += punct.len();
+                Ok(Some(Token::Punct(punct)))
+            }
+            None => Err(JsError(format!("unexpected character '{}'", c))),
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), JsError> {
+        self.newline_before = false;
+        loop {
+            let rest = &self.source[self.position..];
+            if rest.starts_with("//") {
+                self.position += rest.find('\n').unwrap_or(rest.len());
+            } else if rest.starts_with("/*") {
+                let end = rest
+                    .find("*/")
+                    .ok_or_else(|| JsError("unterminated comment".to_owned()))?;
+                self.newline_before |= rest[..end].contains('\n');
+                self.position += end + 2;
+            } else {
+                match rest.chars().next() {
+                    Some(c) if c.is_whitespace() => {
+                        self.newline_before |= c == '\n';
+                        self.position += c.len_utf8();
+                    }
+                    _ => return Ok(()),
+                }
+            }
+        }
+    }
+
+    fn read_number(&mut self) -> Result<Token, JsError> {
+        let rest = &self.source[self.position..];
+        if rest.starts_with("0x") || rest.starts_with("0X") {
+            let len = rest[2..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - 2);
+            self.position += 2 + len;
+            return u64::from_str_radix(&rest[2..2 + len], 16)
+                .map(|number| Token::Number(number as f64))
+                .map_err(|_| JsError(format!("invalid number \"{}\"", &rest[..2 + len])));
+        }
+
+        let mut len = 0;
+        let bytes = rest.as_bytes();
+        let mut previous = b' ';
+        while len < bytes.len() {
+            let byte = bytes[len];
+            let continues = byte.is_ascii_digit()
+                || byte == b'.'
+                || byte == b'e'
+                || byte == b'E'
+                || ((byte == b'+' || byte == b'-') && (previous == b'e' || previous == b'E'));
+            if !continues {
+                break;
+            }
+            previous = byte;
+            len += 1;
+        }
+        self.position += len;
+        rest[..len]
+            .parse::<f64>()
+            .map(Token::Number)
+            .map_err(|_| JsError(format!("invalid number \"{}\"", &rest[..len])))
+    }
+
+    fn read_string(&mut self, quote: char) -> Result<Token, JsError> {
+        let mut chars = self.source[self.position + 1..].char_indices();
+        let mut value = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                _ if c == quote => {
+                    self.position += 1 + index + 1;
+                    return Ok(Token::Str(value));
+                }
+                '\\' => {
+                    let (_, escaped) = chars
+                        .next()
+                        .ok_or_else(|| JsError("unterminated string".to_owned()))?;
+                    let mut code_point = |digits: usize| {
+                        let hex: String = chars.by_ref().take(digits).map(|(_, c)| c).collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| JsError(format!("invalid escape sequence \"{}\"", hex)))
+                    };
+                    match escaped {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'v' => value.push('\u{b}'),
+                        '0' => value.push('\0'),
+                        'x' => value.push(code_point(2)?),
+                        'u' => value.push(code_point(4)?),
+                        // line continuation
+                        '\n' => {}
+                        other => value.push(other),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+        Err(JsError("unterminated string".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizes_minified_code() {
+        let mut lexer =
+            Lexer::new(r#"e=(e%d.length)>>>0;/* comment */ h.push("A\x42C",0x1F,.5e1)"#);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_token().unwrap() {
+            tokens.push(token);
+        }
+
+        let ident = |name: &str| Token::Ident(name.to_owned());
+        assert_eq!(
+            tokens,
+            vec![
+                ident("e"),
+                Token::Punct("="),
+                Token::Punct("("),
+                ident("e"),
+                Token::Punct("%"),
+                ident("d"),
+                Token::Punct("."),
+                ident("length"),
+                Token::Punct(")"),
+                Token::Punct(">>>"),
+                Token::Number(0.0),
+                Token::Punct(";"),
+                ident("h"),
+                Token::Punct("."),
+                ident("push"),
+                Token::Punct("("),
+                Token::Str("ABC".to_owned()),
+                Token::Punct(","),
+                Token::Number(31.0),
+                Token::Punct(","),
+                Token::Number(5.0),
+                Token::Punct(")"),
+            ]
+        );
+    }
+}
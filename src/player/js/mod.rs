@@ -0,0 +1,78 @@
+use interpreter::{Interpreter, Value};
+use parser::Parser;
+use std::fmt;
+
+mod interpreter;
+mod lexer;
+mod parser;
+
+// Just enough of a JavaScript interpreter to run the self-contained functions of the player,
+// which only use plain values, arrays, strings and a few built-ins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsError(pub String);
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// The source of the function expression starting `source`, which goes on with the rest of the player.
+pub fn function_source(source: &str) -> Result<&str, JsError> {
+    let mut parser = Parser::new(source);
+    parser.parse_function_expression()?;
+    Ok(&source[..parser.position()])
+}
+
+// Runs the function expression starting `source`, e.g. "function(a){...}", with string arguments.
+pub fn call_function(source: &str, arguments: &[&str]) -> Result<String, JsError> {
+    let function = Parser::new(source).parse_function_expression()?;
+    let arguments = arguments
+        .iter()
+        .map(|argument| Value::Str((*argument).into()))
+        .collect();
+    let result = Interpreter::new().call_function(&function, arguments)?;
+    Ok(interpreter::to_string(&result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_functions() {
+        let source = r#"function(a,b){var c=a.split(""),d=[function(e){e.reverse()},function(e,f){e.push(f)}];
+            d[0](c);d[1](c,b.length%3);try{throw b}catch(g){c.unshift(typeof g)}
+            for(var i=0;i<c.length;i++)switch(c[i]){case "a":c[i]=String.fromCharCode(97+(i<<1)>>>0);break;default:}
+            return c.join("")}"#;
+        assert_eq!(
+            call_function(source, &["abc", "hello"]).unwrap(),
+            "stringcbg2"
+        );
+    }
+
+    #[test]
+    fn rejects_runaway_functions() {
+        let error = call_function("function(){for(;;){}}", &[]).unwrap_err();
+        assert_eq!(error, JsError("too many steps".to_owned()));
+    }
+
+    #[test]
+    fn rejects_huge_arrays() {
+        let error = call_function("function(){var a=[];a.length=4294967295}", &[]).unwrap_err();
+        assert_eq!(
+            error,
+            JsError("array too large (4294967295 elements)".to_owned())
+        );
+        let error = call_function("function(){var a=[];a[1e9]=0}", &[]).unwrap_err();
+        assert_eq!(
+            error,
+            JsError("array too large (1000000001 elements)".to_owned())
+        );
+        let error = call_function("function(){var a=[0];for(;;)a=a.concat(a)}", &[]).unwrap_err();
+        assert_eq!(
+            error,
+            JsError("array too large (2097152 elements)".to_owned())
+        );
+    }
+}
@@ -0,0 +1,631 @@
+use super::lexer::{Lexer, Token};
+use super::JsError;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct FunctionDef {
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+pub enum Stmt {
+    Var(Vec<(String, Option<Expr>)>),
+    Function(String, Rc<FunctionDef>),
+    Expr(Expr),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    For {
+        init: Option<Box<Stmt>>,
+        test: Option<Expr>,
+        update: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    // each case is `None` for "default"
+    Switch(Expr, Vec<(Option<Expr>, Vec<Stmt>)>),
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Throw(Expr),
+    Try {
+        block: Vec<Stmt>,
+        catch: Option<(Option<String>, Vec<Stmt>)>,
+        finally: Option<Vec<Stmt>>,
+    },
+    Empty,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    This,
+    Array(Vec<Expr>),
+    Object(Vec<(String, Expr)>),
+    Function(Rc<FunctionDef>),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    // constructors aren't supported, their arguments are dropped
+    New(Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    // operator ("++" or "--"), whether it's prefixed, target
+    Update(&'static str, bool, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    // "&&" and "||" only evaluate their right side when needed
+    Logical(&'static str, Box<Expr>, Box<Expr>),
+    // operator ("=", "+=", ...), target, value
+    Assign(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Sequence(Vec<Expr>),
+}
+
+// Binary operators by increasing precedence.
+const BINARY_PRECEDENCE: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!=", "===", "!=="],
+    &["<", ">", "<=", ">=", "instanceof", "in"],
+    &["<<", ">>", ">>>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+const ASSIGNMENT_OPERATORS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", ">>>=", "&=", "|=", "^=",
+];
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token>,
+    // whether a line break precedes the peeked token
+    peeked_newline_before: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Parser {
+            lexer: Lexer::new(source),
+            peeked: None,
+            peeked_newline_before: false,
+        }
+    }
+
+    // Parses `function(<params>){<body>}` at the start of the source, ignoring whatever follows it.
+    pub fn parse_function_expression(&mut self) -> Result<Rc<FunctionDef>, JsError> {
+        self.expect_keyword("function")?;
+        if let Some(Token::Ident(_)) = self.peek()? {
+            self.next()?;
+        }
+        self.parse_function_rest()
+    }
+
+    // The end of what has been parsed so far.
+    pub fn position(&self) -> usize {
+        self.lexer.position()
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, JsError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+            self.peeked_newline_before = self.lexer.newline_before();
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Token, JsError> {
+        self.peek()?;
+        self.peeked
+            .take()
+            .ok_or_else(|| JsError("unexpected end of input".to_owned()))
+    }
+
+    fn is_punct(&mut self, punct: &str) -> Result<bool, JsError> {
+        Ok(matches!(self.peek()?, Some(Token::Punct(p)) if *p == punct))
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> Result<bool, JsError> {
+        Ok(matches!(self.peek()?, Some(Token::Ident(name)) if name == keyword))
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> Result<bool, JsError> {
+        let found = self.is_punct(punct)?;
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), JsError> {
+        match self.next()? {
+            Token::Punct(p) if p == punct => Ok(()),
+            token => Err(JsError(format!("expected '{}', found {:?}", punct, token))),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), JsError> {
+        match self.next()? {
+            Token::Ident(name) if name == keyword => Ok(()),
+            token => Err(JsError(format!(
+                "expected '{}', found {:?}",
+                keyword, token
+            ))),
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, JsError> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(JsError(format!(
+                "expected an identifier, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    // Minified code omits the semicolon before "}", and sometimes at line breaks.
+    fn end_statement(&mut self) -> Result<(), JsError> {
+        if self.eat_punct(";")? || self.is_punct("}")? {
+            return Ok(());
+        }
+        if self.peek()?.is_none() || self.peeked_newline_before {
+            return Ok(());
+        }
+        Err(JsError(format!("expected ';', found {:?}", self.peek()?)))
+    }
+
+    fn parse_function_rest(&mut self) -> Result<Rc<FunctionDef>, JsError> {
+        self.expect_punct("(")?;
+        let mut params = Vec::new();
+        while !self.eat_punct(")")? {
+            params.push(self.expect_ident()?);
+            if !self.is_punct(")")? {
+                self.expect_punct(",")?;
+            }
+        }
+        let body = self.parse_block()?;
+        Ok(Rc::new(FunctionDef { params, body }))
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, JsError> {
+        self.expect_punct("{")?;
+        let mut statements = Vec::new();
+        while !self.eat_punct("}")? {
+            statements.push(self.parse_statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, JsError> {
+        if self.is_punct("{")? {
+            return self.parse_block().map(Stmt::Block);
+        }
+        if self.eat_punct(";")? {
+            return Ok(Stmt::Empty);
+        }
+        let keyword = match self.peek()? {
+            Some(Token::Ident(name)) => name.to_owned(),
+            _ => String::new(),
+        };
+        match keyword.as_str() {
+            "var" | "let" | "const" => {
+                self.next()?;
+                let declarations = self.parse_declarations()?;
+                self.end_statement()?;
+                Ok(declarations)
+            }
+            "function" => {
+                self.next()?;
+                let name = self.expect_ident()?;
+                Ok(Stmt::Function(name, self.parse_function_rest()?))
+            }
+            "if" => {
+                self.next()?;
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                let consequent = Box::new(self.parse_statement()?);
+                let alternate = if self.is_keyword("else")? {
+                    self.next()?;
+                    Some(Box::new(self.parse_statement()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If(test, consequent, alternate))
+            }
+            "for" => self.parse_for(),
+            "while" => {
+                self.next()?;
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                Ok(Stmt::While(test, Box::new(self.parse_statement()?)))
+            }
+            "do" => {
+                self.next()?;
+                let body = Box::new(self.parse_statement()?);
+                self.expect_keyword("while")?;
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                self.eat_punct(";")?;
+                Ok(Stmt::DoWhile(body, test))
+            }
+            "switch" => self.parse_switch(),
+            "break" | "continue" => {
+                self.next()?;
+                self.end_statement()?;
+                Ok(if keyword == "break" {
+                    Stmt::Break
+                } else {
+                    Stmt::Continue
+                })
+            }
+            "return" => {
+                self.next()?;
+                let argument =
+                    if self.is_punct(";")? || self.is_punct("}")? || self.peeked_newline_before {
+                        None
+                    } else {
+                        Some(self.parse_expression()?)
+                    };
+                self.end_statement()?;
+                Ok(Stmt::Return(argument))
+            }
+            "throw" => {
+                self.next()?;
+                let argument = self.parse_expression()?;
+                self.end_statement()?;
+                Ok(Stmt::Throw(argument))
+            }
+            "try" => self.parse_try(),
+            _ => {
+                let expression = self.parse_expression()?;
+                self.end_statement()?;
+                Ok(Stmt::Expr(expression))
+            }
+        }
+    }
+
+    fn parse_declarations(&mut self) -> Result<Stmt, JsError> {
+        let mut declarations = Vec::new();
+        loop {
+            let name = self.expect_ident()?;
+            let init = if self.eat_punct("=")? {
+                Some(self.parse_assignment()?)
+            } else {
+                None
+            };
+            declarations.push((name, init));
+            if !self.eat_punct(",")? {
+                return Ok(Stmt::Var(declarations));
+            }
+        }
+    }
+
+    fn parse_for(&mut self) -> Result<Stmt, JsError> {
+        self.expect_keyword("for")?;
+        self.expect_punct("(")?;
+        let init = if self.is_punct(";")? {
+            None
+        } else if self.is_keyword("var")? || self.is_keyword("let")? || self.is_keyword("const")? {
+            self.next()?;
+            Some(Box::new(self.parse_declarations()?))
+        } else {
+            Some(Box::new(Stmt::Expr(self.parse_expression()?)))
+        };
+        self.expect_punct(";")?;
+        let test = if self.is_punct(";")? {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect_punct(";")?;
+        let update = if self.is_punct(")")? {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect_punct(")")?;
+        let body = Box::new(self.parse_statement()?);
+        Ok(Stmt::For {
+            init,
+            test,
+            update,
+            body,
+        })
+    }
+
+    fn parse_switch(&mut self) -> Result<Stmt, JsError> {
+        self.expect_keyword("switch")?;
+        self.expect_punct("(")?;
+        let discriminant = self.parse_expression()?;
+        self.expect_punct(")")?;
+        self.expect_punct("{")?;
+        let mut cases = Vec::new();
+        while !self.eat_punct("}")? {
+            let test = if self.is_keyword("default")? {
+                self.next()?;
+                None
+            } else {
+                self.expect_keyword("case")?;
+                Some(self.parse_expression()?)
+            };
+            self.expect_punct(":")?;
+            let mut body = Vec::new();
+            while !(self.is_keyword("case")?
+                || self.is_keyword("default")?
+                || self.is_punct("}")?)
+            {
+                body.push(self.parse_statement()?);
+            }
+            cases.push((test, body));
+        }
+        Ok(Stmt::Switch(discriminant, cases))
+    }
+
+    fn parse_try(&mut self) -> Result<Stmt, JsError> {
+        self.expect_keyword("try")?;
+        let block = self.parse_block()?;
+        let catch = if self.is_keyword("catch")? {
+            self.next()?;
+            let param = if self.eat_punct("(")? {
+                let param = self.expect_ident()?;
+                self.expect_punct(")")?;
+                Some(param)
+            } else {
+                None
+            };
+            Some((param, self.parse_block()?))
+        } else {
+            None
+        };
+        let finally = if self.is_keyword("finally")? {
+            self.next()?;
+            Some(self.parse_block()?)
+        } else {
+            None
+        };
+        Ok(Stmt::Try {
+            block,
+            catch,
+            finally,
+        })
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, JsError> {
+        let first = self.parse_assignment()?;
+        if !self.is_punct(",")? {
+            return Ok(first);
+        }
+        let mut expressions = vec![first];
+        while self.eat_punct(",")? {
+            expressions.push(self.parse_assignment()?);
+        }
+        Ok(Expr::Sequence(expressions))
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, JsError> {
+        let target = self.parse_conditional()?;
+        let operator = match self.peek()? {
+            Some(Token::Punct(p)) if ASSIGNMENT_OPERATORS.contains(p) => *p,
+            _ => return Ok(target),
+        };
+        if !matches!(target, Expr::Ident(_) | Expr::Member(..) | Expr::Index(..)) {
+            return Err(JsError("invalid assignment target".to_owned()));
+        }
+        self.next()?;
+        let value = self.parse_assignment()?;
+        Ok(Expr::Assign(operator, Box::new(target), Box::new(value)))
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr, JsError> {
+        let test = self.parse_binary(0)?;
+        if !self.eat_punct("?")? {
+            return Ok(test);
+        }
+        let consequent = self.parse_assignment()?;
+        self.expect_punct(":")?;
+        let alternate = self.parse_assignment()?;
+        Ok(Expr::Conditional(
+            Box::new(test),
+            Box::new(consequent),
+            Box::new(alternate),
+        ))
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, JsError> {
+        if level == BINARY_PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        loop {
+            let operator = match self.peek()? {
+                Some(Token::Punct(p)) => BINARY_PRECEDENCE[level].iter().find(|op| *op == p),
+                Some(Token::Ident(name)) => BINARY_PRECEDENCE[level].iter().find(|op| *op == name),
+                _ => None,
+            };
+            let operator = match operator {
+                Some(operator) => *operator,
+                None => return Ok(left),
+            };
+            self.next()?;
+            let right = Box::new(self.parse_binary(level + 1)?);
+            left = match operator {
+                "&&" | "||" => Expr::Logical(operator, Box::new(left), right),
+                _ => Expr::Binary(operator, Box::new(left), right),
+            };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, JsError> {
+        let operator = match self.peek()? {
+            Some(Token::Punct(p)) if ["!", "-", "+", "~", "++", "--"].contains(p) => *p,
+            Some(Token::Ident(name)) if ["typeof", "void", "delete"].contains(&name.as_str()) => {
+                match name.as_str() {
+                    "typeof" => "typeof",
+                    "void" => "void",
+                    _ => "delete",
+                }
+            }
+            _ => return self.parse_postfix(),
+        };
+        self.next()?;
+        let argument = Box::new(self.parse_unary()?);
+        Ok(match operator {
+            "++" | "--" => Expr::Update(operator, true, argument),
+            _ => Expr::Unary(operator, argument),
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, JsError> {
+        let expression = self.parse_call()?;
+        for operator in ["++", "--"] {
+            // a line break ends the statement instead, e.g. "a\n++b"
+            if self.is_punct(operator)? && !self.peeked_newline_before {
+                self.next()?;
+                return Ok(Expr::Update(operator, false, Box::new(expression)));
+            }
+        }
+        Ok(expression)
+    }
+
+    fn parse_call(&mut self) -> Result<Expr, JsError> {
+        let mut expression = if self.is_keyword("new")? {
+            self.next()?;
+            let callee = self.parse_member_only()?;
+            if self.is_punct("(")? {
+                self.parse_arguments()?;
+            }
+            Expr::New(Box::new(callee))
+        } else {
+            self.parse_primary()?
+        };
+        loop {
+            if self.eat_punct(".")? {
+                expression = Expr::Member(Box::new(expression), self.expect_ident()?);
+            } else if self.eat_punct("[")? {
+                let index = self.parse_expression()?;
+                self.expect_punct("]")?;
+                expression = Expr::Index(Box::new(expression), Box::new(index));
+            } else if self.is_punct("(")? {
+                expression = Expr::Call(Box::new(expression), self.parse_arguments()?);
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    // the callee of `new`, whose arguments are not part of a call
+    fn parse_member_only(&mut self) -> Result<Expr, JsError> {
+        let mut expression = self.parse_primary()?;
+        loop {
+            if self.eat_punct(".")? {
+                expression = Expr::Member(Box::new(expression), self.expect_ident()?);
+            } else if self.eat_punct("[")? {
+                let index = self.parse_expression()?;
+                self.expect_punct("]")?;
+                expression = Expr::Index(Box::new(expression), Box::new(index));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, JsError> {
+        self.expect_punct("(")?;
+        let mut arguments = Vec::new();
+        while !self.eat_punct(")")? {
+            arguments.push(self.parse_assignment()?);
+            if !self.is_punct(")")? {
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, JsError> {
+        match self.next()? {
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::Str(string) => Ok(Expr::Str(string)),
+            Token::Ident(name) => match name.as_str() {
+                "function" => {
+                    if let Some(Token::Ident(_)) = self.peek()? {
+                        self.next()?;
+                    }
+                    self.parse_function_rest().map(Expr::Function)
+                }
+                "this" => Ok(Expr::This),
+                _ => Ok(Expr::Ident(name)),
+            },
+            Token::Punct("(") => {
+                let expression = self.parse_expression()?;
+                self.expect_punct(")")?;
+                Ok(expression)
+            }
+            Token::Punct("[") => {
+                let mut elements = Vec::new();
+                while !self.eat_punct("]")? {
+                    elements.push(self.parse_assignment()?);
+                    if !self.is_punct("]")? {
+                        self.expect_punct(",")?;
+                    }
+                }
+                Ok(Expr::Array(elements))
+            }
+            Token::Punct("{") => {
+                let mut properties = Vec::new();
+                while !self.eat_punct("}")? {
+                    let key = match self.next()? {
+                        Token::Ident(name) | Token::Str(name) => name,
+                        Token::Number(number) => number.to_string(),
+                        token => return Err(JsError(format!("invalid property name {:?}", token))),
+                    };
+                    self.expect_punct(":")?;
+                    properties.push((key, self.parse_assignment()?));
+                    if !self.is_punct("}")? {
+                        self.expect_punct(",")?;
+                    }
+                }
+                Ok(Expr::Object(properties))
+            }
+            token => Err(JsError(format!("unexpected token {:?}", token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_functions_and_ignores_what_follows() {
+        let source = r#"function(d,e){for(var f=64,h=[];++f-h.length-32;)switch(f){case 58:f=96;continue;default:h.push(String.fromCharCode(f))}return h.join("")};var x=/regex/;"#;
+        let function = Parser::new(source).parse_function_expression().unwrap();
+        assert_eq!(function.params, vec!["d", "e"]);
+        assert!(matches!(
+            function.body.as_slice(),
+            [Stmt::For { .. }, Stmt::Return(Some(Expr::Call(..)))]
+        ));
+    }
+
+    #[test]
+    fn respects_operator_precedence() {
+        let function = Parser::new("function(){return a+b*c-d}")
+            .parse_function_expression()
+            .unwrap();
+        match function.body.as_slice() {
+            [Stmt::Return(Some(Expr::Binary("-", left, _)))] => {
+                assert!(
+                    matches!(**left, Expr::Binary("+", _, ref right) if matches!(**right, Expr::Binary("*", ..)))
+                )
+            }
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+}
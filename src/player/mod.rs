@@ -0,0 +1,240 @@
+use crate::models::{Format, PlayerResponse};
+use crate::utils::YOUTUBE_URL;
+use crate::YouDlError;
+use async_compat::CompatExt;
+use n_param::NTransform;
+use qstring::QString;
+use regex::Regex;
+use signature::SignatureTransform;
+use smol::lock::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+mod js;
+mod n_param;
+mod signature;
+
+// What is needed from the player JS to turn protected streams into downloadable URLs.
+#[derive(Debug)]
+pub struct Player {
+    signature_transform: SignatureTransform,
+    // downloads still work without it, only slowly
+    n_transform: Option<NTransform>,
+}
+
+impl Player {
+    pub fn from_js(player_js: &str) -> Result<Self, YouDlError> {
+        let n_transform = match NTransform::extract(player_js) {
+            Ok(n_transform) => Some(n_transform),
+            Err(e) => {
+                warn!("{}, downloads may be throttled", e);
+                None
+            }
+        };
+        Ok(Player {
+            signature_transform: SignatureTransform::extract(player_js)?,
+            n_transform,
+        })
+    }
+
+    // `signature_cipher` is a query string, e.g. "s=<scrambled signature>&sp=sig&url=<stream url>":
+    // the deciphered signature gets appended to the URL, under the name given by "sp".
+    pub fn decipher_url(&self, signature_cipher: &str) -> Result<String, YouDlError> {
+        let cipher = QString::from(signature_cipher);
+        let (url, signature) = cipher.get("url").zip(cipher.get("s")).ok_or_else(|| {
+            YouDlError::InvalidResponse(format!(
+                "invalid signature cipher \"{}\"",
+                signature_cipher
+            ))
+        })?;
+        let signature_param = cipher.get("sp").unwrap_or("signature");
+        let query = QString::new(vec![(
+            signature_param,
+            self.signature_transform.apply(signature),
+        )]);
+        let separator = if url.contains('?') { '&' } else { '?' };
+        Ok(format!("{}{}{}", url, separator, query))
+    }
+
+    // Replaces the `n` parameter of a stream URL, so that it doesn't get throttled.
+    // Formats share the same few values, hence the cache of the transformed ones.
+    fn transform_n_param(
+        &self,
+        url: &str,
+        transformed: &mut HashMap<String, String>,
+    ) -> Result<String, YouDlError> {
+        let n_transform = match &self.n_transform {
+            Some(n_transform) => n_transform,
+            None => return Ok(url.to_owned()),
+        };
+        let captures = match n_param_regex().captures(url) {
+            Some(captures) => captures,
+            None => return Ok(url.to_owned()),
+        };
+        let n = &captures[2];
+        let new_n = match transformed.get(n) {
+            Some(new_n) => new_n.to_owned(),
+            None => {
+                let new_n = n_transform.apply(n)?;
+                transformed.insert(n.to_owned(), new_n.to_owned());
+                new_n
+            }
+        };
+        let range = captures.get(0).map_or(0..0, |n_param| n_param.range());
+        Ok(format!(
+            "{}{}n={}{}",
+            &url[..range.start],
+            &captures[1],
+            new_n,
+            &url[range.end..]
+        ))
+    }
+}
+
+fn n_param_regex() -> &'static Regex {
+    static N_PARAM: OnceLock<Regex> = OnceLock::new();
+    N_PARAM.get_or_init(|| Regex::new(r"([?&])n=([^&]+)").expect("valid regex expression"))
+}
+
+// Fills in the URL of the formats that only come with a signature cipher, and transforms their `n` parameter.
+// The player is only fetched when at least one format needs it.
+pub async fn resolve_stream_urls(
+    player_response: &mut PlayerResponse,
+    players: &PlayerCache,
+) -> Result<(), YouDlError> {
+    let formats: Vec<&mut Format> = match player_response.streaming_data.as_mut() {
+        Some(streaming_data) => streaming_data
+            .formats
+            .iter_mut()
+            .chain(streaming_data.adaptive_formats.iter_mut())
+            .filter(|format| {
+                format.url.is_none() && format.signature_cipher.is_some()
+                    || format
+                        .url
+                        .as_ref()
+                        .is_some_and(|url| n_param_regex().is_match(url))
+            })
+            .collect(),
+        None => return Ok(()),
+    };
+    if formats.is_empty() {
+        return Ok(());
+    }
+
+    let needs_deciphering = formats.iter().any(|format| format.url.is_none());
+    let player = match players.get().await {
+        Ok(player) => player,
+        Err(e) if !needs_deciphering => {
+            warn!("{}, downloads may be throttled", e);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    let mut transformed = HashMap::new();
+    for format in formats {
+        let url = match (&format.url, &format.signature_cipher) {
+            (Some(url), _) => url.to_owned(),
+            (None, Some(signature_cipher)) => player.decipher_url(signature_cipher)?,
+            (None, None) => continue,
+        };
+        format.url = Some(match player.transform_n_param(&url, &mut transformed) {
+            Ok(url) => url,
+            Err(e) => {
+                warn!("{}, downloads may be throttled", e);
+                url
+            }
+        });
+    }
+    Ok(())
+}
+
+// The player of a `Downloader`, fetched and parsed by the first video that needs it and shared by the others.
+// YouTube changes players every few days (e.g. "8f3e1c2a"), but the one of a run keeps working until it's done.
+#[derive(Default)]
+pub struct PlayerCache(Mutex<Option<Arc<Player>>>);
+
+impl PlayerCache {
+    async fn get(&self) -> Result<Arc<Player>, YouDlError> {
+        // the lock is held while fetching, so that concurrent downloads wait for the same player
+        let mut cached = self.0.lock().await;
+        if let Some(player) = cached.as_ref() {
+            return Ok(Arc::clone(player));
+        }
+
+        let player_url = format!(
+            "{}/s/player/{}/player_ias.vflset/en_US/base.js",
+            YOUTUBE_URL,
+            get_player_version().await?
+        );
+        let player = Arc::new(Player::from_js(&get_text(&player_url).await?)?);
+        *cached = Some(Arc::clone(&player));
+        Ok(player)
+    }
+}
+
+// The iframe API script refers to the current player, e.g. "https:\/\/www.youtube.com\/s\/player\/8f3e1c2a\/www-widgetapi.vflset\/www-widgetapi.js".
+async fn get_player_version() -> Result<String, YouDlError> {
+    let iframe_api = get_text(&format!("{}/iframe_api", YOUTUBE_URL)).await?;
+    extract_player_version(&iframe_api).ok_or(YouDlError::InvalidResponse(
+        "missing player version".to_owned(),
+    ))
+}
+
+fn extract_player_version(text: &str) -> Option<String> {
+    Regex::new(r"player\\?/([0-9a-fA-F]{8})\\?/")
+        .expect("valid regex expression")
+        .captures(text)
+        .and_then(|captures| captures.get(1))
+        .map(|version| version.as_str().to_owned())
+}
+
+async fn get_text(url: &str) -> Result<String, YouDlError> {
+    reqwest::get(url)
+        .compat()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?
+        .text()
+        .compat()
+        .await
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deciphers_stream_urls() {
+        let player = Player::from_js(include_str!("fixtures/base.js")).unwrap();
+        let signature_cipher = "s=ABCDEFGHIJKLMNOPQRSTUVWXYZ%3D0123&sp=sig&url=https%3A%2F%2Fr1---sn-example.googlevideo.com%2Fvideoplayback%3Fexpire%3D1600000000%26itag%3D22";
+        assert_eq!(
+            player.decipher_url(signature_cipher).unwrap(),
+            "https://r1---sn-example.googlevideo.com/videoplayback?expire=1600000000&itag=22&sig=BCDEFGHIJKLMNOPQRATUVWXYZ%3D31"
+        );
+    }
+
+    #[test]
+    fn transforms_n_parameters() {
+        let player = Player::from_js(include_str!("fixtures/base.js")).unwrap();
+        let mut transformed = HashMap::new();
+        let url = "https://r1---sn-example.googlevideo.com/videoplayback?expire=1600000000&n=vWxY-z_012AbcD&itag=22";
+        assert_eq!(
+            player.transform_n_param(url, &mut transformed).unwrap(),
+            "https://r1---sn-example.googlevideo.com/videoplayback?expire=1600000000&n=IiCa8A41ExzU9h&itag=22"
+        );
+        assert_eq!(
+            transformed.get("vWxY-z_012AbcD").map(String::as_str),
+            Some("IiCa8A41ExzU9h")
+        );
+    }
+
+    #[test]
+    fn extracts_player_version() {
+        let iframe_api = r"var scriptUrl = 'https:\/\/www.youtube.com\/s\/player\/8f3e1c2a\/www-widgetapi.vflset\/www-widgetapi.js';";
+        assert_eq!(
+            extract_player_version(iframe_api).as_deref(),
+            Some("8f3e1c2a")
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use super::js;
+use crate::YouDlError;
+use regex::Regex;
+
+// Streams whose URL still carries the original `n` parameter get throttled to about 50 KB/s.
+// The player transforms it with a self-contained function, which is run by a small interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NTransform {
+    function: String,
+}
+
+impl NTransform {
+    // The function is called where the player rewrites stream URLs, e.g. `(b=a.get("n"))&&(b=Bpa[0](b),a.set("n",b)...)`,
+    // either directly or through an array holding it, e.g. `var Bpa=[Cpa]`.
+    pub fn extract(player_js: &str) -> Result<Self, YouDlError> {
+        let missing =
+            |what: &str| YouDlError::InvalidResponse(format!("missing {} in the player", what));
+
+        let call = Regex::new(
+            r#"\.get\("n"\)\)&&\([a-zA-Z0-9$]+=([a-zA-Z0-9$]+)(?:\[(\d+)\])?\([a-zA-Z0-9$]+\)"#,
+        )
+        .expect("valid regex expression")
+        .captures(player_js)
+        .ok_or_else(|| missing("n parameter function call"))?;
+        let mut name = call[1].to_owned();
+        if let Some(index) = call.get(2) {
+            let index: usize = index.as_str().parse().unwrap_or(0);
+            let array = Regex::new(&format!(r"var {}\s*=\s*\[([^\]]+)\]", regex::escape(&name)))
+                .expect("valid regex expression")
+                .captures(player_js)
+                .and_then(|captures| captures.get(1))
+                .ok_or_else(|| missing("n parameter function array"))?;
+            name = array
+                .as_str()
+                .split(',')
+                .nth(index)
+                .map(|name| name.trim().to_owned())
+                .ok_or_else(|| missing("n parameter function array"))?;
+        }
+
+        let start = Regex::new(&format!(
+            r"(?:^|[^a-zA-Z0-9$.])(?:{}\s*=\s*(function\()|(function\s+){}\()",
+            regex::escape(&name),
+            regex::escape(&name)
+        ))
+        .expect("valid regex expression")
+        .captures(player_js)
+        .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .ok_or_else(|| missing(&format!("n parameter function {}", name)))?
+        .start();
+        let function = js::function_source(&player_js[start..]).map_err(|e| {
+            YouDlError::InvalidResponse(format!("invalid n parameter function: {}", e))
+        })?;
+        Ok(NTransform {
+            function: function.to_owned(),
+        })
+    }
+
+    pub fn apply(&self, n: &str) -> Result<String, YouDlError> {
+        let invalid = |reason: String| {
+            YouDlError::InvalidResponse(format!("could not transform n parameter: {}", reason))
+        };
+        let result = js::call_function(&self.function, &[n]).map_err(|e| invalid(e.0))?;
+        // the function catches its own errors, returning the original value prefixed
+        if result.starts_with("enhanced_except_") || result == n {
+            return Err(invalid(format!("unexpected result \"{}\"", result)));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_n_parameter() {
+        let transform = NTransform::extract(include_str!("fixtures/base.js")).unwrap();
+        assert!(transform
+            .function
+            .starts_with("function(a){var b=a.split(\"\")"));
+        assert!(transform.function.ends_with("return b.join(\"\")}"));
+
+        assert_eq!(transform.apply("vWxY-z_012AbcD").unwrap(), "IiCa8A41ExzU9h");
+        assert_eq!(transform.apply("aBcDeFgHiJkLmN").unwrap(), "mK4BTDt5YmQYXY");
+    }
+
+    #[test]
+    fn rejects_failed_transformations() {
+        let transform = NTransform {
+            function: r#"function(a){try{a.x.y}catch(b){return"enhanced_except_"+a}}"#.to_owned(),
+        };
+        assert!(transform.apply("vWxY-z_012AbcD").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::utils;
+use crate::YouDlError;
+use regex::Regex;
+
+// The player scrambles signatures with a sequence of these operations on their characters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Reverse,
+    // drops the first n characters
+    Splice(usize),
+    // swaps the first character with the one at n (modulo the length)
+    Swap(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureTransform(Vec<Operation>);
+
+impl SignatureTransform {
+    // The deciphering function looks like `vB=function(a){a=a.split("");uB.Xw(a,49);uB.fY(a,34);...;return a.join("")}`,
+    // and its helpers are the methods of the object `uB`: each one either reverses, splices or swaps.
+    pub fn extract(player_js: &str) -> Result<Self, YouDlError> {
+        let missing =
+            |what: &str| YouDlError::InvalidResponse(format!("missing {} in the player", what));
+
+        let body = Regex::new(
+            r#"(?:^|[^a-zA-Z0-9$])[a-zA-Z0-9$]{2,}\s*=\s*function\(\s*a\s*\)\s*\{\s*a\s*=\s*a\.split\(\s*""\s*\);([^}]+)return\s+a\.join\(\s*""\s*\)"#,
+        )
+        .expect("valid regex expression")
+        .captures(player_js)
+        .and_then(|captures| captures.get(1))
+        .ok_or_else(|| missing("signature function"))?
+        .as_str();
+
+        let calls: Vec<(&str, &str, usize)> =
+            Regex::new(r#"([a-zA-Z0-9$]+)(?:\.([a-zA-Z0-9$]+)|\["([a-zA-Z0-9$]+)"\])\(a,(\d+)\)"#)
+                .expect("valid regex expression")
+                .captures_iter(body)
+                .map(|captures| {
+                    let object = captures.get(1).map_or("", |object| object.as_str());
+                    let method = captures
+                        .get(2)
+                        .or_else(|| captures.get(3))
+                        .map_or("", |method| method.as_str());
+                    let argument = captures[4].parse().unwrap_or(0);
+                    (object, method, argument)
+                })
+                .collect();
+        let object_name = calls
+            .first()
+            .map(|&(object, ..)| object)
+            .ok_or_else(|| missing("signature operations"))?;
+
+        let object = utils::extract_json_object(player_js, &format!("var {}=", object_name))
+            .ok_or_else(|| missing("signature helper object"))?;
+        let methods: Vec<(&str, &str)> =
+            Regex::new(r#"([a-zA-Z0-9$]+)\s*:\s*function\([^)]*\)\s*\{([^}]*)\}"#)
+                .expect("valid regex expression")
+                .captures_iter(object)
+                .map(|captures| {
+                    let name = captures.get(1).map_or("", |name| name.as_str());
+                    let body = captures.get(2).map_or("", |body| body.as_str());
+                    (name, body)
+                })
+                .collect();
+
+        let operations = calls
+            .into_iter()
+            .map(|(_, method, argument)| {
+                let (_, method_body) = methods
+                    .iter()
+                    .find(|(name, _)| *name == method)
+                    .ok_or_else(|| missing(&format!("signature helper {}", method)))?;
+                Ok(if method_body.contains("reverse") {
+                    Operation::Reverse
+                } else if method_body.contains("splice") {
+                    Operation::Splice(argument)
+                } else {
+                    Operation::Swap(argument)
+                })
+            })
+            .collect::<Result<_, YouDlError>>()?;
+        Ok(SignatureTransform(operations))
+    }
+
+    pub fn apply(&self, signature: &str) -> String {
+        let mut chars: Vec<char> = signature.chars().collect();
+        for operation in &self.0 {
+            match *operation {
+                Operation::Reverse => chars.reverse(),
+                Operation::Splice(n) => {
+                    chars.drain(..n.min(chars.len()));
+                }
+                Operation::Swap(n) if !chars.is_empty() => {
+                    let len = chars.len();
+                    chars.swap(0, n % len);
+                }
+                Operation::Swap(_) => {}
+            }
+        }
+        chars.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_operations_from_player() {
+        let transform = SignatureTransform::extract(include_str!("fixtures/base.js")).unwrap();
+        assert_eq!(
+            transform,
+            SignatureTransform(vec![
+                Operation::Swap(49),
+                Operation::Splice(1),
+                Operation::Reverse,
+                Operation::Swap(3),
+                Operation::Splice(2),
+                Operation::Reverse,
+            ])
+        );
+    }
+
+    #[test]
+    fn deciphers_signatures() {
+        let transform = SignatureTransform(vec![
+            Operation::Swap(9),
+            Operation::Splice(1),
+            Operation::Reverse,
+            Operation::Swap(1),
+        ]);
+        // "0123456789" -> "9123456780" -> "123456780" -> "087654321" -> "807654321"
+        assert_eq!(transform.apply("0123456789"), "807654321");
+    }
+}
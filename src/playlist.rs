@@ -0,0 +1,390 @@
+use crate::utils::{self, Channel, YoutubeUrl, YOUTUBE_URL};
+use crate::YouDlError;
+use async_compat::CompatExt;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::future::Future;
+use std::str::FromStr;
+
+// 100 videos each, well beyond the largest playlists and channels
+const MAX_CONTINUATIONS: usize = 2000;
+
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistOptions {
+    // which videos of the playlist to download, all of them if `None`
+    pub items: Option<PlaylistItems>,
+    pub reverse: bool,
+    // for URLs pointing at both a video and a playlist, download the video only
+    pub no_playlist: bool,
+}
+
+// 1-based positions within a playlist, e.g. "1-5,8,10-" (open-ended ranges go until the end).
+#[derive(Debug, Clone)]
+pub struct PlaylistItems(Vec<(usize, Option<usize>)>);
+
+impl PlaylistItems {
+    pub fn contains(&self, position: usize) -> bool {
+        self.0
+            .iter()
+            .any(|&(first, last)| position >= first && last.is_none_or(|last| position <= last))
+    }
+}
+
+impl FromStr for PlaylistItems {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || YouDlError::User(format!("invalid playlist items \"{}\"", s));
+        let parse_position = |position: &str| {
+            position
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&position| position > 0)
+                .ok_or_else(invalid)
+        };
+
+        let ranges = s
+            .split(',')
+            .map(|range| match range.split_once('-') {
+                None => parse_position(range).map(|position| (position, Some(position))),
+                Some((first, last)) if last.trim().is_empty() => {
+                    parse_position(first).map(|first| (first, None))
+                }
+                Some((first, last)) => match (parse_position(first)?, parse_position(last)?) {
+                    (first, last) if first <= last => Ok((first, Some(last))),
+                    _ => Err(invalid()),
+                },
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PlaylistItems(ranges))
+    }
+}
+
+// Replaces playlist and channel URLs with the URLs of their videos; video URLs are returned as they are.
+// Channels are expanded through their uploads playlist, so the same options apply.
+pub async fn expand_url(url: &str, options: &PlaylistOptions) -> Result<Vec<String>, YouDlError> {
+    let playlist_id = match utils::parse_url(url) {
+        // unknown URLs are left to the downloader (youtube-dl supports many more websites)
+        Err(_) | Ok(YoutubeUrl::Video(_)) => return Ok(vec![url.to_owned()]),
+        Ok(YoutubeUrl::VideoInPlaylist { .. }) if options.no_playlist => {
+            return Ok(vec![url.to_owned()])
+        }
+        Ok(YoutubeUrl::VideoInPlaylist { playlist_id, .. })
+        | Ok(YoutubeUrl::Playlist(playlist_id)) => playlist_id.to_owned(),
+        Ok(YoutubeUrl::Channel(channel)) => {
+            let channel_id = get_channel_id(&channel).await?;
+            info!("found channel: {}", channel_id);
+            uploads_playlist_id(&channel_id)
+        }
+    };
+    let playlist_id = playlist_id.as_str();
+
+    let video_ids = get_playlist_video_ids(playlist_id).await?;
+    info!(
+        "found {} videos in playlist: {}",
+        video_ids.len(),
+        playlist_id
+    );
+    Ok(select_items(video_ids, options)
+        .into_iter()
+        .map(|video_id| format!("{}/watch?v={}", YOUTUBE_URL, video_id))
+        .collect())
+}
+
+// Every channel "UC<id>" has its uploads listed in the playlist "UU<id>".
+fn uploads_playlist_id(channel_id: &str) -> String {
+    format!("UU{}", channel_id.trim_start_matches("UC"))
+}
+
+async fn get_channel_id(channel: &Channel<'_>) -> Result<String, YouDlError> {
+    let channel_path = match channel {
+        Channel::Id(channel_id) => return Ok((*channel_id).to_owned()),
+        Channel::CustomName(name) => format!("c/{}", name),
+        Channel::Handle(handle) => format!("@{}", handle),
+        Channel::User(user) => format!("user/{}", user),
+    };
+    let page = get_text(&format!("{}/{}", YOUTUBE_URL, channel_path)).await?;
+    extract_initial_data(&page)?["metadata"]["channelMetadataRenderer"]["externalId"]
+        .as_str()
+        .map(|channel_id| channel_id.to_owned())
+        .ok_or(YouDlError::InvalidResponse(format!(
+            "missing channel id for {}",
+            channel_path
+        )))
+}
+
+fn select_items(video_ids: Vec<String>, options: &PlaylistOptions) -> Vec<String> {
+    let mut video_ids: Vec<_> = video_ids
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| {
+            options
+                .items
+                .as_ref()
+                .is_none_or(|items| items.contains(index + 1))
+        })
+        .map(|(_, video_id)| video_id)
+        .collect();
+    if options.reverse {
+        video_ids.reverse();
+    }
+    video_ids
+}
+
+// The playlist page embeds the first 100 videos in its "ytInitialData";
+// the remaining ones are fetched in batches through the continuation tokens.
+async fn get_playlist_video_ids(playlist_id: &str) -> Result<Vec<String>, YouDlError> {
+    let playlist_url = format!("{}/playlist?list={}", YOUTUBE_URL, playlist_id);
+    let page = get_text(&playlist_url).await?;
+    let initial_data = extract_initial_data(&page)?;
+
+    let mut video_ids = Vec::new();
+    let continuation = collect_video_ids(&initial_data, &mut video_ids);
+    if video_ids.is_empty() {
+        return Err(YouDlError::InvalidResponse(format!(
+            "no videos found in playlist {}",
+            playlist_id
+        )));
+    }
+
+    let (api_key, client_version) = match continuation {
+        Some(_) => extract_innertube_config(&page)?,
+        None => return Ok(video_ids),
+    };
+    let browse_url = format!("{}/youtubei/v1/browse?key={}", YOUTUBE_URL, api_key);
+    follow_continuations(continuation, &mut video_ids, |token| {
+        let body = json!({
+            "context": { "client": { "clientName": "WEB", "clientVersion": client_version } },
+            "continuation": token,
+        });
+        let browse_url = &browse_url;
+        async move { post_json(browse_url, &body).await }
+    })
+    .await?;
+    Ok(video_ids)
+}
+
+// Fetches the next batches until there is no continuation token left.
+// A token seen before, a batch without videos or too many batches end the playlist early, rather than looping forever.
+async fn follow_continuations<F, Fut>(
+    mut continuation: Option<String>,
+    video_ids: &mut Vec<String>,
+    fetch: F,
+) -> Result<(), YouDlError>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Value, YouDlError>>,
+{
+    let mut seen_tokens = HashSet::new();
+    while let Some(token) = continuation {
+        if seen_tokens.len() == MAX_CONTINUATIONS || !seen_tokens.insert(token.to_owned()) {
+            warn!(
+                "stopping after {} videos: the playlist keeps asking for more",
+                video_ids.len()
+            );
+            break;
+        }
+        let response = fetch(token).await?;
+        let collected = video_ids.len();
+        continuation = collect_video_ids(&response, video_ids);
+        if video_ids.len() == collected {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn extract_initial_data(page: &str) -> Result<Value, YouDlError> {
+    ["var ytInitialData = ", "window[\"ytInitialData\"] = "]
+        .iter()
+        .find_map(|marker| utils::extract_json_object(page, marker))
+        .ok_or(YouDlError::InvalidResponse(
+            "missing value for ytInitialData".to_owned(),
+        ))
+        .and_then(|json| {
+            serde_json::from_str(json).map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+        })
+}
+
+fn extract_innertube_config(page: &str) -> Result<(String, String), YouDlError> {
+    let extract = |key: &str| {
+        Regex::new(&format!(r#""{}"\s*:\s*"([^"]+)""#, key))
+            .expect("valid regex expression")
+            .captures(page)
+            .and_then(|captures| captures.get(1))
+            .map(|value| value.as_str().to_owned())
+            .ok_or(YouDlError::InvalidResponse(format!(
+                "missing value for {}",
+                key
+            )))
+    };
+    Ok((
+        extract("INNERTUBE_API_KEY")?,
+        extract("INNERTUBE_CLIENT_VERSION")?,
+    ))
+}
+
+// Walks the JSON tree (arrays keep the playlist order), appending the id of every video found,
+// and returns the continuation token for the next batch of videos, if any.
+fn collect_video_ids(value: &Value, video_ids: &mut Vec<String>) -> Option<String> {
+    let mut continuation = None;
+    match value {
+        Value::Object(object) => {
+            if let Some(video_id) = object
+                .get("playlistVideoRenderer")
+                .and_then(|renderer| renderer["videoId"].as_str())
+            {
+                video_ids.push(video_id.to_owned());
+            }
+            if let Some(token) = object.get("continuationItemRenderer").and_then(|renderer| {
+                renderer["continuationEndpoint"]["continuationCommand"]["token"].as_str()
+            }) {
+                continuation = Some(token.to_owned());
+            }
+            for child in object.values() {
+                continuation = collect_video_ids(child, video_ids).or(continuation);
+            }
+        }
+        Value::Array(array) => {
+            for child in array {
+                continuation = collect_video_ids(child, video_ids).or(continuation);
+            }
+        }
+        _ => {}
+    }
+    continuation
+}
+
+async fn get_text(url: &str) -> Result<String, YouDlError> {
+    reqwest::Client::new()
+        .get(url)
+        // skips the cookie consent page shown to European visitors
+        .header(reqwest::header::COOKIE, "CONSENT=YES+1")
+        .send()
+        .compat()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?
+        .text()
+        .compat()
+        .await
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+}
+
+async fn post_json(url: &str, body: &Value) -> Result<Value, YouDlError> {
+    let body = serde_json::to_string(body).expect("valid json");
+    let response_body = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .compat()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?
+        .text()
+        .compat()
+        .await
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+    serde_json::from_str(&response_body).map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_playlist_items() {
+        let items: PlaylistItems = "1-3,5,8-".parse().unwrap();
+        let selected: Vec<usize> = (1..=10)
+            .filter(|&position| items.contains(position))
+            .collect();
+        assert_eq!(selected, vec![1, 2, 3, 5, 8, 9, 10]);
+
+        for invalid in &["", "0", "3-1", "a-b", "1,,2"] {
+            assert!(invalid.parse::<PlaylistItems>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn selects_and_reverses_items() {
+        let video_ids: Vec<String> = (1..=5).map(|i| format!("video{}", i)).collect();
+        let options = PlaylistOptions {
+            items: Some("2-3,5".parse().unwrap()),
+            reverse: true,
+            no_playlist: false,
+        };
+        assert_eq!(
+            select_items(video_ids, &options),
+            vec!["video5", "video3", "video2"]
+        );
+    }
+
+    #[test]
+    fn finds_uploads_playlist_of_channel() {
+        assert_eq!(
+            uploads_playlist_id("UCuAXFkgsw1L7xaCfnd5JJOw"),
+            "UUuAXFkgsw1L7xaCfnd5JJOw"
+        );
+    }
+
+    #[test]
+    fn collects_video_ids_and_continuation_from_initial_data() {
+        let page = r#"<script>var ytInitialData = {"contents":{"playlistVideoListRenderer":{"contents":[
+            {"playlistVideoRenderer":{"videoId":"aaaaaaaaaaa","title":{"runs":[{"text":"{first}"}]}}},
+            {"playlistVideoRenderer":{"videoId":"bbbbbbbbbbb"}},
+            {"continuationItemRenderer":{"continuationEndpoint":{"continuationCommand":{"token":"4qmFsgI"}}}}
+        ]}}};</script>"#;
+
+        let initial_data = extract_initial_data(page).unwrap();
+        let mut video_ids = Vec::new();
+        let continuation = collect_video_ids(&initial_data, &mut video_ids);
+        assert_eq!(video_ids, vec!["aaaaaaaaaaa", "bbbbbbbbbbb"]);
+        assert_eq!(continuation.as_deref(), Some("4qmFsgI"));
+    }
+
+    #[test]
+    fn stops_following_continuations_that_go_nowhere() {
+        let batch = |video_id: &str, token: &str| {
+            json!({ "items": [
+                { "playlistVideoRenderer": { "videoId": video_id } },
+                { "continuationItemRenderer": { "continuationEndpoint": {
+                    "continuationCommand": { "token": token } } } },
+            ]})
+        };
+        let follow = |responses: Vec<Value>| {
+            let mut video_ids = vec!["first".to_owned()];
+            let requests = std::cell::Cell::new(0);
+            smol::block_on(follow_continuations(
+                Some("a".to_owned()),
+                &mut video_ids,
+                |_token| {
+                    let response = responses[requests.get().min(responses.len() - 1)].clone();
+                    requests.set(requests.get() + 1);
+                    async move { Ok(response) }
+                },
+            ))
+            .unwrap();
+            (video_ids, requests.get())
+        };
+
+        // the same token again
+        assert_eq!(
+            follow(vec![batch("second", "b"), batch("third", "b")]),
+            (
+                vec!["first".to_owned(), "second".to_owned(), "third".to_owned()],
+                2
+            )
+        );
+        // a batch without videos
+        assert_eq!(
+            follow(vec![json!({ "items": [
+                { "continuationItemRenderer": { "continuationEndpoint": {
+                    "continuationCommand": { "token": "b" } } } },
+            ]})]),
+            (vec!["first".to_owned()], 1)
+        );
+    }
+}
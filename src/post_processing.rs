@@ -0,0 +1,75 @@
+use crate::post_processor::{
+    EmbedMetadata, EmbedSubtitles, Exec, ExtractAudio, Merge, MoveToFinalDir, Pipeline, Remux,
+    RemuxFormat,
+};
+use crate::{AudioExtraction, SubtitleOptions};
+use std::path::PathBuf;
+
+// What happens to a downloaded file before it is final.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostProcessing {
+    pub remux: Option<RemuxFormat>,
+    pub audio_extraction: Option<AudioExtraction>,
+    // title, uploader, upload date, description and URL
+    pub embed_metadata: bool,
+    pub embed_thumbnail: bool,
+    pub subtitles: Option<SubtitleOptions>,
+    // where files are downloaded and processed, before being moved to the output directory
+    pub temp_dir: Option<PathBuf>,
+    // a shell command run on each final file
+    pub exec: Option<String>,
+}
+
+impl PostProcessing {
+    // Merging and moving the file are always part of it, but only run when needed.
+    pub(crate) fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::default();
+        pipeline.push(Merge);
+        if let Some(format) = self.remux {
+            pipeline.push(Remux(format));
+        }
+        if let Some(audio_extraction) = self.audio_extraction {
+            pipeline.push(ExtractAudio(audio_extraction));
+        }
+        if self.embed_metadata || self.embed_thumbnail {
+            pipeline.push(EmbedMetadata {
+                metadata: self.embed_metadata,
+                thumbnail: self.embed_thumbnail,
+                audio_only: self.audio_extraction.is_some(),
+            });
+        }
+        if let Some(subtitles) = &self.subtitles {
+            pipeline.push(EmbedSubtitles(subtitles.clone()));
+        }
+        pipeline.push(MoveToFinalDir);
+        if let Some(command) = &self.exec {
+            pipeline.push(Exec(command.to_owned()));
+        }
+        pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_the_steps() {
+        let post_processing = PostProcessing {
+            remux: Some(RemuxFormat::Mkv),
+            embed_metadata: true,
+            exec: Some("echo".to_owned()),
+            ..PostProcessing::default()
+        };
+        assert_eq!(
+            post_processing.pipeline().steps(),
+            vec![
+                "Merging video and audio",
+                "Remuxing video",
+                "Embedding metadata",
+                "Moving to the output directory",
+                "Running command",
+            ]
+        );
+    }
+}
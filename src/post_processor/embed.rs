@@ -0,0 +1,61 @@
+use super::{Context, PostProcessor};
+use crate::models::{ChosenFormat, StreamKind};
+use crate::{embed, subtitles, SubtitleOptions, YouDlError};
+use futures::future::BoxFuture;
+
+// `--embed-metadata` and `--embed-thumbnail`.
+pub struct EmbedMetadata {
+    pub metadata: bool,
+    pub thumbnail: bool,
+    // the video was dropped by audio extraction
+    pub audio_only: bool,
+}
+
+impl PostProcessor for EmbedMetadata {
+    fn step(&self) -> &'static str {
+        "Embedding metadata"
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            // merged files take their metadata from the video stream, like their name
+            let (download_option, has_video) = match context.chosen_format {
+                ChosenFormat::Single(option) => (
+                    option,
+                    option.kind != StreamKind::AudioOnly && !self.audio_only,
+                ),
+                ChosenFormat::Merge { video, .. } => (video, !self.audio_only),
+            };
+            embed::embed(
+                &context.path,
+                download_option,
+                has_video,
+                self.metadata,
+                self.thumbnail,
+            )
+            .await
+        })
+    }
+}
+
+// `--write-subs` and `--embed-subs`: the written subtitles follow the file.
+pub struct EmbedSubtitles(pub SubtitleOptions);
+
+impl PostProcessor for EmbedSubtitles {
+    fn step(&self) -> &'static str {
+        if self.0.embed {
+            "Embedding subtitles"
+        } else {
+            "Writing subtitles"
+        }
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            let written =
+                subtitles::process(&context.path, context.subtitle_tracks, &self.0).await?;
+            context.sidecar_files.extend(written);
+            Ok(())
+        })
+    }
+}
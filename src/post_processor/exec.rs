@@ -0,0 +1,85 @@
+use super::{Context, PostProcessor};
+use crate::YouDlError;
+use futures::future::BoxFuture;
+use smol::process;
+use std::path::Path;
+
+// `--exec`: runs a shell command on the final file, like youtube-dl does.
+// "{}" is replaced by the quoted path, which is otherwise appended to the command.
+pub struct Exec(pub String);
+
+impl PostProcessor for Exec {
+    fn step(&self) -> &'static str {
+        "Running command"
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            let command = command_line(&self.0, &context.path);
+            let process_output = shell(&command)
+                .output()
+                .await
+                .map_err(|e| YouDlError::Application(e.to_string()))?;
+            if !process_output.status.success() {
+                return Err(YouDlError::Application(format!(
+                    "\"{}\" failed ({}): {}",
+                    command,
+                    process_output.status,
+                    String::from_utf8_lossy(&process_output.stderr).trim()
+                )));
+            }
+            Ok(())
+        })
+    }
+}
+
+fn command_line(command: &str, path: &Path) -> String {
+    let path = quote(&path.to_string_lossy());
+    if command.contains("{}") {
+        command.replace("{}", &path)
+    } else {
+        format!("{} {}", command, path)
+    }
+}
+
+#[cfg(not(windows))]
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path)
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_the_quoted_path() {
+        let path = Path::new("/videos/Don't Stop.mp4");
+        assert_eq!(
+            command_line("mpv {} --fs", path),
+            r"mpv '/videos/Don'\''t Stop.mp4' --fs"
+        );
+        assert_eq!(
+            command_line("ls -l", path),
+            r"ls -l '/videos/Don'\''t Stop.mp4'"
+        );
+    }
+}
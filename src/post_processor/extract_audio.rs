@@ -0,0 +1,41 @@
+use super::{Context, PostProcessor};
+use crate::models::{self, ChosenFormat};
+use crate::{ffmpeg, AudioExtraction, YouDlError};
+use futures::future::BoxFuture;
+use smol::fs;
+
+// Converts the downloaded audio stream, dropping any video.
+pub struct ExtractAudio(pub AudioExtraction);
+
+impl PostProcessor for ExtractAudio {
+    fn step(&self) -> &'static str {
+        "Extracting audio"
+    }
+
+    fn applies(&self, context: &Context<'_>) -> bool {
+        context.streams.len() == 1
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            let mime_type = match context.chosen_format {
+                ChosenFormat::Single(option) => &option.mime_type,
+                ChosenFormat::Merge { audio, .. } => &audio.mime_type,
+            };
+            let codecs = models::codecs_from_mime_type(mime_type);
+            let downloaded_path = context.streams.remove(0);
+            let extracted = ffmpeg::extract_audio(
+                &downloaded_path,
+                &context.path,
+                &self.0,
+                codecs.as_deref().unwrap_or_default(),
+            )
+            .await;
+            // like merging, the downloaded file is removed even if extraction failed
+            fs::remove_file(&downloaded_path)
+                .await
+                .map_err(|e| YouDlError::Application(e.to_string()))?;
+            extracted
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use super::{Context, PostProcessor};
+use crate::{ffmpeg, YouDlError};
+use futures::future::BoxFuture;
+use smol::fs;
+
+// Joins the video and audio streams into a single file, without re-encoding.
+pub struct Merge;
+
+impl PostProcessor for Merge {
+    fn step(&self) -> &'static str {
+        "Merging video and audio"
+    }
+
+    fn applies(&self, context: &Context<'_>) -> bool {
+        context.streams.len() == 2
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            let (video_path, audio_path) = (&context.streams[0], &context.streams[1]);
+            let merged = ffmpeg::merge(video_path, audio_path, &context.path).await;
+            // the intermediate files are cleaned up whether merging succeeded or not
+            for path in context.streams.drain(..) {
+                fs::remove_file(path)
+                    .await
+                    .map_err(|e| YouDlError::Application(e.to_string()))?;
+            }
+            merged
+        })
+    }
+}
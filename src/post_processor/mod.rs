@@ -0,0 +1,139 @@
+use crate::models::ChosenFormat;
+use crate::{ProgressEvent, ProgressObserver, SubtitleTrack, YouDlError};
+use futures::future::BoxFuture;
+use std::path::PathBuf;
+
+mod embed;
+mod exec;
+mod extract_audio;
+mod merge;
+mod move_to_final_dir;
+mod remux;
+pub use embed::{EmbedMetadata, EmbedSubtitles};
+pub use exec::Exec;
+pub use extract_audio::ExtractAudio;
+pub use merge::Merge;
+pub use move_to_final_dir::MoveToFinalDir;
+pub use remux::{Remux, RemuxFormat};
+
+// A download on its way through the pipeline.
+pub struct Context<'a> {
+    pub chosen_format: &'a ChosenFormat,
+    pub subtitle_tracks: &'a [SubtitleTrack],
+    // downloaded streams that still have to be turned into `path`:
+    // the video and the audio to merge, or the audio to extract
+    pub streams: Vec<PathBuf>,
+    // the file being processed, in the working directory
+    pub path: PathBuf,
+    // files written next to it, which follow it into the final directory, e.g. subtitles
+    pub sidecar_files: Vec<PathBuf>,
+    // where the file ends up once processed
+    pub final_path: PathBuf,
+}
+
+pub trait PostProcessor: Send + Sync {
+    // shown while it runs, and in its errors, e.g. "Merging video and audio"
+    fn step(&self) -> &'static str;
+
+    // whether there is anything to do for this download
+    fn applies(&self, _context: &Context<'_>) -> bool {
+        true
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>>;
+}
+
+// Post-processors run in order, each one on the output of the previous one.
+#[derive(Default)]
+pub struct Pipeline(Vec<Box<dyn PostProcessor>>);
+
+impl Pipeline {
+    pub fn push(&mut self, post_processor: impl PostProcessor + 'static) {
+        self.0.push(Box::new(post_processor));
+    }
+
+    #[cfg(test)]
+    pub fn steps(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .map(|post_processor| post_processor.step())
+            .collect()
+    }
+
+    pub async fn run(
+        &self,
+        context: &mut Context<'_>,
+        observer: &dyn ProgressObserver,
+    ) -> Result<(), YouDlError> {
+        for post_processor in self.0.iter() {
+            if !post_processor.applies(context) {
+                continue;
+            }
+            observer.on_event(ProgressEvent::PostProcessing {
+                step: post_processor.step().to_owned(),
+            });
+            post_processor.run(context).await.map_err(|e| {
+                YouDlError::PostProcessing(post_processor.step().to_owned(), e.to_string())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use crate::models::DownloadOption;
+    use crate::test_dir::TempDir;
+    use crate::{NoProgress, PostProcessing};
+    use std::fs;
+
+    #[test]
+    fn moves_the_files_then_runs_the_command() {
+        smol::block_on(async {
+            let dir = TempDir::new("pipeline");
+            let (work_dir, output_dir) = (dir.join("work"), dir.join("output"));
+            fs::create_dir_all(&work_dir).unwrap();
+            fs::create_dir_all(&output_dir).unwrap();
+            fs::write(work_dir.join("Title.mp4"), "video").unwrap();
+            fs::write(work_dir.join("Title.en.srt"), "subtitles").unwrap();
+
+            let chosen_format = ChosenFormat::Single(DownloadOption::fixture());
+            let mut context = Context {
+                chosen_format: &chosen_format,
+                subtitle_tracks: &[],
+                streams: Vec::new(),
+                path: work_dir.join("Title.mp4"),
+                sidecar_files: vec![work_dir.join("Title.en.srt")],
+                final_path: output_dir.join("Title.mp4"),
+            };
+            let log_path = dir.join("log");
+            let post_processing = |command: String| PostProcessing {
+                exec: Some(command),
+                ..PostProcessing::default()
+            };
+
+            post_processing(format!("cat {{}} >> {}", log_path.display()))
+                .pipeline()
+                .run(&mut context, &NoProgress)
+                .await
+                .unwrap();
+            assert_eq!(context.path, output_dir.join("Title.mp4"));
+            assert_eq!(fs::read_to_string(&log_path).unwrap(), "video");
+            assert_eq!(
+                fs::read_to_string(output_dir.join("Title.en.srt")).unwrap(),
+                "subtitles"
+            );
+            assert!(!work_dir.join("Title.mp4").exists());
+
+            match post_processing("exit 3".to_owned())
+                .pipeline()
+                .run(&mut context, &NoProgress)
+                .await
+            {
+                Err(YouDlError::PostProcessing(step, _)) => assert_eq!(step, "Running command"),
+                result => panic!("unexpected result {:?}", result),
+            }
+        });
+    }
+}
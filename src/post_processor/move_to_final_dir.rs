@@ -0,0 +1,94 @@
+use super::{Context, PostProcessor};
+use crate::{filename, YouDlError};
+use futures::future::BoxFuture;
+use smol::fs;
+use std::path::{Path, PathBuf};
+
+// Moves the processed file, and the files written next to it, from the working directory (`--temp-dir`)
+// to the output directory, or to the name it was meant to have.
+pub struct MoveToFinalDir;
+
+impl PostProcessor for MoveToFinalDir {
+    fn step(&self) -> &'static str {
+        "Moving to the output directory"
+    }
+
+    fn applies(&self, context: &Context<'_>) -> bool {
+        context.path != context.final_path
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            // another download may have taken the name in the meantime
+            let final_path = filename::avoid_collision(&context.final_path).await;
+            move_file(&context.path, &final_path).await?;
+            let mut moved_sidecar_files = Vec::new();
+            for sidecar_file in context.sidecar_files.drain(..) {
+                let target = sidecar_target(&context.path, &final_path, &sidecar_file);
+                move_file(&sidecar_file, &target).await?;
+                moved_sidecar_files.push(target);
+            }
+
+            context.path = final_path.clone();
+            context.final_path = final_path;
+            context.sidecar_files = moved_sidecar_files;
+            Ok(())
+        })
+    }
+}
+
+// Sidecar files are named after the file, e.g. "<name>.en.srt" for "<name>.mp4", and get renamed along with it.
+fn sidecar_target(path: &Path, final_path: &Path, sidecar_file: &Path) -> PathBuf {
+    let file_name = sidecar_file
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let target_name = match file_name.strip_prefix(&stem(path)) {
+        Some(suffix) if suffix.starts_with('.') => format!("{}{}", stem(final_path), suffix),
+        _ => file_name,
+    };
+    final_path.with_file_name(target_name)
+}
+
+// Renaming fails across file systems, e.g. from a tmpfs, where the file is copied instead.
+async fn move_file(from: &Path, to: &Path) -> Result<(), YouDlError> {
+    if fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))?;
+    fs::remove_file(from)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_sidecar_files_with_the_file() {
+        assert_eq!(
+            sidecar_target(
+                Path::new("/tmp/work/Title.mp4"),
+                Path::new("/videos/Title (1).mp4"),
+                Path::new("/tmp/work/Title.en.srt"),
+            ),
+            Path::new("/videos/Title (1).en.srt")
+        );
+        assert_eq!(
+            sidecar_target(
+                Path::new("/tmp/work/Title.mp4"),
+                Path::new("/videos/Title.mp4"),
+                Path::new("/tmp/work/cover.jpg"),
+            ),
+            Path::new("/videos/cover.jpg")
+        );
+    }
+}
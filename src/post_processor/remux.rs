@@ -0,0 +1,89 @@
+use super::{Context, PostProcessor};
+use crate::{ffmpeg, filename, YouDlError};
+use futures::future::BoxFuture;
+use smol::fs;
+use std::fmt;
+use std::str::FromStr;
+
+// `--remux-video`: the containers the downloaded streams can be copied into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemuxFormat {
+    Mp4,
+    Mkv,
+    Webm,
+    Mov,
+}
+
+impl RemuxFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            RemuxFormat::Mp4 => "mp4",
+            RemuxFormat::Mkv => "mkv",
+            RemuxFormat::Webm => "webm",
+            RemuxFormat::Mov => "mov",
+        }
+    }
+}
+
+impl FromStr for RemuxFormat {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mp4" => Ok(RemuxFormat::Mp4),
+            "mkv" => Ok(RemuxFormat::Mkv),
+            "webm" => Ok(RemuxFormat::Webm),
+            "mov" => Ok(RemuxFormat::Mov),
+            _ => Err(YouDlError::User(format!(
+                "invalid remux format \"{}\", expected mp4, mkv, webm or mov",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RemuxFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file_extension())
+    }
+}
+
+// Copies the streams of the file into another container, e.g. to play it on devices that only know mp4.
+// Fails when the container doesn't support the codecs, e.g. h264 in webm.
+pub struct Remux(pub RemuxFormat);
+
+impl PostProcessor for Remux {
+    fn step(&self) -> &'static str {
+        "Remuxing video"
+    }
+
+    fn applies(&self, context: &Context<'_>) -> bool {
+        let extension = self.0.file_extension();
+        context.streams.is_empty()
+            && context
+                .path
+                .extension()
+                .is_some_and(|current| current != extension)
+    }
+
+    fn run<'a>(&'a self, context: &'a mut Context<'_>) -> BoxFuture<'a, Result<(), YouDlError>> {
+        Box::pin(async move {
+            let remuxed_path =
+                filename::avoid_collision(&context.path.with_extension(self.0.file_extension()))
+                    .await;
+            if let Err(e) = ffmpeg::remux(&context.path, &remuxed_path).await {
+                let _ = fs::remove_file(&remuxed_path).await;
+                return Err(e);
+            }
+            fs::remove_file(&context.path)
+                .await
+                .map_err(|e| YouDlError::Application(e.to_string()))?;
+
+            if let Some(file_name) = remuxed_path.file_name() {
+                context.final_path = context.final_path.with_file_name(file_name);
+            }
+            context.path = remuxed_path;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,225 @@
+use crate::YouDlError;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+// Caps the bandwidth of every download it is shared with, e.g. all the concurrent downloads of the CLI.
+// Bytes are taken from a token bucket holding up to a second of transfer.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    // bytes per second
+    rate: u64,
+    schedule: Option<RateSchedule>,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // negative once more bytes went through than the rate allows, until the waiting is over
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        RateLimiter {
+            rate,
+            schedule: None,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: rate as f64,
+                refilled_at: Instant::now(),
+            })),
+        }
+    }
+
+    // Only limits the rate during the schedule, e.g. to go full speed at night.
+    pub fn with_schedule(mut self, schedule: RateSchedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    // The limit of each of `processes` downloading at the same time without sharing the bucket,
+    // e.g. youtube-dl processes, so that they don't exceed the rate together.
+    pub fn split_between(&self, processes: usize) -> Self {
+        let rate_limiter = RateLimiter::new((self.rate / processes.max(1) as u64).max(1));
+        match &self.schedule {
+            Some(schedule) => rate_limiter.with_schedule(schedule.clone()),
+            None => rate_limiter,
+        }
+    }
+
+    // The limit in effect right now, in bytes per second.
+    pub fn current_rate(&self) -> Option<u64> {
+        match &self.schedule {
+            Some(schedule) if !schedule.contains(local_minute_of_day()) => None,
+            _ => Some(self.rate),
+        }
+    }
+
+    // Waits until `bytes` more bytes can go through without exceeding the rate.
+    pub async fn acquire(&self, bytes: u64) {
+        let rate = match self.current_rate() {
+            Some(rate) => rate as f64,
+            None => return,
+        };
+        let delay = {
+            let mut bucket = self.bucket.lock().expect("unpoisoned rate limiter");
+            let now = Instant::now();
+            let refilled = now.duration_since(bucket.refilled_at).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refilled).min(rate) - bytes as f64;
+            bucket.refilled_at = now;
+            // the debt is paid by whoever took the last bytes, later ones wait for it too
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / rate)
+            } else {
+                Duration::from_secs(0)
+            }
+        };
+        if delay > Duration::from_secs(0) {
+            smol::Timer::after(delay).await;
+        }
+    }
+}
+
+// Times of the day, in local time, e.g. "08:00-19:00" or "22:00-06:00,12:00-14:00".
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateSchedule(Vec<(u32, u32)>);
+
+impl RateSchedule {
+    // whether the minute of the day (0 is midnight) is in one of the ranges, which can go past midnight
+    pub fn contains(&self, minute: u32) -> bool {
+        self.0.iter().any(|&(start, end)| {
+            if start <= end {
+                start <= minute && minute < end
+            } else {
+                minute >= start || minute < end
+            }
+        })
+    }
+}
+
+impl FromStr for RateSchedule {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || YouDlError::User(format!("invalid schedule \"{}\"", s));
+        // "24:00" ends a range at midnight
+        let parse_time = |time: &str| {
+            let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+            match (hours.parse::<u32>(), minutes.parse::<u32>()) {
+                (Ok(hours), Ok(minutes))
+                    if minutes < 60 && hours * 60 + minutes <= MINUTES_PER_DAY =>
+                {
+                    Ok(hours * 60 + minutes)
+                }
+                _ => Err(invalid()),
+            }
+        };
+
+        s.split(',')
+            .map(|range| {
+                let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+                match (parse_time(start)?, parse_time(end)?) {
+                    (start, end) if start == end => Err(invalid()),
+                    (start, end) => Ok((start % MINUTES_PER_DAY, end)),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(RateSchedule)
+    }
+}
+
+fn local_minute_of_day() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    local_minute_of(now).unwrap_or(((now / 60) % u64::from(MINUTES_PER_DAY)) as u32)
+}
+
+#[cfg(unix)]
+fn local_minute_of(seconds_since_epoch: u64) -> Option<u32> {
+    let time = seconds_since_epoch as libc::time_t;
+    // SAFETY: `localtime_r` only writes to the given `tm`, which is plain data
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((tm.tm_hour * 60 + tm.tm_min) as u32)
+}
+
+// elsewhere, schedules are in UTC (as documented by `--limit-rate-schedule`)
+#[cfg(not(unix))]
+fn local_minute_of(_seconds_since_epoch: u64) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges_going_past_midnight() {
+        let schedule: RateSchedule = "08:00-19:30, 22:00-06:00".parse().unwrap();
+
+        assert_eq!(schedule, RateSchedule(vec![(480, 1170), (1320, 360)]));
+        assert!(schedule.contains(8 * 60));
+        assert!(!schedule.contains(19 * 60 + 30));
+        assert!(schedule.contains(23 * 60));
+        assert!(schedule.contains(5 * 60 + 59));
+        assert!(!schedule.contains(7 * 60));
+        assert!("00:00-24:00"
+            .parse::<RateSchedule>()
+            .unwrap()
+            .contains(23 * 60));
+        assert!("9-17".parse::<RateSchedule>().is_err());
+        assert!("08:00-08:00".parse::<RateSchedule>().is_err());
+        assert!("08:60-09:00".parse::<RateSchedule>().is_err());
+    }
+
+    #[test]
+    fn shares_the_rate_between_concurrent_downloads() {
+        let rate_limiter = RateLimiter::new(100_000);
+        let started_at = Instant::now();
+
+        // a second worth of bytes goes through right away, the next half second is waited for
+        smol::block_on(futures::future::join_all((0..3).map(|_| {
+            let rate_limiter = rate_limiter.clone();
+            smol::spawn(async move {
+                for _ in 0..10 {
+                    rate_limiter.acquire(5_000).await;
+                }
+            })
+        })));
+        let elapsed = started_at.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn splits_the_rate_between_processes() {
+        let schedule: RateSchedule = "08:00-19:00".parse().unwrap();
+        let rate_limiter = RateLimiter::new(1_000_000).with_schedule(schedule.clone());
+
+        let split = rate_limiter.split_between(3);
+        assert_eq!(split.rate, 333_333);
+        assert_eq!(split.schedule, Some(schedule));
+        assert_eq!(RateLimiter::new(2).split_between(3).rate, 1);
+    }
+
+    #[test]
+    fn goes_full_speed_outside_of_the_schedule() {
+        let now = local_minute_of_day();
+        let schedule = RateSchedule(vec![(
+            (now + 60) % MINUTES_PER_DAY,
+            (now + 120) % MINUTES_PER_DAY,
+        )]);
+        let rate_limiter = RateLimiter::new(1).with_schedule(schedule);
+
+        assert_eq!(rate_limiter.current_rate(), None);
+        let started_at = Instant::now();
+        smol::block_on(rate_limiter.acquire(1_000_000));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::YouDlError;
+use async_compat::CompatExt;
+use reqwest::{header, StatusCode};
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+// the first retry waits about this long, and each next one twice as long
+const INITIAL_DELAY: Duration = if cfg!(test) {
+    Duration::from_millis(1)
+} else {
+    Duration::from_secs(1)
+};
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Sends a request, failing with a retryable error on timeouts, dropped connections, 5xx and 429 responses.
+// Other error statuses are left to the caller, e.g. a 416 for a range past the end of the file.
+pub async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, YouDlError> {
+    let response = request.send().compat().await.map_err(from_reqwest)?;
+    let status = response.status();
+    if is_retryable_status(status) {
+        return Err(YouDlError::Transient(
+            format!("{} from {}", status, response.url()),
+            retry_after(&response),
+        ));
+    }
+    Ok(response)
+}
+
+pub fn from_reqwest(e: reqwest::Error) -> YouDlError {
+    let retryable = match e.status() {
+        Some(status) => is_retryable_status(status),
+        // decoding and redirect errors would happen again
+        None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+    };
+    if retryable {
+        YouDlError::Transient(e.to_string(), None)
+    } else {
+        YouDlError::InvalidResponse(e.to_string())
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+// either a number of seconds or a date, e.g. "120" or "Wed, 21 Oct 2015 07:28:00 GMT"
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    match value.trim().parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value.trim()).ok().map(|date| {
+            date.duration_since(SystemTime::now())
+                .unwrap_or(Duration::from_secs(0))
+        }),
+    }
+}
+
+// Runs `attempt` until it succeeds, fails for good, or `retries` retries are used up.
+pub async fn with_retries<T, F, Fut>(retries: u32, mut attempt: F) -> Result<T, YouDlError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, YouDlError>>,
+{
+    let mut retried = 0;
+    loop {
+        match attempt().await {
+            Err(e) if e.is_retryable() && retried < retries => {
+                wait(&e, retried, retries).await;
+                retried += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Waits before the retry number `retried + 1`.
+pub async fn wait(error: &YouDlError, retried: u32, retries: u32) {
+    let delay = delay(error, retried);
+    warn!(
+        "{} (retry {}/{} in {:.1}s)",
+        error,
+        retried + 1,
+        retries,
+        delay.as_secs_f64()
+    );
+    smol::Timer::after(delay).await;
+}
+
+// As long as the server asked, up to `MAX_DELAY`, or else with an exponential backoff.
+fn delay(error: &YouDlError, retried: u32) -> Duration {
+    match error {
+        YouDlError::Transient(_, Some(retry_after)) => (*retry_after).min(MAX_DELAY),
+        _ => backoff(retried),
+    }
+}
+
+// Between half and all of the exponential delay, so that concurrent downloads don't retry in lockstep.
+fn backoff(retried: u32) -> Duration {
+    let delay = INITIAL_DELAY
+        .checked_mul(2u32.saturating_pow(retried))
+        .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY));
+    delay.mul_f64(0.5 + fastrand::f64() / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn doubles_the_delay_up_to_a_limit() {
+        for retried in 0..3 {
+            let delay = backoff(retried);
+            let full_delay = INITIAL_DELAY * 2u32.pow(retried);
+            assert!(delay >= full_delay / 2 && delay <= full_delay);
+        }
+        assert!(backoff(100) <= MAX_DELAY);
+    }
+
+    #[test]
+    fn waits_as_asked_but_not_for_hours() {
+        let asking = |retry_after| YouDlError::Transient("429".to_owned(), Some(retry_after));
+
+        assert_eq!(
+            delay(&asking(Duration::from_secs(5)), 0),
+            Duration::from_secs(5)
+        );
+        assert_eq!(delay(&asking(Duration::from_secs(3 * 3600)), 0), MAX_DELAY);
+    }
+
+    #[test]
+    fn retries_only_the_transient_failures() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted_requests = Arc::clone(&requests);
+        let base_url = test_server::start(move |request| {
+            let count = counted_requests.fetch_add(1, Ordering::SeqCst);
+            match (request.path.as_str(), count) {
+                ("/flaky", 0) => Response::status(503),
+                ("/flaky", 1) => {
+                    let mut response = Response::status(429);
+                    response
+                        .headers
+                        .push(("Retry-After".to_owned(), "0".to_owned()));
+                    response
+                }
+                ("/flaky", _) => Response::ok("done"),
+                _ => Response::status(404),
+            }
+        });
+        let get = |path: &str| {
+            let url = format!("{}{}", base_url, path);
+            smol::block_on(with_retries(5, || async {
+                send(reqwest::Client::new().get(&url))
+                    .await?
+                    .error_for_status()
+                    .map_err(from_reqwest)
+            }))
+        };
+
+        assert_eq!(get("/flaky").unwrap().status(), StatusCode::OK);
+        assert_eq!(requests.swap(0, Ordering::SeqCst), 3);
+        match get("/missing") {
+            Err(YouDlError::InvalidResponse(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let base_url = test_server::start(|_request| Response::status(500));
+        let url = format!("{}/down", base_url);
+
+        match smol::block_on(with_retries(2, || send(reqwest::Client::new().get(&url)))) {
+            Err(YouDlError::Transient(reason, None)) => {
+                assert!(
+                    reason.starts_with("500 Internal Server Error"),
+                    "{}",
+                    reason
+                )
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use crate::log;
+use crate::part_file::PartFile;
+use crate::retry;
+use crate::ui::TransferProgress;
+use crate::{RateLimiter, YouDlError};
+use futures_util::StreamExt;
+use reqwest::{header, StatusCode};
+use smol::fs;
+use smol::io::{AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// The settings of every stream transfer, sequential or segmented: splitting, retries and rate limiting.
+#[derive(Debug, Clone)]
+pub struct DownloadSettings {
+    // number of concurrent connections; with 1, streams are downloaded sequentially
+    pub segments: usize,
+    // bytes requested by each "Range" request
+    pub chunk_size: u64,
+    // for the requests opening a stream
+    pub retries: u32,
+    // for each interruption of a stream, or each chunk of a segmented download
+    pub fragment_retries: u32,
+    // shared by every stream it is given to
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        DownloadSettings {
+            segments: 1,
+            chunk_size: 10 * 1024 * 1024,
+            retries: 10,
+            fragment_retries: 10,
+            rate_limiter: None,
+        }
+    }
+}
+
+// Downloads `url` into the (already created) segmented part file, skipping the `completed` ranges.
+// The stream is cut into `chunk_size`-long ranges, which are fetched by `segments` tasks
+// running concurrently on the smol executor and written at their own offset.
+pub async fn download(
+    url: &str,
+    part_file: &PartFile,
+    total: u64,
+    completed: HashSet<(u64, u64)>,
+    settings: &DownloadSettings,
+    progress: &Arc<TransferProgress>,
+) -> Result<(), YouDlError> {
+    let next_chunk_start = Arc::new(AtomicU64::new(0));
+    let completed = Arc::new(completed);
+    let tasks: Vec<_> = (0..settings.segments)
+        .map(|_| {
+            // the tasks log to the logger of their download, from whatever thread runs them
+            smol::spawn(log::scoped(
+                log::scoped_logger(),
+                fetch_chunks(
+                    url.to_owned(),
+                    part_file.clone(),
+                    total,
+                    Arc::clone(&completed),
+                    settings.clone(),
+                    Arc::clone(&next_chunk_start),
+                    Arc::clone(progress),
+                ),
+            ))
+        })
+        .collect();
+
+    for result in futures::future::join_all(tasks).await {
+        result?;
+    }
+    Ok(())
+}
+
+// Keeps fetching the next chunk that no other task took yet, until the whole stream is covered.
+// An interrupted chunk is requested again from its first missing byte,
+// and a complete one is recorded in the part file once it's on disk.
+async fn fetch_chunks(
+    url: String,
+    part_file: PartFile,
+    total: u64,
+    completed: Arc<HashSet<(u64, u64)>>,
+    settings: DownloadSettings,
+    next_chunk_start: Arc<AtomicU64>,
+    progress: Arc<TransferProgress>,
+) -> Result<(), YouDlError> {
+    let client = reqwest::Client::new();
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(part_file.part_path())
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))?;
+
+    loop {
+        let start = next_chunk_start.fetch_add(settings.chunk_size, Ordering::SeqCst);
+        if start >= total {
+            break;
+        }
+        let end = (start + settings.chunk_size).min(total) - 1;
+        if completed.contains(&(start, end)) {
+            continue;
+        }
+
+        let mut position = start;
+        let mut retried = 0;
+        loop {
+            let fetched = fetch_range(
+                &client,
+                &url,
+                &mut file,
+                &mut position,
+                end,
+                settings.rate_limiter.as_ref(),
+                &progress,
+            );
+            match fetched.await {
+                Ok(()) => break,
+                Err(e) if e.is_retryable() && retried < settings.fragment_retries => {
+                    retry::wait(&e, retried, settings.fragment_retries).await;
+                    retried += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        file.flush()
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        part_file.record_range(start, end).await?;
+    }
+    Ok(())
+}
+
+// Writes the bytes from `position` to `end` (included) at their offset, moving `position` past the written ones.
+// A response that ends early is an interruption; one that doesn't match the range is invalid.
+async fn fetch_range(
+    client: &reqwest::Client,
+    url: &str,
+    file: &mut fs::File,
+    position: &mut u64,
+    end: u64,
+    rate_limiter: Option<&RateLimiter>,
+    progress: &TransferProgress,
+) -> Result<(), YouDlError> {
+    let response = retry::send(
+        client
+            .get(url)
+            .header(header::RANGE, format!("bytes={}-{}", position, end)),
+    )
+    .await?
+    .error_for_status()
+    .map_err(retry::from_reqwest)?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(YouDlError::InvalidResponse(
+            "the server does not support range requests".to_owned(),
+        ));
+    }
+    let content_range = response
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|content_range| content_range.to_str().ok())
+        .unwrap_or_default();
+    if !content_range.starts_with(&format!("bytes {}-", position)) {
+        return Err(YouDlError::InvalidResponse(format!(
+            "asked for bytes {}-{}, got \"{}\"",
+            position, end, content_range
+        )));
+    }
+
+    file.seek(SeekFrom::Start(*position))
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))?;
+    let mut bytes_stream = response.bytes_stream();
+    while let Some(bytes) = bytes_stream.next().await {
+        let bytes = bytes.map_err(retry::from_reqwest)?;
+        if *position + bytes.len() as u64 > end + 1 {
+            return Err(YouDlError::InvalidResponse(
+                "the response is longer than the requested range".to_owned(),
+            ));
+        }
+        if let Some(rate_limiter) = rate_limiter {
+            rate_limiter.acquire(bytes.len() as u64).await;
+        }
+        file.write_all(&bytes)
+            .await
+            .map_err(|e| YouDlError::Application(e.to_string()))?;
+        *position += bytes.len() as u64;
+        progress.advance(bytes.len().try_into().expect("valid conversion"));
+    }
+    if *position > end {
+        Ok(())
+    } else {
+        Err(YouDlError::Transient(
+            format!("the response ended {} bytes early", end + 1 - *position),
+            None,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use crate::{test_server, NoProgress, ProgressEvent};
+    use std::sync::Mutex;
+
+    // a part file that is ready for a segmented download of `total` bytes
+    async fn create_part_file(name: &str, total: u64) -> (TempDir, PartFile) {
+        let dir = TempDir::new(name);
+        let part_file = PartFile::new(&dir.join("video.mp4"));
+        part_file.create_segmented(total).await.unwrap();
+        (dir, part_file)
+    }
+
+    #[test]
+    fn writes_every_chunk_at_its_offset() {
+        let body: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let served_body = body.clone();
+        let base_url =
+            test_server::start(move |request| test_server::serve_ranges(request, &served_body));
+
+        smol::block_on(async {
+            let (_dir, part_file) = create_part_file("segmented", 1000).await;
+            let settings = DownloadSettings {
+                segments: 3,
+                chunk_size: 64,
+                ..DownloadSettings::default()
+            };
+            let (sender, receiver) = smol::channel::unbounded();
+            let progress = Arc::new(TransferProgress::new(Arc::new(sender)));
+            progress.start(Some(1000), 0);
+
+            let url = format!("{}/videoplayback", base_url);
+            download(&url, &part_file, 1000, HashSet::new(), &settings, &progress)
+                .await
+                .unwrap();
+            assert_eq!(fs::read(part_file.part_path()).await.unwrap(), body);
+            let mut last_event = None;
+            while let Ok(event) = receiver.try_recv() {
+                last_event = Some(event);
+            }
+            match last_event {
+                Some(ProgressEvent::Progress { bytes, .. }) => assert_eq!(bytes, 1000),
+                event => panic!("unexpected event: {:?}", event),
+            }
+        })
+    }
+
+    #[test]
+    fn retries_the_failed_chunks_from_their_last_byte() {
+        let body: Vec<u8> = (0..=255).cycle().take(300).collect();
+        let served_body = body.clone();
+        let failed = Arc::new(Mutex::new(HashSet::new()));
+        let base_url = test_server::start(move |request| {
+            let range = request.headers.get("range").cloned().unwrap_or_default();
+            let first_try = failed.lock().unwrap().insert(range.to_owned());
+            let mut response = test_server::serve_ranges(request, &served_body);
+            match range.as_str() {
+                "bytes=0-99" if first_try => test_server::Response::status(503),
+                "bytes=100-199" if first_try => {
+                    response.cut_after = Some(30);
+                    response
+                }
+                // a complete response, only shorter than the range
+                "bytes=200-299" if first_try => {
+                    response.body.truncate(50);
+                    response
+                }
+                _ => response,
+            }
+        });
+
+        smol::block_on(async {
+            let (_dir, part_file) = create_part_file("retried", 300).await;
+            let settings = DownloadSettings {
+                segments: 2,
+                chunk_size: 100,
+                ..DownloadSettings::default()
+            };
+            let progress = Arc::new(TransferProgress::new(Arc::new(NoProgress)));
+
+            let url = format!("{}/videoplayback", base_url);
+            download(&url, &part_file, 300, HashSet::new(), &settings, &progress)
+                .await
+                .unwrap();
+            assert_eq!(fs::read(part_file.part_path()).await.unwrap(), body);
+        })
+    }
+
+    #[test]
+    fn resumes_with_the_chunks_a_previous_run_missed() {
+        let body: Vec<u8> = (0..=255).cycle().take(300).collect();
+        let served_body = body.clone();
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let failing = Arc::new(Mutex::new(true));
+        let base_url = {
+            let requested = Arc::clone(&requested);
+            let failing = Arc::clone(&failing);
+            test_server::start(move |request| {
+                let range = request.headers.get("range").cloned().unwrap_or_default();
+                requested.lock().unwrap().push(range.to_owned());
+                match range.as_str() {
+                    "bytes=100-199" if *failing.lock().unwrap() => {
+                        test_server::Response::status(404)
+                    }
+                    _ => test_server::serve_ranges(request, &served_body),
+                }
+            })
+        };
+
+        smol::block_on(async {
+            let (_dir, part_file) = create_part_file("resumed-segments", 300).await;
+            let settings = DownloadSettings {
+                segments: 2,
+                chunk_size: 100,
+                ..DownloadSettings::default()
+            };
+            let progress = Arc::new(TransferProgress::new(Arc::new(NoProgress)));
+            let url = format!("{}/videoplayback", base_url);
+            assert!(
+                download(&url, &part_file, 300, HashSet::new(), &settings, &progress)
+                    .await
+                    .is_err()
+            );
+
+            // another run, e.g. after the download was interrupted
+            *failing.lock().unwrap() = false;
+            requested.lock().unwrap().clear();
+            assert!(part_file.completed_ranges(299).await.is_none());
+            let completed = part_file.completed_ranges(300).await.unwrap();
+            assert!(completed.contains(&(0, 99)));
+            assert!(!completed.contains(&(100, 199)));
+            download(&url, &part_file, 300, completed, &settings, &progress)
+                .await
+                .unwrap();
+            assert!(requested
+                .lock()
+                .unwrap()
+                .contains(&"bytes=100-199".to_owned()));
+            assert!(!requested.lock().unwrap().contains(&"bytes=0-99".to_owned()));
+            assert_eq!(fs::read(part_file.part_path()).await.unwrap(), body);
+        })
+    }
+
+    #[test]
+    fn gives_up_on_a_chunk_after_its_last_retry() {
+        let base_url = test_server::start(|_request| test_server::Response::status(503));
+
+        smol::block_on(async {
+            let (_dir, part_file) = create_part_file("given-up", 300).await;
+            let settings = DownloadSettings {
+                fragment_retries: 2,
+                ..DownloadSettings::default()
+            };
+            let progress = Arc::new(TransferProgress::new(Arc::new(NoProgress)));
+
+            let url = format!("{}/videoplayback", base_url);
+            match download(&url, &part_file, 300, HashSet::new(), &settings, &progress).await {
+                Err(YouDlError::Transient(..)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use super::timedtext::Cue;
+use std::fmt::Write;
+
+// SubRip: numbered cues, with a comma before the milliseconds.
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            srt,
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            timestamp(cue.start, ','),
+            timestamp(cue.end, ','),
+            cue.text
+        );
+    }
+    srt
+}
+
+// WebVTT: unlike SubRip, the text is markup, so `&` and `<` must be escaped.
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        let _ = write!(
+            vtt,
+            "{} --> {}\n{}\n\n",
+            timestamp(cue.start, '.'),
+            timestamp(cue.end, '.'),
+            cue.text.replace('&', "&amp;").replace('<', "&lt;")
+        );
+    }
+    vtt
+}
+
+// HH:MM:SS followed by the milliseconds
+fn timestamp(ms: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::timedtext;
+    use super::*;
+
+    #[test]
+    fn converts_timedtext_to_srt_and_vtt() {
+        let cues = timedtext::parse_xml(include_str!("fixtures/timedtext.xml")).unwrap();
+        assert_eq!(to_srt(&cues), include_str!("fixtures/expected.srt"));
+        assert_eq!(to_vtt(&cues), include_str!("fixtures/expected.vtt"));
+
+        let cues = timedtext::parse_json3(include_str!("fixtures/timedtext.json3")).unwrap();
+        assert_eq!(to_srt(&cues), include_str!("fixtures/expected.srt"));
+        assert_eq!(to_vtt(&cues), include_str!("fixtures/expected.vtt"));
+    }
+}
@@ -0,0 +1,325 @@
+mod convert;
+mod timedtext;
+
+use crate::models::{CaptionTrack, PlayerResponse};
+use crate::utils::YOUTUBE_URL;
+use crate::{ffmpeg, YouDlError};
+use async_compat::CompatExt;
+use serde::Serialize;
+use smol::fs;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+impl FromStr for SubtitleFormat {
+    type Err = YouDlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "srt" => Ok(SubtitleFormat::Srt),
+            "vtt" => Ok(SubtitleFormat::Vtt),
+            _ => Err(YouDlError::User(format!(
+                "invalid subtitle format \"{}\", expected srt or vtt",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SubtitleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file_extension())
+    }
+}
+
+// `--write-subs`, `--write-auto-subs` and `--embed-subs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleOptions {
+    // language codes, e.g. "en" or "pt-BR"; `None` for all of them
+    pub languages: Option<Vec<String>>,
+    // used for the languages without subtitles written by the uploader
+    pub auto_generated: bool,
+    pub format: SubtitleFormat,
+    // kept next to the downloaded file, e.g. "<name>.en.srt", instead of being removed once embedded
+    pub write: bool,
+    pub embed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubtitleTrack {
+    pub language_code: String,
+    // e.g. "English (auto-generated)"
+    pub name: String,
+    pub auto_generated: bool,
+    // in YouTube's timedtext formats
+    pub url: String,
+}
+
+impl From<&CaptionTrack> for SubtitleTrack {
+    fn from(track: &CaptionTrack) -> Self {
+        let name = track
+            .name
+            .as_ref()
+            .and_then(|name| {
+                name.simple_text
+                    .clone()
+                    .or_else(|| Some(name.runs.iter().map(|run| run.text.as_str()).collect()))
+            })
+            .filter(|name: &String| !name.is_empty())
+            .unwrap_or_else(|| track.language_code.clone());
+        let url = if track.base_url.starts_with('/') {
+            format!("{}{}", YOUTUBE_URL, track.base_url)
+        } else {
+            track.base_url.clone()
+        };
+        SubtitleTrack {
+            language_code: track.language_code.clone(),
+            name,
+            auto_generated: track.kind.as_deref() == Some("asr"),
+            url,
+        }
+    }
+}
+
+pub fn tracks(player_response: &PlayerResponse) -> Vec<SubtitleTrack> {
+    player_response
+        .captions
+        .as_ref()
+        .map(|captions| {
+            captions
+                .player_captions_tracklist_renderer
+                .caption_tracks
+                .iter()
+                .map(SubtitleTrack::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// At most one track per language: the uploader's, or else the auto-generated one if wanted.
+fn select<'a>(tracks: &'a [SubtitleTrack], options: &SubtitleOptions) -> Vec<&'a SubtitleTrack> {
+    let wanted = |track: &SubtitleTrack| match &options.languages {
+        Some(languages) => languages
+            .iter()
+            .any(|language| language.eq_ignore_ascii_case(&track.language_code)),
+        None => true,
+    };
+    let uploaded = tracks.iter().filter(|track| !track.auto_generated);
+    let auto_generated = tracks
+        .iter()
+        .filter(|track| track.auto_generated && options.auto_generated);
+    let mut selected: Vec<&SubtitleTrack> = Vec::new();
+    for track in uploaded.chain(auto_generated) {
+        if wanted(track)
+            && !selected
+                .iter()
+                .any(|selected| selected.language_code == track.language_code)
+        {
+            selected.push(track);
+        }
+    }
+    selected
+}
+
+// Downloads the subtitles of the video saved at `path` next to it, and embeds them into it.
+// Returns the subtitle files that are kept.
+pub async fn process(
+    path: &Path,
+    tracks: &[SubtitleTrack],
+    options: &SubtitleOptions,
+) -> Result<Vec<PathBuf>, YouDlError> {
+    let selected = select(tracks, options);
+    if selected.is_empty() {
+        let languages = match &options.languages {
+            Some(languages) => languages.join(", "),
+            None => "any language".to_owned(),
+        };
+        warn!("no subtitles in {} for: {}", languages, path.display());
+        return Ok(Vec::new());
+    }
+
+    let mut subtitle_paths = Vec::new();
+    for track in &selected {
+        let subtitle_path = path.with_extension(format!(
+            "{}.{}",
+            track.language_code,
+            options.format.file_extension()
+        ));
+        write(track, options.format, &subtitle_path).await?;
+        subtitle_paths.push((track.language_code.as_str(), subtitle_path));
+    }
+
+    let embedded = if options.embed {
+        embed(path, &subtitle_paths).await
+    } else {
+        Ok(())
+    };
+    if !options.write {
+        for (_, subtitle_path) in subtitle_paths.drain(..) {
+            fs::remove_file(subtitle_path)
+                .await
+                .map_err(|e| YouDlError::Application(e.to_string()))?;
+        }
+    }
+    embedded.map(|_| {
+        subtitle_paths
+            .into_iter()
+            .map(|(_, subtitle_path)| subtitle_path)
+            .collect()
+    })
+}
+
+// Fetched as JSON3, which keeps the line breaks of auto-generated captions.
+async fn write(
+    track: &SubtitleTrack,
+    format: SubtitleFormat,
+    path: &Path,
+) -> Result<(), YouDlError> {
+    let body = crate::get(&json3_url(&track.url))
+        .await?
+        .text()
+        .compat()
+        .await
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+    let cues = if body.trim_start().starts_with('{') {
+        timedtext::parse_json3(&body)?
+    } else {
+        timedtext::parse_xml(&body)?
+    };
+    let converted = match format {
+        SubtitleFormat::Srt => convert::to_srt(&cues),
+        SubtitleFormat::Vtt => convert::to_vtt(&cues),
+    };
+    fs::write(path, converted)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))
+}
+
+// Replaces the format the URL asks for, e.g. "fmt=srv3", which `timedtext` can't parse.
+fn json3_url(url: &str) -> String {
+    let (base, query) = url.split_once('?').unwrap_or((url, ""));
+    let parameters: Vec<&str> = query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty() && !parameter.starts_with("fmt="))
+        .chain(std::iter::once("fmt=json3"))
+        .collect();
+    format!("{}?{}", base, parameters.join("&"))
+}
+
+async fn embed(path: &Path, subtitle_paths: &[(&str, PathBuf)]) -> Result<(), YouDlError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "mp4" | "m4v" | "mov" | "mkv" | "webm") {
+        warn!("subtitles can't be embedded into .{} files", extension);
+        return Ok(());
+    }
+
+    let subtitles: Vec<(&str, &Path)> = subtitle_paths
+        .iter()
+        .map(|(language, path)| (*language, path.as_path()))
+        .collect();
+    let temp_path = path.with_extension(format!("temp.{}", extension));
+    if let Err(e) = ffmpeg::embed_subtitles(path, &temp_path, &subtitles).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+    fs::rename(&temp_path, path)
+        .await
+        .map_err(|e| YouDlError::Application(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use crate::test_server::{self, Response};
+
+    #[test]
+    fn prefers_the_uploaders_subtitles() {
+        let track = |language_code: &str, auto_generated| SubtitleTrack {
+            language_code: language_code.to_owned(),
+            name: language_code.to_owned(),
+            auto_generated,
+            url: format!(
+                "https://www.youtube.com/api/timedtext?lang={}",
+                language_code
+            ),
+        };
+        let tracks = [
+            track("en", true),
+            track("en", false),
+            track("de", true),
+            track("fr", false),
+        ];
+        let selected = |languages: Option<&[&str]>, auto_generated| {
+            let options = SubtitleOptions {
+                languages: languages
+                    .map(|languages| languages.iter().map(|l| l.to_string()).collect()),
+                auto_generated,
+                format: SubtitleFormat::Srt,
+                write: true,
+                embed: false,
+            };
+            select(&tracks, &options)
+                .into_iter()
+                .map(|track| (track.language_code.as_str(), track.auto_generated))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(selected(Some(&["en", "de"]), false), vec![("en", false)]);
+        assert_eq!(
+            selected(Some(&["en", "DE"]), true),
+            vec![("en", false), ("de", true)]
+        );
+        assert_eq!(
+            selected(None, true),
+            vec![("en", false), ("fr", false), ("de", true)]
+        );
+    }
+
+    #[test]
+    fn fetches_json3_whatever_the_track_asks_for() {
+        let base_url = test_server::start(|request| {
+            if request.path.ends_with("&fmt=json3") && !request.path.contains("srv3") {
+                Response::ok(include_str!("fixtures/timedtext.json3"))
+            } else {
+                Response::ok("<timedtext format=\"3\"><body></body></timedtext>")
+            }
+        });
+        let track = SubtitleTrack {
+            language_code: "en".to_owned(),
+            name: "English".to_owned(),
+            auto_generated: false,
+            url: format!("{}/api/timedtext?v=dQw4w9WgXcQ&fmt=srv3&lang=en", base_url),
+        };
+        let dir = TempDir::new("subtitles");
+        let path = dir.join("video.en.srt");
+
+        smol::block_on(write(&track, SubtitleFormat::Srt, &path)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            include_str!("fixtures/expected.srt")
+        );
+        assert_eq!(
+            json3_url("https://www.youtube.com/api/timedtext?lang=en"),
+            "https://www.youtube.com/api/timedtext?lang=en&fmt=json3"
+        );
+    }
+}
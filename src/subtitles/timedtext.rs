@@ -0,0 +1,179 @@
+use crate::YouDlError;
+use regex::Regex;
+use serde::Deserialize;
+
+// A line of subtitles and when it is shown, in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+// The default format of YouTube's timedtext API:
+// `<transcript><text start="1.23" dur="4.56">Hello &amp;amp; welcome</text>...</transcript>`
+pub fn parse_xml(xml: &str) -> Result<Vec<Cue>, YouDlError> {
+    let text_regex =
+        Regex::new(r#"(?s)<text\s+([^>]*?)/?>(?:(.*?)</text>)?"#).expect("valid regex expression");
+    let attribute_regex = Regex::new(r#"(\w+)="([^"]*)""#).expect("valid regex expression");
+    if !xml.contains("<transcript") {
+        return Err(YouDlError::InvalidResponse(
+            "missing transcript in subtitles".to_owned(),
+        ));
+    }
+
+    let mut cues = Vec::new();
+    for captures in text_regex.captures_iter(xml) {
+        let mut start = None;
+        let mut duration = 0.0;
+        for attribute in attribute_regex.captures_iter(&captures[1]) {
+            match &attribute[1] {
+                "start" => start = attribute[2].parse::<f64>().ok(),
+                "dur" => duration = attribute[2].parse::<f64>().unwrap_or(0.0),
+                _ => {}
+            }
+        }
+        let start = start.ok_or_else(|| {
+            YouDlError::InvalidResponse(format!("invalid subtitle \"{}\"", &captures[0]))
+        })?;
+        // the text is escaped twice, e.g. "&amp;#39;" for an apostrophe
+        let text = captures.get(2).map_or("", |text| text.as_str());
+        let text = decode_entities(&decode_entities(text));
+        push_cue(
+            &mut cues,
+            seconds_to_ms(start),
+            seconds_to_ms(start + duration),
+            &text,
+        );
+    }
+    Ok(cues)
+}
+
+#[derive(Deserialize)]
+struct Json3 {
+    #[serde(default)]
+    events: Vec<Json3Event>,
+}
+
+#[derive(Deserialize)]
+struct Json3Event {
+    #[serde(rename(deserialize = "tStartMs"), default)]
+    start: u64,
+    #[serde(rename(deserialize = "dDurationMs"), default)]
+    duration: u64,
+    // missing for the events that only set up the caption window
+    segs: Option<Vec<Json3Segment>>,
+}
+
+#[derive(Deserialize)]
+struct Json3Segment {
+    #[serde(default)]
+    utf8: String,
+}
+
+// `&fmt=json3`: `{"events":[{"tStartMs":1230,"dDurationMs":4560,"segs":[{"utf8":"Hello "},{"utf8":"& welcome"}]}]}`.
+// Auto-generated captions split each line into one segment per word.
+pub fn parse_json3(json: &str) -> Result<Vec<Cue>, YouDlError> {
+    let json3: Json3 =
+        serde_json::from_str(json).map_err(|e| YouDlError::InvalidResponse(e.to_string()))?;
+    let mut cues = Vec::new();
+    for event in json3.events {
+        if let Some(segments) = event.segs {
+            let text: String = segments
+                .iter()
+                .map(|segment| segment.utf8.as_str())
+                .collect();
+            push_cue(&mut cues, event.start, event.start + event.duration, &text);
+        }
+    }
+    Ok(cues)
+}
+
+// Blank lines, e.g. the line breaks that auto-generated captions send on their own, are dropped.
+fn push_cue(cues: &mut Vec<Cue>, start: u64, end: u64, text: &str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        cues.push(Cue {
+            start,
+            end,
+            text: text.to_owned(),
+        });
+    }
+}
+
+fn seconds_to_ms(seconds: f64) -> u64 {
+    (seconds * 1000.0).round().max(0.0) as u64
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(ampersand) = rest.find('&') {
+        decoded.push_str(&rest[..ampersand]);
+        rest = &rest[ampersand..];
+        let entity = rest
+            .find(';')
+            .filter(|&semicolon| semicolon <= 10)
+            .map(|semicolon| &rest[1..semicolon]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|decimal| decimal.parse()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_formats_into_the_same_cues() {
+        let from_xml = parse_xml(include_str!("fixtures/timedtext.xml")).unwrap();
+        let from_json3 = parse_json3(include_str!("fixtures/timedtext.json3")).unwrap();
+        assert_eq!(
+            from_xml[..2],
+            [
+                Cue {
+                    start: 0,
+                    end: 2500,
+                    text: "We're no strangers to love".to_owned()
+                },
+                Cue {
+                    start: 2500,
+                    end: 5250,
+                    text: "You know the rules & so do I".to_owned()
+                },
+            ]
+        );
+        assert_eq!(from_xml, from_json3);
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("&lt;i&gt; &#39;a&#x27; &amp;amp; &unknown; & b"),
+            "<i> 'a' &amp; &unknown; & b"
+        );
+    }
+}
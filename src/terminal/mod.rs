@@ -0,0 +1,95 @@
+use colored::Colorize;
+use dialoguer::Select;
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use you_dl::{LogLevel, Logger, ProgressEvent, ProgressObserver, Prompt, YouDlError};
+
+// Messages are written to stderr, keeping stdout for the output of `--dump-json`.
+macro_rules! info {
+    ($($arg:tt)*) => (eprintln!("{:>7} {}", colored::Colorize::blue("Info:"), format_args!($($arg)*)));
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => (eprintln!("{:>7} {}", colored::Colorize::bold(colored::Colorize::red("Error:")), format_args!($($arg)*)));
+}
+
+macro_rules! failed {
+    ($($arg:tt)*) => (eprintln!("{:>7} {}", colored::Colorize::bold(colored::Colorize::red("Failed:")), format_args!($($arg)*)));
+}
+
+pub struct TerminalLogger;
+
+impl Logger for TerminalLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        let label = match level {
+            LogLevel::Info => "Info:".blue(),
+            LogLevel::Success => "Success:".green().bold(),
+            LogLevel::Warn => "Warn:".yellow(),
+        };
+        eprintln!("{:>7} {}", label, message);
+    }
+}
+
+// Only used when stdin is a terminal.
+pub struct TerminalPrompt;
+
+impl Prompt for TerminalPrompt {
+    fn select(&self, message: &str, items: &[String]) -> Result<usize, YouDlError> {
+        eprintln!("{:>7} {}", "Select:".yellow().bold(), message);
+        Select::new()
+            .items(items)
+            .default(0)
+            .interact()
+            .map_err(|e| YouDlError::Application(e.to_string()))
+    }
+}
+
+// Renders the events of a download on the bar of its worker, in a `MultiProgress`.
+// The bar is reused by the next download of the worker, so outcomes are printed above the bars.
+pub struct TerminalProgress(pub ProgressBar);
+
+impl TerminalProgress {
+    fn idle(&self, message: &str) {
+        self.0
+            .set_style(ProgressStyle::default_bar().template("{prefix:.green} {wide_msg}"));
+        self.0.set_prefix("Status:");
+        self.0.set_message(message);
+    }
+}
+
+impl ProgressObserver for TerminalProgress {
+    fn on_event(&self, event: ProgressEvent) {
+        let bar = &self.0;
+        match event {
+            ProgressEvent::Resolving { url } => self.idle(&format!("Resolving {}...", url)),
+            ProgressEvent::Skipped { .. } => self.idle(""),
+            ProgressEvent::FormatChosen { title, itag } => {
+                bar.set_message(&format!("Chosen itag {} for: {}", itag, title));
+            }
+            ProgressEvent::Started { total, downloaded } => {
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{prefix:.green} {bar:40.cyan/blue} {percent}% {wide_msg}")
+                        .progress_chars("##-"),
+                );
+                bar.set_prefix("Status:");
+                bar.set_message("");
+                bar.set_length(total.unwrap_or(u64::MAX));
+                bar.set_position(downloaded);
+            }
+            ProgressEvent::Progress { bytes, speed, eta } => {
+                bar.set_position(bytes);
+                let eta = eta.map_or("unknown".to_owned(), |eta| HumanDuration(eta).to_string());
+                bar.set_message(&format!("{}/s, ETA {}", HumanBytes(speed as u64), eta));
+            }
+            ProgressEvent::PostProcessing { step } => bar.set_message(&format!("{}...", step)),
+            ProgressEvent::Finished { title, .. } => {
+                bar.println(format!("{:>7} {}", "Success:".green().bold(), title));
+                self.idle("");
+            }
+            ProgressEvent::Failed { error } => {
+                bar.println(format!("{:>7} {}", "Failed:".red().bold(), error));
+                self.idle("");
+            }
+        }
+    }
+}
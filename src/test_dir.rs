@@ -0,0 +1,27 @@
+use std::path::{Path, PathBuf};
+
+// A directory of its own for a test, removed with everything in it once dropped, even if the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    // e.g. "/tmp/you-dl-<name>-<pid>"
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("you-dl-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).expect("writable temporary directory");
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,146 @@
+// A minimal HTTP/1.1 server for tests, answering every connection from its own thread.
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+
+pub struct Request {
+    // e.g. "GET"
+    pub method: String,
+    // path and query, e.g. "/watch?v=dQw4w9WgXcQ"
+    pub path: String,
+    // lowercase header names
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // the connection is closed after this many bytes of the body, which still announces its full length
+    pub cut_after: Option<usize>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+            cut_after: None,
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            cut_after: None,
+        }
+    }
+}
+
+// Returns the base url, e.g. "http://127.0.0.1:12345".
+pub fn start<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").expect("free local port");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let handler = Arc::clone(&handler);
+            thread::spawn(move || {
+                if let Some(request) = read_request(&mut BufReader::new(&stream)) {
+                    let response = handler(&request);
+                    let _ = write_response(&mut stream, &response);
+                }
+            });
+        }
+    });
+    base_url
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut request_line = request_line.split_whitespace();
+    let method = request_line.next()?.to_owned();
+    let path = request_line.next()?.to_owned();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+    }
+    let content_length = headers
+        .get("content-length")
+        .map_or(Ok(0), |len| len.parse())
+        .ok()?;
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn write_response(writer: &mut impl Write, response: &Response) -> std::io::Result<()> {
+    write!(writer, "HTTP/1.1 {} Test\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        writer,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    )?;
+    let body_end = response.cut_after.map_or(response.body.len(), |cut_after| {
+        cut_after.min(response.body.len())
+    });
+    writer.write_all(&response.body[..body_end])?;
+    writer.flush()
+}
+
+// Serves `body` honoring "Range: bytes=<start>-[<end>]" headers, like YouTube's media servers.
+pub fn serve_ranges(request: &Request, body: &[u8]) -> Response {
+    let range = request
+        .headers
+        .get("range")
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'));
+    let (start, end) = match range {
+        None => return Response::ok(body),
+        Some((start, end)) => (
+            start.parse::<usize>().unwrap(),
+            end.parse::<usize>()
+                .map_or(body.len() - 1, |end| end.min(body.len() - 1)),
+        ),
+    };
+    if start >= body.len() {
+        return Response::status(416);
+    }
+    let content_range = format!("bytes {}-{}/{}", start, end, body.len());
+    Response {
+        status: 206,
+        headers: vec![("Content-Range".to_owned(), content_range)],
+        body: body[start..=end].to_vec(),
+        cut_after: None,
+    }
+}
@@ -0,0 +1,265 @@
+use crate::models::{Selectable, Selection, StreamKind};
+use crate::YouDlError;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// between two `Progress` events, so that observers aren't flooded with one event per network chunk
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// What happens to a download, in this order. `Failed` can come at any point and ends it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    Resolving {
+        url: String,
+    },
+    // e.g. already in the download archive; nothing else follows
+    Skipped {
+        reason: String,
+    },
+    // formatted like youtube-dl does, e.g. "22" or "137+140"
+    FormatChosen {
+        title: String,
+        itag: String,
+    },
+    // `downloaded` bytes are already there when the download is resumed; `total` is unknown for some streams
+    Started {
+        total: Option<u64>,
+        downloaded: u64,
+    },
+    // `bytes` downloaded so far, resumed ones included; the speed (in bytes per second) only counts this run.
+    // Sent at most every 100ms, and once all the bytes are there.
+    Progress {
+        bytes: u64,
+        speed: f64,
+        eta: Option<Duration>,
+    },
+    PostProcessing {
+        step: String,
+    },
+    Finished {
+        title: String,
+        path: PathBuf,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+// Follows a download, e.g. with a progress bar. Segmented downloads report from several tasks at once.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: ProgressEvent);
+}
+
+// For downloads nobody watches.
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_event(&self, _event: ProgressEvent) {}
+}
+
+// Turns the events into a stream, e.g. for a UI running on another task.
+// Events are dropped once the receiver is gone, or while a bounded channel is full.
+impl ProgressObserver for smol::channel::Sender<ProgressEvent> {
+    fn on_event(&self, event: ProgressEvent) {
+        let _ = self.try_send(event);
+    }
+}
+
+// Asks the user to pick one of `items`, e.g. the file format to download, and returns its index.
+pub trait Prompt: Send + Sync {
+    fn select(&self, message: &str, items: &[String]) -> Result<usize, YouDlError>;
+}
+
+// Asks for the format of a video, then for the audio to merge with it when the format is video-only.
+// Both backends prompt this way, each with its own kind of formats. Prompts answering with an index
+// past the items they were given are refused.
+pub(crate) fn ask_preferred_format<T: Selectable + fmt::Display>(
+    prompt: &dyn Prompt,
+    title: &str,
+    formats: &[T],
+) -> Result<Selection, YouDlError> {
+    let items: Vec<String> = formats.iter().map(ToString::to_string).collect();
+    let chosen_index = prompt.select(&format!("choose the file format for: {}", title), &items)?;
+    let chosen_format = formats
+        .get(chosen_index)
+        .ok_or_else(|| invalid_choice(chosen_index, formats.len()))?;
+
+    let audio_indexes: Vec<usize> = (0..formats.len())
+        .filter(|&index| formats[index].kind() == StreamKind::AudioOnly)
+        .collect();
+    if chosen_format.kind() != StreamKind::VideoOnly || audio_indexes.is_empty() {
+        return Ok(Selection::Single(chosen_index));
+    }
+
+    let audio_items: Vec<String> = audio_indexes
+        .iter()
+        .map(|&index| items[index].to_owned())
+        .collect();
+    let chosen_audio = prompt.select(
+        &format!(
+            "choose the audio to merge with itag {}",
+            chosen_format.itag()
+        ),
+        &audio_items,
+    )?;
+    let audio_index = audio_indexes
+        .get(chosen_audio)
+        .ok_or_else(|| invalid_choice(chosen_audio, audio_indexes.len()))?;
+    Ok(Selection::Pair(chosen_index, *audio_index))
+}
+
+fn invalid_choice(index: usize, len: usize) -> YouDlError {
+    YouDlError::User(format!("invalid choice {} out of {} items", index, len))
+}
+
+// Counts the bytes of the streams of one download, and reports them with their speed and ETA.
+// Reports are throttled, except for the one completing the download.
+pub(crate) struct TransferProgress {
+    observer: Arc<dyn ProgressObserver>,
+    state: Mutex<TransferState>,
+}
+
+struct TransferState {
+    total: Option<u64>,
+    downloaded: u64,
+    // bytes resumed from a previous run, left out of the speed
+    offset: u64,
+    started_at: Instant,
+    // the bytes of the last `Progress` event, and when it was sent
+    reported: Option<(u64, Instant)>,
+}
+
+impl TransferProgress {
+    pub fn new(observer: Arc<dyn ProgressObserver>) -> Self {
+        TransferProgress {
+            observer,
+            state: Mutex::new(TransferState {
+                total: None,
+                downloaded: 0,
+                offset: 0,
+                started_at: Instant::now(),
+                reported: None,
+            }),
+        }
+    }
+
+    pub fn start(&self, total: Option<u64>, downloaded: u64) {
+        let mut state = self.state.lock().expect("unpoisoned progress");
+        *state = TransferState {
+            total,
+            downloaded,
+            offset: downloaded,
+            started_at: Instant::now(),
+            reported: None,
+        };
+        self.observer
+            .on_event(ProgressEvent::Started { total, downloaded });
+    }
+
+    pub fn advance(&self, bytes: u64) {
+        // reported under the lock, so that concurrent segments can't report out of order
+        let mut state = self.state.lock().expect("unpoisoned progress");
+        state.downloaded += bytes;
+        let is_due = match state.reported {
+            Some((_, reported_at)) => reported_at.elapsed() >= PROGRESS_INTERVAL,
+            None => true,
+        };
+        if is_due || state.total == Some(state.downloaded) {
+            self.report(&mut state);
+        }
+    }
+
+    // Reports the bytes that the throttling held back, once the streams are downloaded.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().expect("unpoisoned progress");
+        if state.reported.map(|(bytes, _)| bytes) != Some(state.downloaded) {
+            self.report(&mut state);
+        }
+    }
+
+    fn report(&self, state: &mut TransferState) {
+        let elapsed = state.started_at.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            (state.downloaded - state.offset) as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = state.total.filter(|_| speed > 0.0).map(|total| {
+            Duration::from_secs_f64(total.saturating_sub(state.downloaded) as f64 / speed)
+        });
+        state.reported = Some((state.downloaded, Instant::now()));
+        self.observer.on_event(ProgressEvent::Progress {
+            bytes: state.downloaded,
+            speed,
+            eta,
+        });
+    }
+
+    // Takes back bytes that have to be downloaded again.
+    pub fn discard(&self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        let mut state = self.state.lock().expect("unpoisoned progress");
+        state.downloaded = state.downloaded.saturating_sub(bytes);
+        state.offset = state.offset.min(state.downloaded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_resumed_bytes_too() {
+        let (sender, receiver) = smol::channel::unbounded();
+        let progress = TransferProgress::new(Arc::new(sender));
+        progress.start(Some(100), 40);
+        std::thread::sleep(Duration::from_millis(10));
+        progress.advance(10);
+        progress.advance(50);
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            ProgressEvent::Started {
+                total: Some(100),
+                downloaded: 40
+            }
+        );
+        match receiver.try_recv().unwrap() {
+            ProgressEvent::Progress { bytes, speed, eta } => {
+                assert_eq!(bytes, 50);
+                assert!(speed > 0.0);
+                assert!(eta.is_some());
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+        match receiver.try_recv().unwrap() {
+            ProgressEvent::Progress { bytes, eta, .. } => {
+                assert_eq!(bytes, 100);
+                assert_eq!(eta, Some(Duration::from_secs(0)));
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn throttles_the_progress_events() {
+        let (sender, receiver) = smol::channel::unbounded();
+        let progress = TransferProgress::new(Arc::new(sender));
+        progress.start(None, 0);
+        for _ in 0..1000 {
+            progress.advance(1);
+        }
+        progress.finish();
+
+        let events: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert!(events.len() < 10, "{} events", events.len());
+        assert!(matches!(
+            events.last(),
+            Some(ProgressEvent::Progress { bytes: 1000, .. })
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+// Extracts the JSON object that follows `marker` in a web page, e.g. `var ytInitialData = {...};`.
+// A regex can't tell where such an object ends, so braces are balanced by hand,
+// ignoring those inside string literals.
+pub fn extract_json_object<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    let marker_end = text.find(marker)? + marker.len();
+    let start = marker_end + text[marker_end..].find('{')?;
+
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &byte) in text.as_bytes()[start..].iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..=start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_balanced_json_object() {
+        let html = r#"<script>var ytInitialData = {"a":{"b":"}{\"}"},"c":[1,{"d":2}]};var other = {};</script>"#;
+        assert_eq!(
+            extract_json_object(html, "var ytInitialData = "),
+            Some(r#"{"a":{"b":"}{\"}"},"c":[1,{"d":2}]}"#)
+        );
+        assert_eq!(extract_json_object(html, "var missing = "), None);
+        assert_eq!(
+            extract_json_object(r#"var x = {"unterminated": {}"#, "var x = "),
+            None
+        );
+    }
+}
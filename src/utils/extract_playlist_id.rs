@@ -0,0 +1,30 @@
+use regex::Regex;
+
+pub fn extract_playlist_id(url: &str) -> Option<&str> {
+    Regex::new(r"[?&]list=([\w-]+)")
+        .expect("valid regex expression")
+        .captures(url)?
+        .get(1)
+        .map(|playlist_id| playlist_id.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_playlist_id_from_url() {
+        let urls = [
+            "https://www.youtube.com/playlist?list=PLBCF2DAC6FFB574DE",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLBCF2DAC6FFB574DE&index=2",
+            "https://youtube.com/embed/videoseries?list=PLBCF2DAC6FFB574DE",
+        ];
+        for &url in &urls {
+            assert_eq!(extract_playlist_id(url), Some("PLBCF2DAC6FFB574DE"));
+        }
+        assert_eq!(
+            extract_playlist_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            None
+        );
+    }
+}
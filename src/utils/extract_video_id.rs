@@ -11,6 +11,10 @@ pub fn extract_video_id(url: &str) -> Result<&str, YouDlError> {
             .get(1)
             .ok_or(YouDlError::InvalidURL(url.to_owned()))?
             .as_str();
+    // "embed/videoseries?list=<playlist_id>" embeds a whole playlist
+    if video_id == "videoseries" {
+        return Err(YouDlError::InvalidURL(url.to_owned()));
+    }
     Ok(video_id)
 }
 
@@ -1,18 +1,42 @@
 pub const FILE_SIZE_SUFFIXES: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
 
-pub fn format_file_size(file_size_bytes: i32) -> String {
+pub fn format_file_size(file_size_bytes: u64) -> String {
     for (i, &suffix) in FILE_SIZE_SUFFIXES.iter().enumerate() {
         let i = i as u32;
-        let lower_bound = i32::pow(1024, i);
-        let higher_bound = i32::pow(1024, i + 1);
+        let lower_bound = u64::pow(1024, i);
+        let higher_bound = u64::pow(1024, i + 1);
         if file_size_bytes >= lower_bound && file_size_bytes < higher_bound {
-            let val = f64::from(file_size_bytes) / f64::from(lower_bound);
+            let val = file_size_bytes as f64 / lower_bound as f64;
             return format!("{:.2}{}", val, suffix);
         }
     }
     format!("{}{}", file_size_bytes, FILE_SIZE_SUFFIXES[0])
 }
 
+// Inverse of `format_file_size`, also accepting the short suffixes used on the command line,
+// e.g. "512K", "10M", "1.5GiB" or a plain number of bytes.
+pub fn parse_file_size(file_size: &str) -> Option<u64> {
+    let file_size = file_size.trim();
+    let number_len = file_size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(file_size.len());
+    let (number, suffix) = file_size.split_at(number_len);
+    let number = number.parse::<f64>().ok()?;
+
+    let suffix = suffix.trim();
+    let exponent = FILE_SIZE_SUFFIXES.iter().position(|&known_suffix| {
+        suffix.eq_ignore_ascii_case(known_suffix)
+            || suffix.eq_ignore_ascii_case(&known_suffix[..1])
+            || suffix.eq_ignore_ascii_case(known_suffix.trim_end_matches("iB"))
+    });
+    let exponent = match (suffix, exponent) {
+        ("", _) => 0,
+        (_, Some(exponent)) => exponent as i32,
+        (_, None) => return None,
+    };
+    Some((number * 1024_f64.powi(exponent)) as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,4 +45,15 @@ mod tests {
     fn creates_proper_string_representation() {
         assert_eq!(&format_file_size(10485760), "10.00MiB")
     }
+
+    #[test]
+    fn parses_string_representation() {
+        assert_eq!(parse_file_size("10.00MiB"), Some(10485760));
+        assert_eq!(parse_file_size("10M"), Some(10485760));
+        assert_eq!(parse_file_size("512k"), Some(524288));
+        assert_eq!(parse_file_size("1.5G"), Some(1610612736));
+        assert_eq!(parse_file_size("1000"), Some(1000));
+        assert_eq!(parse_file_size("10X"), None);
+        assert_eq!(parse_file_size("M"), None);
+    }
 }
@@ -29,3 +29,34 @@ const PAIRS: [(i32, &str); 24] = [
 pub fn get_file_extension(itag: i32) -> Option<&'static str> {
     PAIRS.iter().find(|&item| item.0 == itag).map(|item| item.1)
 }
+
+// DASH itags are not listed in `PAIRS`, but their mime type is enough to pick an extension.
+pub fn get_file_extension_from_mime_type(mime_type: &str) -> Option<&'static str> {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    match essence {
+        "video/mp4" => Some("mp4"),
+        "video/webm" | "audio/webm" => Some("webm"),
+        "video/3gpp" => Some("3gp"),
+        "audio/mp4" => Some("m4a"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gets_file_extension_from_mime_type() {
+        let pairs = [
+            ("video/mp4; codecs=\"avc1.640028\"", Some("mp4")),
+            ("video/webm; codecs=\"vp9\"", Some("webm")),
+            ("audio/mp4; codecs=\"mp4a.40.2\"", Some("m4a")),
+            ("audio/webm; codecs=\"opus\"", Some("webm")),
+            ("text/plain", None),
+        ];
+        for &(mime_type, file_extension) in &pairs {
+            assert_eq!(get_file_extension_from_mime_type(mime_type), file_extension);
+        }
+    }
+}
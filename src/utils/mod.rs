@@ -1,7 +1,15 @@
+mod extract_json_object;
+mod extract_playlist_id;
 mod extract_video_id;
 mod format_file_size;
 mod get_file_extension;
+mod parse_url;
 
+pub const YOUTUBE_URL: &str = "https://www.youtube.com";
+
+pub use extract_json_object::extract_json_object;
+pub use extract_playlist_id::extract_playlist_id;
 pub use extract_video_id::extract_video_id;
-pub use format_file_size::{format_file_size, FILE_SIZE_SUFFIXES};
-pub use get_file_extension::get_file_extension;
+pub use format_file_size::{format_file_size, parse_file_size, FILE_SIZE_SUFFIXES};
+pub use get_file_extension::{get_file_extension, get_file_extension_from_mime_type};
+pub use parse_url::{parse_url, Channel, YoutubeUrl};
@@ -0,0 +1,112 @@
+use super::{extract_playlist_id, extract_video_id};
+use crate::YouDlError;
+use regex::Regex;
+
+#[derive(Debug, PartialEq)]
+pub enum YoutubeUrl<'a> {
+    Video(&'a str),
+    Playlist(&'a str),
+    // e.g. "watch?v=<video_id>&list=<playlist_id>"
+    VideoInPlaylist {
+        video_id: &'a str,
+        playlist_id: &'a str,
+    },
+    Channel(Channel<'a>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Channel<'a> {
+    // "/channel/UC..."
+    Id(&'a str),
+    // "/c/<name>"
+    CustomName(&'a str),
+    // "/@<handle>"
+    Handle(&'a str),
+    // "/user/<name>"
+    User(&'a str),
+}
+
+pub fn parse_url(url: &str) -> Result<YoutubeUrl<'_>, YouDlError> {
+    // videos take precedence: "youtube.com/user/<name>#p/u/1/<video_id>" is a video, not a channel
+    match (extract_video_id(url), extract_playlist_id(url)) {
+        (Ok(video_id), Some(playlist_id)) => {
+            return Ok(YoutubeUrl::VideoInPlaylist {
+                video_id,
+                playlist_id,
+            })
+        }
+        (Ok(video_id), None) => return Ok(YoutubeUrl::Video(video_id)),
+        (Err(_), Some(playlist_id)) => return Ok(YoutubeUrl::Playlist(playlist_id)),
+        (Err(_), None) => {}
+    }
+
+    let captures =
+        Regex::new(r"youtube\.com/(?:channel/(UC[\w-]+)|c/([^/?#&]+)|@([^/?#&]+)|user/([^/?#&]+))")
+            .expect("valid regex expression")
+            .captures(url)
+            .ok_or(YouDlError::InvalidURL(url.to_owned()))?;
+    let group = |index| captures.get(index).map(|group| group.as_str());
+    let channel = match (group(1), group(2), group(3), group(4)) {
+        (Some(channel_id), ..) => Channel::Id(channel_id),
+        (_, Some(custom_name), ..) => Channel::CustomName(custom_name),
+        (_, _, Some(handle), _) => Channel::Handle(handle),
+        (.., Some(user)) => Channel::User(user),
+        _ => unreachable!("one group always matches"),
+    };
+    Ok(YoutubeUrl::Channel(channel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_youtube_urls() {
+        let pairs = [
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                YoutubeUrl::Video("dQw4w9WgXcQ"),
+            ),
+            (
+                "http://www.youtube.com/user/Scobleizer#p/u/1/dQw4w9WgXcQ",
+                YoutubeUrl::Video("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://www.youtube.com/playlist?list=PLBCF2DAC6FFB574DE",
+                YoutubeUrl::Playlist("PLBCF2DAC6FFB574DE"),
+            ),
+            (
+                "https://www.youtube.com/embed/videoseries?list=PLBCF2DAC6FFB574DE",
+                YoutubeUrl::Playlist("PLBCF2DAC6FFB574DE"),
+            ),
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLBCF2DAC6FFB574DE",
+                YoutubeUrl::VideoInPlaylist {
+                    video_id: "dQw4w9WgXcQ",
+                    playlist_id: "PLBCF2DAC6FFB574DE",
+                },
+            ),
+            (
+                "https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw/videos",
+                YoutubeUrl::Channel(Channel::Id("UCuAXFkgsw1L7xaCfnd5JJOw")),
+            ),
+            (
+                "https://www.youtube.com/c/RickAstleyYT",
+                YoutubeUrl::Channel(Channel::CustomName("RickAstleyYT")),
+            ),
+            (
+                "https://www.youtube.com/@RickAstleyYT/videos",
+                YoutubeUrl::Channel(Channel::Handle("RickAstleyYT")),
+            ),
+            (
+                "https://youtube.com/user/RickAstleyVEVO",
+                YoutubeUrl::Channel(Channel::User("RickAstleyVEVO")),
+            ),
+        ];
+
+        for (url, expected) in &pairs {
+            assert_eq!(&parse_url(url).unwrap(), expected);
+        }
+        assert!(parse_url("https://www.youtube.com/feed/trending").is_err());
+    }
+}
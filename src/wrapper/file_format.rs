@@ -1,3 +1,4 @@
+use crate::models::{Selectable, StreamKind};
 use crate::utils::FILE_SIZE_SUFFIXES;
 use crate::YouDlError;
 use std::fmt;
@@ -7,6 +8,8 @@ pub struct FileFormat {
     pub file_extension: String,
     pub video_resolution: String,
     pub file_size: String,
+    pub bitrate: Option<i32>,
+    pub kind: StreamKind,
 }
 
 impl FileFormat {
@@ -18,6 +21,13 @@ impl FileFormat {
             .collect()
     }
 
+    fn resolution(&self) -> Option<(i32, i32)> {
+        let mut dimensions = self.video_resolution.split('x');
+        let width = dimensions.next()?.parse().ok()?;
+        let height = dimensions.next()?.parse().ok()?;
+        Some((width, height))
+    }
+
     fn parse_line(line: &str) -> Result<FileFormat, YouDlError> {
         let mut words_iter = line.split_whitespace();
         let (itag, file_extension, video_resolution) =
@@ -32,15 +42,58 @@ impl FileFormat {
             }
         })?;
 
+        // e.g. "130k" in "DASH audio  130k , m4a_dash container"
+        let bitrate = line
+            .split_whitespace()
+            .filter_map(|word| word.strip_suffix('k'))
+            .find_map(|kbps| kbps.parse::<i32>().ok())
+            .map(|kbps| kbps * 1000);
+        let kind = if line.contains("audio only") {
+            StreamKind::AudioOnly
+        } else if line.contains("video only") {
+            StreamKind::VideoOnly
+        } else {
+            StreamKind::Muxed
+        };
+
         Ok(FileFormat {
             itag: extract_option_str(itag)?,
             file_extension: extract_option_str(file_extension)?,
             video_resolution: extract_option_str(video_resolution)?,
             file_size,
+            bitrate,
+            kind,
         })
     }
 }
 
+// `video_resolution` is either "<width>x<height>" or "audio" (as in "audio only")
+impl Selectable for FileFormat {
+    fn itag(&self) -> String {
+        self.itag.clone()
+    }
+
+    fn file_extension(&self) -> &str {
+        &self.file_extension
+    }
+
+    fn width(&self) -> Option<i32> {
+        self.resolution().map(|(width, _)| width)
+    }
+
+    fn height(&self) -> Option<i32> {
+        self.resolution().map(|(_, height)| height)
+    }
+
+    fn bitrate(&self) -> Option<i32> {
+        self.bitrate
+    }
+
+    fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
 impl fmt::Display for FileFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -66,6 +119,34 @@ fn is_valid_file_size(file_size: &str) -> bool {
         .any(|suffix| file_size.ends_with(suffix))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_youtube_dl_file_formats() {
+        let stdout = "\
+format code  extension  resolution note
+140          m4a        audio only DASH audio  130k , m4a_dash container, mp4a.40.2@128k (44100Hz)
+136          mp4        1280x720   DASH video 2310k , mp4_dash container, avc1.4d4016, 30fps, video only
+22           mp4        1280x720   720p 1472k , avc1.64001F, 30fps, mp4a.40.2@192k (44100Hz) (best)";
+        let file_formats = FileFormat::from_youtube_dl_stdout(stdout).unwrap();
+
+        let summary: Vec<_> = file_formats
+            .iter()
+            .map(|f| (f.itag.as_str(), f.height(), f.bitrate, f.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("140", None, Some(130_000), StreamKind::AudioOnly),
+                ("136", Some(720), Some(2_310_000), StreamKind::VideoOnly),
+                ("22", Some(720), Some(1_472_000), StreamKind::Muxed),
+            ]
+        );
+    }
+}
+
 //
 // EXAMPLE FILE FORMATS FROM YOUTUBE-DL
 //
@@ -0,0 +1,177 @@
+use crate::models::{FormatInfo, StreamKind, VideoInfo, SCHEMA_VERSION};
+use crate::SubtitleTrack;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// The subset of `youtube-dl -J` needed to fill in a `VideoInfo`.
+#[derive(Deserialize, Debug)]
+pub struct InfoJson {
+    id: String,
+    title: String,
+    uploader: Option<String>,
+    upload_date: Option<String>,
+    description: Option<String>,
+    thumbnail: Option<String>,
+    #[serde(default)]
+    formats: Vec<InfoJsonFormat>,
+    // language code -> the same subtitles in several formats
+    #[serde(default)]
+    subtitles: BTreeMap<String, Vec<InfoJsonSubtitle>>,
+    #[serde(default)]
+    automatic_captions: BTreeMap<String, Vec<InfoJsonSubtitle>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InfoJsonSubtitle {
+    ext: String,
+    url: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InfoJsonFormat {
+    format_id: String,
+    ext: String,
+    url: String,
+    width: Option<i32>,
+    height: Option<i32>,
+    // kbit/s
+    tbr: Option<f64>,
+    // "none" when the stream lacks video or audio
+    vcodec: Option<String>,
+    acodec: Option<String>,
+    filesize: Option<f64>,
+    filesize_approx: Option<f64>,
+}
+
+impl From<InfoJson> for VideoInfo {
+    fn from(info_json: InfoJson) -> Self {
+        VideoInfo {
+            schema_version: SCHEMA_VERSION,
+            id: info_json.id,
+            title: info_json.title,
+            uploader: info_json.uploader,
+            upload_date: info_json.upload_date,
+            description: info_json.description,
+            thumbnail: info_json.thumbnail,
+            formats: info_json
+                .formats
+                .into_iter()
+                .map(|format| {
+                    let codec = |codec: Option<String>| codec.filter(|codec| codec != "none");
+                    let (vcodec, acodec) = (codec(format.vcodec), codec(format.acodec));
+                    let kind = match (&vcodec, &acodec) {
+                        (None, Some(_)) => StreamKind::AudioOnly,
+                        (Some(_), None) => StreamKind::VideoOnly,
+                        _ => StreamKind::Muxed,
+                    };
+                    let codecs: Vec<_> = vcodec.into_iter().chain(acodec).collect();
+                    FormatInfo {
+                        itag: format.format_id,
+                        ext: format.ext,
+                        kind,
+                        mime_type: None,
+                        codecs: Some(codecs.join(", ")).filter(|codecs| !codecs.is_empty()),
+                        bitrate: format.tbr.map(|kbps| (kbps * 1000.0).round() as u64),
+                        width: format.width,
+                        height: format.height,
+                        filesize: format.filesize.map(|size| size as u64),
+                        filesize_approx: format.filesize_approx.map(|size| size as u64),
+                        url: format.url,
+                    }
+                })
+                .collect(),
+            subtitles: subtitle_tracks(info_json.subtitles, false)
+                .chain(subtitle_tracks(info_json.automatic_captions, true))
+                .collect(),
+        }
+    }
+}
+
+// Only the JSON3 variant is kept, which `you-dl` can convert.
+fn subtitle_tracks(
+    subtitles: BTreeMap<String, Vec<InfoJsonSubtitle>>,
+    auto_generated: bool,
+) -> impl Iterator<Item = SubtitleTrack> {
+    subtitles
+        .into_iter()
+        .filter_map(move |(language_code, formats)| {
+            let json3 = formats.into_iter().find(|format| format.ext == "json3")?;
+            Some(SubtitleTrack {
+                name: json3.name.unwrap_or_else(|| language_code.clone()),
+                language_code,
+                auto_generated,
+                url: json3.url,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_into_video_info() {
+        let json = r#"{
+            "id": "dQw4w9WgXcQ", "title": "Rick Astley - Never Gonna Give You Up",
+            "uploader": "RickAstleyVEVO", "upload_date": "20091025", "duration": 212,
+            "formats": [
+                {"format_id": "140", "ext": "m4a", "url": "https://a", "tbr": 129.482,
+                 "vcodec": "none", "acodec": "mp4a.40.2", "filesize": 3433514, "height": null},
+                {"format_id": "137", "ext": "mp4", "url": "https://v", "width": 1920, "height": 1080,
+                 "tbr": 2500.5, "vcodec": "avc1.640028", "acodec": "none", "filesize_approx": 66000000.5},
+                {"format_id": "22", "ext": "mp4", "url": "https://m", "width": 1280, "height": 720,
+                 "vcodec": "avc1.64001F", "acodec": "mp4a.40.2"}
+            ],
+            "subtitles": {"en": [{"ext": "vtt", "url": "https://s/vtt"}, {"ext": "json3", "url": "https://s/json3", "name": "English"}]},
+            "automatic_captions": {"de": [{"ext": "json3", "url": "https://c/json3"}]}
+        }"#;
+        let video_info = VideoInfo::from(serde_json::from_str::<InfoJson>(json).unwrap());
+
+        assert_eq!(video_info.id, "dQw4w9WgXcQ");
+        assert_eq!(video_info.upload_date.as_deref(), Some("20091025"));
+        let kinds: Vec<_> = video_info
+            .formats
+            .iter()
+            .map(|format| format.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                StreamKind::AudioOnly,
+                StreamKind::VideoOnly,
+                StreamKind::Muxed
+            ]
+        );
+        assert_eq!(video_info.formats[0].bitrate, Some(129_482));
+        assert_eq!(video_info.formats[0].filesize, Some(3_433_514));
+        assert_eq!(video_info.formats[1].filesize_approx, Some(66_000_000));
+        assert_eq!(
+            video_info.formats[2].codecs.as_deref(),
+            Some("avc1.64001F, mp4a.40.2")
+        );
+
+        assert_eq!(
+            video_info.subtitles,
+            vec![
+                SubtitleTrack {
+                    language_code: "en".to_owned(),
+                    name: "English".to_owned(),
+                    auto_generated: false,
+                    url: "https://s/json3".to_owned()
+                },
+                SubtitleTrack {
+                    language_code: "de".to_owned(),
+                    name: "de".to_owned(),
+                    auto_generated: true,
+                    url: "https://c/json3".to_owned()
+                },
+            ]
+        );
+
+        let json = serde_json::to_value(&video_info).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["formats"][1]["kind"], "video_only");
+        assert_eq!(json["formats"][1]["mime_type"], serde_json::Value::Null);
+    }
+}
@@ -1,21 +1,154 @@
-use crate::YouDlError;
-use dialoguer::Select;
+use crate::models::{Selection, VideoInfo};
+use crate::{ui, utils};
+use crate::{
+    DownloadArchive, DownloadSettings, FilenamePolicy, FormatSelector, OutputTemplate,
+    PostProcessing, ProgressEvent, ProgressObserver, Prompt, RateLimiter, YouDlError,
+};
 use file_format::FileFormat;
+use info_json::InfoJson;
 use smol::process;
 
 mod file_format;
+mod info_json;
 
-pub async fn process_request(url: &str, output_dir: &str) -> Result<(), YouDlError> {
-    let title = get_title(&url).await?;
-    let available_file_formats = get_available_file_formats(&url).await?;
-    let chosen_itag = ask_preferred_itag(&title, &available_file_formats);
-    download(&url, &title, &chosen_itag, &output_dir).await?;
+// A video looked up through youtube-dl, whose format is still to be chosen.
+pub struct Video {
+    url: String,
+    pub title: String,
+    file_formats: Vec<FileFormat>,
+}
+
+// Mirrors the settings of a `Downloader`, which youtube-dl gets as arguments.
+#[derive(Clone, Copy)]
+pub struct Options<'a> {
+    pub output_dir: &'a str,
+    pub output_template: &'a OutputTemplate,
+    pub format: Option<&'a FormatSelector>,
+    pub post_processing: &'a PostProcessing,
+    pub settings: &'a DownloadSettings,
+    pub archive: Option<&'a DownloadArchive>,
+}
+
+pub async fn process_request(
+    url: &str,
+    options: Options<'_>,
+    prompt: Option<&dyn Prompt>,
+    observer: &dyn ProgressObserver,
+) -> Result<(), YouDlError> {
+    let video = match resolve(url, options.archive, observer).await? {
+        Some(video) => video,
+        None => return Ok(()),
+    };
+    let itag = choose_format(&video, options, prompt)?;
+    download(&video, &itag, options, observer).await
+}
+
+// Looks up the title and formats of a video, unless it is in the download archive.
+pub async fn resolve(
+    url: &str,
+    archive: Option<&DownloadArchive>,
+    observer: &dyn ProgressObserver,
+) -> Result<Option<Video>, YouDlError> {
+    // only YouTube videos can be recorded: youtube-dl supports many more websites
+    if let Some((archive, video_id)) = archive.zip(utils::extract_video_id(url).ok()) {
+        if archive.contains(video_id).await? {
+            info!("skipping {}: already in the download archive", video_id);
+            observer.on_event(ProgressEvent::Skipped {
+                reason: "already in the download archive".to_owned(),
+            });
+            return Ok(None);
+        }
+    }
+
+    observer.on_event(ProgressEvent::Resolving {
+        url: url.to_owned(),
+    });
+    Ok(Some(Video {
+        url: url.to_owned(),
+        title: get_title(url).await?,
+        file_formats: get_available_file_formats(url).await?,
+    }))
+}
+
+// Returns the itag to pass to youtube-dl.
+pub fn choose_format(
+    video: &Video,
+    options: Options<'_>,
+    prompt: Option<&dyn Prompt>,
+) -> Result<String, YouDlError> {
+    let best_audio = FormatSelector::best_audio();
+    let format = options.format.or(options
+        .post_processing
+        .audio_extraction
+        .map(|_| &best_audio));
+    choose_itag(&video.title, &video.file_formats, format, prompt)
+}
+
+pub async fn download(
+    video: &Video,
+    itag: &str,
+    options: Options<'_>,
+    observer: &dyn ProgressObserver,
+) -> Result<(), YouDlError> {
+    // another job of this run may have the same video
+    let claim = match options
+        .archive
+        .zip(utils::extract_video_id(&video.url).ok())
+    {
+        Some((archive, video_id)) => match archive.claim(video_id).await? {
+            Some(claim) => Some(claim),
+            None => {
+                info!("skipping {}: already in the download archive", video_id);
+                observer.on_event(ProgressEvent::Skipped {
+                    reason: "already in the download archive".to_owned(),
+                });
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    // youtube-dl reports nothing else until it's done
+    observer.on_event(ProgressEvent::FormatChosen {
+        title: video.title.to_owned(),
+        itag: itag.to_owned(),
+    });
+    run_youtube_dl(&video.url, &video.title, itag, options).await?;
+
+    if let Some(claim) = claim {
+        claim.record(itag).await?;
+    }
     Ok(())
 }
 
+// Same schema as the native `get_video_info`, filled in from `youtube-dl -J`.
+pub async fn get_video_info(url: &str) -> Result<VideoInfo, YouDlError> {
+    let process_output = process::Command::new("youtube-dl")
+        .args(["-J", url])
+        .output()
+        .await
+        .map_err(|e| YouDlError::YoutubeDl(e.to_string()))
+        .and_then(handle_bad_exit_status)?;
+
+    serde_json::from_slice::<InfoJson>(&process_output.stdout)
+        .map(VideoInfo::from)
+        .map_err(|e| YouDlError::InvalidResponse(e.to_string()))
+}
+
+// youtube-dl's own table of the available subtitles, as printed by `--list-subs`.
+pub async fn list_subtitles(url: &str) -> Result<String, YouDlError> {
+    let process_output = process::Command::new("youtube-dl")
+        .args(["--list-subs", url])
+        .output()
+        .await
+        .map_err(|e| YouDlError::YoutubeDl(e.to_string()))
+        .and_then(handle_bad_exit_status)?;
+
+    String::from_utf8(process_output.stdout).map_err(|e| YouDlError::Application(e.to_string()))
+}
+
 async fn get_title(url: &str) -> Result<String, YouDlError> {
     let process_output = process::Command::new("youtube-dl")
-        .args(&["--get-title", &url])
+        .args(["--get-title", url])
         .output()
         .await
         .map_err(|e| YouDlError::YoutubeDl(e.to_string()))
@@ -28,7 +161,7 @@ async fn get_title(url: &str) -> Result<String, YouDlError> {
 
 async fn get_available_file_formats(url: &str) -> Result<Vec<FileFormat>, YouDlError> {
     let process_output = process::Command::new("youtube-dl")
-        .args(&["-F", &url])
+        .args(["-F", url])
         .output()
         .await
         .map_err(|e| YouDlError::YoutubeDl(e.to_string()))
@@ -39,27 +172,116 @@ async fn get_available_file_formats(url: &str) -> Result<Vec<FileFormat>, YouDlE
         .and_then(|s| FileFormat::from_youtube_dl_stdout(&s))
 }
 
-fn ask_preferred_itag(title: &str, available_file_formats: &[FileFormat]) -> String {
-    select!("choose the file format for: {}", title);
-    let chosen_index = Select::new()
-        .items(available_file_formats)
-        .default(0)
-        .interact()
-        .unwrap();
-
-    let itag = available_file_formats
-        .get(chosen_index)
-        .map(|file_format| file_format.itag.clone())
-        .expect("chosen available itag");
+// Same rules as the native `Downloader::choose_format`: the prompt is only used without a selector.
+// A pair of video-only and audio-only formats is passed as "<video>+<audio>", and youtube-dl merges them.
+fn choose_itag(
+    title: &str,
+    available_file_formats: &[FileFormat],
+    format: Option<&FormatSelector>,
+    prompt: Option<&dyn Prompt>,
+) -> Result<String, YouDlError> {
+    let selection = match (format, prompt) {
+        (None, Some(prompt)) => ui::ask_preferred_format(prompt, title, available_file_formats)?,
+        _ => {
+            let default_selector = FormatSelector::default();
+            let selector = format.unwrap_or(&default_selector);
+            selector.select(available_file_formats).ok_or_else(|| {
+                YouDlError::Undownloadable(
+                    title.to_owned(),
+                    format!("no file format matches \"{}\"", selector),
+                )
+            })?
+        }
+    };
+
+    let itag_at = |index: usize| &available_file_formats[index].itag;
+    let itag = match selection {
+        Selection::Single(index) => itag_at(index).to_owned(),
+        Selection::Pair(video, audio) => format!("{}+{}", itag_at(video), itag_at(audio)),
+    };
     info!("chosen itag {} for: {}", itag, title);
-    itag
+    Ok(itag)
 }
 
-async fn download(url: &str, title: &str, itag: &str, output_dir: &str) -> Result<(), YouDlError> {
+async fn run_youtube_dl(
+    url: &str,
+    title: &str,
+    itag: &str,
+    options: Options<'_>,
+) -> Result<(), YouDlError> {
+    let Options {
+        output_dir,
+        output_template,
+        post_processing,
+        settings,
+        ..
+    } = options;
     info!("start downloading: {} ...", title);
-    let file_path = format!("{}/%(title)s.%(ext)s", output_dir);
-    process::Command::new("youtube-dl")
-        .args(&["-f", itag, "-o", &file_path, &url])
+    let file_path = format!("{}/{}", output_dir, output_template.to_youtube_dl());
+    let mut command = process::Command::new("youtube-dl");
+    command.args(["-f", itag, "-o", &file_path, url]);
+    // youtube-dl sanitizes the fields on its own, its closest option is used for ASCII-only names
+    if output_template.sanitizer().policy == FilenamePolicy::Ascii {
+        command.arg("--restrict-filenames");
+    }
+    command.args([
+        "--retries",
+        &settings.retries.to_string(),
+        "--fragment-retries",
+        &settings.fragment_retries.to_string(),
+    ]);
+    // each youtube-dl process is limited on its own (the settings have its share of the rate), by the rate in effect when it starts
+    let current_rate = settings
+        .rate_limiter
+        .as_ref()
+        .and_then(RateLimiter::current_rate);
+    if let Some(rate) = current_rate {
+        command.args(["-r", &rate.to_string()]);
+    }
+    if let Some(audio_extraction) = post_processing.audio_extraction {
+        let audio_format = audio_extraction
+            .format
+            .map_or("best".to_owned(), |format| format.to_string());
+        command.args([
+            "--extract-audio",
+            "--audio-format",
+            &audio_format,
+            "--audio-quality",
+            &audio_extraction.quality.to_string(),
+        ]);
+    }
+    if post_processing.embed_metadata {
+        command.arg("--add-metadata");
+    }
+    if post_processing.embed_thumbnail {
+        command.arg("--embed-thumbnail");
+    }
+    if let Some(exec) = &post_processing.exec {
+        command.args(["--exec", exec]);
+    }
+    // youtube-dl can only re-encode videos, and always works in the output directory
+    if post_processing.remux.is_some() {
+        warn!("--remux-video is ignored by youtube-dl");
+    }
+    if post_processing.temp_dir.is_some() {
+        warn!("--temp-dir is ignored by youtube-dl");
+    }
+    if let Some(subtitles) = &post_processing.subtitles {
+        // youtube-dl only embeds written subtitles, and removes them afterwards
+        command.arg("--write-sub");
+        if subtitles.auto_generated {
+            command.arg("--write-auto-sub");
+        }
+        match &subtitles.languages {
+            Some(languages) => command.args(["--sub-lang", &languages.join(",")]),
+            None => command.arg("--all-subs"),
+        };
+        command.args(["--convert-subs", subtitles.format.file_extension()]);
+        if subtitles.embed {
+            command.arg("--embed-subs");
+        }
+    }
+    command
         .output()
         .await
         .map_err(|e| YouDlError::YoutubeDl(e.to_string()))